@@ -7,26 +7,99 @@ fn main() {
 
 #[cfg(feature = "ffi")]
 fn build_ffi_wrapper() {
-    use cbindgen::Config;
+    use cbindgen::{Config, Language};
     use std::env;
-    use std::path::PathBuf;
+    use std::fs;
+    use std::path::{Path, PathBuf};
 
-    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    /// If set, the generated headers and the `pkg-config` file are
+    /// (additionally) written to this directory.
+    const INCLUDE_DIR_VAR: &str = "ALLOC_MADVISE_INCLUDE_DIR";
+
+    println!("cargo:rerun-if-env-changed={INCLUDE_DIR_VAR}");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=build.rs");
 
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let package_name = env::var("CARGO_PKG_NAME").unwrap();
-    let output_file = target_dir()
-        .join(format!("{}.hpp", package_name))
-        .display()
-        .to_string();
+    let lib_name = package_name.replace('-', "_");
+
+    let mut output_dirs = vec![target_dir()];
+    if let Some(dir) = env::var_os(INCLUDE_DIR_VAR).filter(|dir| !dir.is_empty()) {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir).unwrap_or_else(|e| {
+            panic!("Unable to create {INCLUDE_DIR_VAR} {}: {e}", dir.display())
+        });
+        output_dirs.push(dir);
+    }
 
-    let config = Config {
+    let prefix = lib_name.to_uppercase();
+
+    let cpp_config = Config {
+        language: Language::Cxx,
         namespace: Some(String::from("ffi")),
-        ..Default::default()
+        ..base_config(&prefix)
+    };
+
+    let mut c_config = Config {
+        language: Language::C,
+        include_guard: Some(format!("{prefix}_H")),
+        cpp_compat: true,
+        ..base_config(&prefix)
     };
+    c_config.export.prefix = Some(format!("{prefix}_"));
+    c_config.enumeration.prefix_with_name = true;
+
+    let cpp_bindings = cbindgen::generate_with_config(&crate_dir, cpp_config).unwrap();
+    let c_bindings = cbindgen::generate_with_config(&crate_dir, c_config).unwrap();
+
+    for dir in &output_dirs {
+        cpp_bindings.write_to_file(dir.join(format!("{package_name}.hpp")));
+        c_bindings.write_to_file(dir.join(format!("{lib_name}.h")));
+        write_pkg_config(dir, &package_name, &lib_name);
+    }
 
-    cbindgen::generate_with_config(&crate_dir, config)
-        .unwrap()
-        .write_to_file(&output_file);
+    /// Builds the configuration shared by the C and C++ headers.
+    ///
+    /// Deprecated functions are annotated with a `<PREFIX>_DEPRECATED` macro, and items
+    /// behind the `legacy-symbols` and `stats` features are guarded by `<PREFIX>_LEGACY_SYMBOLS`
+    /// and `<PREFIX>_STATS` defines that are set if the library was built with these features.
+    fn base_config(prefix: &str) -> Config {
+        let mut after_includes = format!(
+            "\n\
+             #if defined(__GNUC__) || defined(__clang__)\n\
+             #define {prefix}_DEPRECATED(note) __attribute__((deprecated(note)))\n\
+             #elif defined(_MSC_VER)\n\
+             #define {prefix}_DEPRECATED(note) __declspec(deprecated(note))\n\
+             #else\n\
+             #define {prefix}_DEPRECATED(note)\n\
+             #endif\n"
+        );
+        if env::var_os("CARGO_FEATURE_LEGACY_SYMBOLS").is_some() {
+            after_includes.push_str(&format!("\n#define {prefix}_LEGACY_SYMBOLS\n"));
+        }
+        if env::var_os("CARGO_FEATURE_STATS").is_some() {
+            after_includes.push_str(&format!("\n#define {prefix}_STATS\n"));
+        }
+
+        let mut config = Config {
+            after_includes: Some(after_includes),
+            usize_is_size_t: true,
+            ..Default::default()
+        };
+        // Enums passed as `uint32_t` to be validated are not referenced by any signature.
+        config.export.include = vec![String::from("MapMode"), String::from("Advice")];
+        config.function.deprecated = Some(format!("{prefix}_DEPRECATED(\"\")"));
+        config.function.deprecated_with_note = Some(format!("{prefix}_DEPRECATED({{}})"));
+        config.defines.insert(
+            String::from("feature = legacy-symbols"),
+            format!("{prefix}_LEGACY_SYMBOLS"),
+        );
+        config
+            .defines
+            .insert(String::from("feature = stats"), format!("{prefix}_STATS"));
+        config
+    }
 
     /// Find the location of the `target/` directory. Note that this may be
     /// overridden by `cmake`, so we also need to check the `CARGO_TARGET_DIR`
@@ -38,4 +111,42 @@ fn build_ffi_wrapper() {
             PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("target")
         }
     }
+
+    /// Find the directory the final library artifacts are placed in, e.g. `target/release`.
+    ///
+    /// Cargo does not expose this to build scripts, but `OUT_DIR` is always
+    /// located at `<profile dir>/build/<package>-<hash>/out`.
+    fn library_dir() -> PathBuf {
+        let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+        out_dir
+            .ancestors()
+            .nth(3)
+            .map(Path::to_path_buf)
+            .unwrap_or(out_dir)
+    }
+
+    /// Writes a `pkg-config` file for linking against the `cdylib` or `staticlib`.
+    fn write_pkg_config(include_dir: &Path, package_name: &str, lib_name: &str) {
+        let contents = format!(
+            "libdir={libdir}\n\
+             includedir={includedir}\n\
+             \n\
+             Name: {package_name}\n\
+             Description: {description}\n\
+             Version: {version}\n\
+             URL: {url}\n\
+             Libs: -L${{libdir}} -l{lib_name}\n\
+             Libs.private: -lpthread -ldl -lm\n\
+             Cflags: -I${{includedir}}\n",
+            libdir = library_dir().display(),
+            includedir = include_dir.display(),
+            description = env::var("CARGO_PKG_DESCRIPTION").unwrap(),
+            version = env::var("CARGO_PKG_VERSION").unwrap(),
+            url = env::var("CARGO_PKG_HOMEPAGE").unwrap(),
+        );
+
+        let path = include_dir.join(format!("{package_name}.pc"));
+        fs::write(&path, contents)
+            .unwrap_or_else(|e| panic!("Unable to write {}: {e}", path.display()));
+    }
 }
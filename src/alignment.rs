@@ -1,16 +1,17 @@
 //! This module provides functionality for determining optimal memory alignment
-//! for allocations based on the number of bytes requested. It includes constants
-//! for common alignment sizes and a struct to represent alignment hints.
+//! for allocations based on the number of bytes requested. It includes a struct
+//! to represent alignment hints.
 //!
 //! The main functionality is provided by the [`AlignmentHint::new`](AlignmentHint::get)
 //! function, which returns an [`AlignmentHint`] indicating the suggested alignment and whether
-//! huge pages should be used.
+//! huge pages should be used. The thresholds are derived from the host's page configuration
+//! as reported by [`SystemInfo`].
 //!
 //! # Structs
 //! - [`AlignmentHint`]: Contains alignment information and a flag for huge page usage.
 
-const TWO_MEGABYTES: usize = 2 * 1024 * 1024;
-const SIXTY_FOUR_BYTES: usize = 64;
+use crate::system_info::SystemInfo;
+
 const ZERO_BYTES: usize = 0;
 
 /// An alignment hint to control subsequent allocations or de-allocations.
@@ -26,36 +27,53 @@ pub struct AlignmentHint {
 }
 
 impl AlignmentHint {
+    /// Gets the optimal alignment for the number of bytes on this host.
+    ///
+    /// See [`AlignmentHint::with_system_info`].
+    ///
+    /// ## Arguments
+    /// * `num_bytes` - The number of bytes to allocate.
+    ///
+    /// ## Returns
+    /// An [`AlignmentHint`] outlining the suggested alignment.
+    #[inline(always)]
+    pub fn new(num_bytes: usize) -> Self {
+        Self::with_system_info(num_bytes, SystemInfo::get())
+    }
+
     /// Gets the optimal alignment for the number of bytes.
     ///
-    /// If the number of bytes is a multiple of 2 MB, a natural 2 MB boundary
+    /// If Transparent Huge Pages are available and the number of bytes is a multiple
+    /// of the huge page size (e.g. 2 MB on x86-64), a natural huge page boundary
     /// is selected and a hint for using Huge/Large Pages is issued.
     ///
-    /// In any other case, an alignment of 64 byte boundaries is produced, which
-    /// should be optimal for both AVX-2 and AVX-512 operations.
+    /// In any other case, an alignment to the SIMD boundary (the cache line size, but
+    /// at least 64 bytes) is produced, which should be optimal for both AVX-2 and
+    /// AVX-512 operations.
     ///
     /// ## Arguments
     /// * `num_bytes` - The number of bytes to allocate.
+    /// * `system` - The page configuration of the host.
     ///
     /// ## Returns
     /// An [`AlignmentHint`] outlining the suggested alignment.
-    #[inline(always)]
-    pub fn new(num_bytes: usize) -> Self {
+    pub fn with_system_info(num_bytes: usize, system: &SystemInfo) -> Self {
         if num_bytes == 0 {
-            AlignmentHint {
+            return AlignmentHint {
                 alignment: ZERO_BYTES,
                 use_huge_pages: false,
-            }
-        } else if (num_bytes & (TWO_MEGABYTES - 1)) == 0 {
-            AlignmentHint {
-                alignment: TWO_MEGABYTES,
+            };
+        }
+
+        match system.transparent_huge_page_size() {
+            Some(huge_page_size) if num_bytes % huge_page_size == 0 => AlignmentHint {
+                alignment: huge_page_size,
                 use_huge_pages: true,
-            }
-        } else {
-            AlignmentHint {
-                alignment: SIXTY_FOUR_BYTES,
+            },
+            _ => AlignmentHint {
+                alignment: system.simd_alignment(),
                 use_huge_pages: false,
-            }
+            },
         }
     }
 }
@@ -63,46 +81,94 @@ impl AlignmentHint {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::system_info::{x86_64, ThpMode};
+
+    const TWO_MEGABYTES: usize = 2 * 1024 * 1024;
+    const SIXTY_FOUR_BYTES: usize = 64;
+
+    fn get(num_bytes: usize) -> AlignmentHint {
+        AlignmentHint::with_system_info(num_bytes, &x86_64())
+    }
 
     #[test]
     fn get_alignment_4mb_is_2mb_aligned_hugepage() {
-        let memory = AlignmentHint::new(2 * TWO_MEGABYTES);
+        let memory = get(2 * TWO_MEGABYTES);
         assert_eq!(memory.alignment, TWO_MEGABYTES);
         assert!(memory.use_huge_pages);
     }
 
     #[test]
     fn get_alignment_2mb_is_2mb_aligned_hugepage() {
-        let memory = AlignmentHint::new(TWO_MEGABYTES);
+        let memory = get(TWO_MEGABYTES);
         assert_eq!(memory.alignment, TWO_MEGABYTES);
         assert!(memory.use_huge_pages);
     }
 
     #[test]
     fn get_alignment_1mb_is_64b_aligned() {
-        let memory = AlignmentHint::new(TWO_MEGABYTES / 2);
+        let memory = get(TWO_MEGABYTES / 2);
         assert_eq!(memory.alignment, SIXTY_FOUR_BYTES);
         assert!(!memory.use_huge_pages);
     }
 
     #[test]
     fn get_alignment_63kb_is_64b_aligned() {
-        let memory = AlignmentHint::new(63 * 1024);
+        let memory = get(63 * 1024);
         assert_eq!(memory.alignment, SIXTY_FOUR_BYTES);
         assert!(!memory.use_huge_pages);
     }
 
     #[test]
     fn get_alignment_64kb_is_64b_aligned() {
-        let memory = AlignmentHint::new(64 * 1024);
+        let memory = get(64 * 1024);
         assert_eq!(memory.alignment, SIXTY_FOUR_BYTES);
         assert!(!memory.use_huge_pages);
     }
 
     #[test]
     fn get_alignment_0b_is_0b_aligned() {
-        let memory = AlignmentHint::new(0);
+        let memory = get(0);
         assert_eq!(memory.alignment, ZERO_BYTES);
         assert!(!memory.use_huge_pages);
     }
+
+    #[test]
+    fn get_alignment_without_thp_is_64b_aligned() {
+        let system = SystemInfo {
+            thp_mode: ThpMode::Never,
+            ..x86_64()
+        };
+        let memory = AlignmentHint::with_system_info(2 * TWO_MEGABYTES, &system);
+        assert_eq!(memory.alignment, SIXTY_FOUR_BYTES);
+        assert!(!memory.use_huge_pages);
+    }
+
+    #[test]
+    fn get_alignment_aarch64_64kb_pages() {
+        const FIVE_HUNDRED_TWELVE_MEGABYTES: usize = 512 * 1024 * 1024;
+        let system = SystemInfo {
+            page_size: 64 * 1024,
+            huge_page_size: FIVE_HUNDRED_TWELVE_MEGABYTES,
+            ..x86_64()
+        };
+
+        let memory = AlignmentHint::with_system_info(2 * TWO_MEGABYTES, &system);
+        assert_eq!(memory.alignment, SIXTY_FOUR_BYTES);
+        assert!(!memory.use_huge_pages);
+
+        let memory = AlignmentHint::with_system_info(FIVE_HUNDRED_TWELVE_MEGABYTES, &system);
+        assert_eq!(memory.alignment, FIVE_HUNDRED_TWELVE_MEGABYTES);
+        assert!(memory.use_huge_pages);
+    }
+
+    #[test]
+    fn get_alignment_uses_cache_line_size() {
+        let system = SystemInfo {
+            cache_line_size: 128,
+            ..x86_64()
+        };
+        let memory = AlignmentHint::with_system_info(64 * 1024, &system);
+        assert_eq!(memory.alignment, 128);
+        assert!(!memory.use_huge_pages);
+    }
 }
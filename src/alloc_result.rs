@@ -1,5 +1,6 @@
 //! Provides the [`AllocationError`] struct.
 
+use crate::preflight::PreflightVerdict;
 use std::alloc::LayoutError;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
@@ -10,10 +11,31 @@ pub enum AllocationError {
     EmptyAllocation,
     /// The generated memory layout was invalid.
     InvalidAlignment(LayoutError),
+    /// A system call failed with the contained `errno` value.
+    OsError(i32),
+    /// The allocation was rejected by the hook set using [`set_alloc_hook`](crate::set_alloc_hook).
+    Rejected,
+    /// The allocation exceeds the remaining [`Budget`](crate::Budget).
+    BudgetExceeded,
+    /// The host cannot back the allocation, see [`Memory::can_allocate`](crate::Memory::can_allocate).
+    InsufficientMemory(PreflightVerdict),
+}
+
+impl AllocationError {
+    /// Creates an [`AllocationError::OsError`] from the current value of `errno`.
+    pub(crate) fn last_os_error() -> Self {
+        Self::from(std::io::Error::last_os_error())
+    }
 }
 
 impl Error for AllocationError {}
 
+impl From<std::io::Error> for AllocationError {
+    fn from(value: std::io::Error) -> Self {
+        Self::OsError(value.raw_os_error().unwrap_or(libc::EIO))
+    }
+}
+
 impl From<LayoutError> for AllocationError {
     fn from(value: LayoutError) -> Self {
         Self::InvalidAlignment(value)
@@ -25,6 +47,14 @@ impl Display for AllocationError {
         match self {
             AllocationError::EmptyAllocation => f.write_str("zero-byte allocation"),
             AllocationError::InvalidAlignment(e) => write!(f, "invalid memory layout: {e}"),
+            AllocationError::OsError(code) => {
+                write!(f, "{}", std::io::Error::from_raw_os_error(*code))
+            }
+            AllocationError::Rejected => f.write_str("allocation rejected by hook"),
+            AllocationError::BudgetExceeded => f.write_str("memory budget exceeded"),
+            AllocationError::InsufficientMemory(verdict) => {
+                write!(f, "insufficient memory: allocation {verdict}")
+            }
         }
     }
 }
@@ -34,6 +64,10 @@ impl From<AllocationError> for AllocResult {
         match val {
             AllocationError::EmptyAllocation => AllocResult::Empty,
             AllocationError::InvalidAlignment(_) => AllocResult::InvalidAlignment,
+            AllocationError::OsError(_) => AllocResult::OsError,
+            AllocationError::Rejected => AllocResult::Rejected,
+            AllocationError::BudgetExceeded => AllocResult::BudgetExceeded,
+            AllocationError::InsufficientMemory(_) => AllocResult::InsufficientMemory,
         }
     }
 }
@@ -44,6 +78,10 @@ pub enum AllocResult {
     Ok = 0,
     Empty = 1 << 0,
     InvalidAlignment = 1 << 1,
+    OsError = 1 << 2,
+    Rejected = 1 << 3,
+    BudgetExceeded = 1 << 4,
+    InsufficientMemory = 1 << 5,
 }
 
 impl From<u32> for AllocResult {
@@ -52,6 +90,10 @@ impl From<u32> for AllocResult {
             0 => AllocResult::Ok,
             1 => AllocResult::Empty,
             2 => AllocResult::InvalidAlignment,
+            4 => AllocResult::OsError,
+            8 => AllocResult::Rejected,
+            16 => AllocResult::BudgetExceeded,
+            32 => AllocResult::InsufficientMemory,
             _ => panic!(),
         }
     }
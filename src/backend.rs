@@ -0,0 +1,65 @@
+//! Provides the [`Backend`] enum describing how the memory of a [`Memory`](crate::Memory)
+//! instance was obtained, and thus how it needs to be released.
+
+use crate::alloc_result::AllocationError;
+
+/// The mechanism the memory was obtained from.
+#[repr(u32)]
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum Backend {
+    /// The memory was allocated from the global allocator.
+    #[default]
+    Heap = 0,
+    /// The memory is a mapping of a file. See [`Memory::map_file`](crate::Memory::map_file).
+    File = 1,
+    /// The memory is a mapping of an anonymous `memfd` owned by the instance.
+    /// See [`Memory::allocate_shared`](crate::Memory::allocate_shared).
+    Memfd = 2,
+    /// The memory is an anonymous mapping. See [`Memory::allocate_secure`](crate::Memory::allocate_secure).
+    Anonymous = 3,
+    /// The memory is a mapping of `memfd_secret(2)` memory.
+    /// See [`SecureOptions::memfd_secret`](crate::SecureOptions::memfd_secret).
+    Secret = 4,
+}
+
+impl TryFrom<u32> for Backend {
+    type Error = AllocationError;
+
+    /// Converts a raw backend, failing with [`AllocationError::OsError`] (`EINVAL`) for unknown values.
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Backend::Heap),
+            1 => Ok(Backend::File),
+            2 => Ok(Backend::Memfd),
+            3 => Ok(Backend::Anonymous),
+            4 => Ok(Backend::Secret),
+            _ => Err(AllocationError::OsError(libc::EINVAL)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for backend in [
+            Backend::Heap,
+            Backend::File,
+            Backend::Memfd,
+            Backend::Anonymous,
+            Backend::Secret,
+        ] {
+            assert_eq!(Backend::try_from(backend as u32), Ok(backend));
+        }
+    }
+
+    #[test]
+    fn invalid_backend() {
+        assert_eq!(
+            Backend::try_from(u32::MAX),
+            Err(AllocationError::OsError(libc::EINVAL))
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Provides [`Budget`], a byte limit that allocations can be charged against.
+//!
+//! Memory allocated using [`Memory::allocate_in`] is charged to a budget by its capacity,
+//! and the charge is released when the memory is freed. Allocations exceeding the budget
+//! fail with [`AllocationError::BudgetExceeded`] rather than driving the process into the
+//! out-of-memory killer. A budget can be derived from the cgroup v2 `memory.max` limit of
+//! the current process using [`Budget::from_cgroup`].
+//!
+//! # Structs
+//! - [`Budget`]: A shareable byte limit.
+
+use crate::alloc_result::AllocationError;
+use crate::cgroup;
+use crate::memory::Memory;
+use crate::options::AllocationOptions;
+use crate::stats;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A byte limit that allocations can be charged against.
+///
+/// Clones share the same limit and usage, so a budget can be handed to multiple threads.
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::{AllocationError, AllocationOptions, Budget, Memory};
+/// let budget = Budget::new(64 * 1024);
+/// let options = AllocationOptions::new();
+///
+/// let memory = Memory::allocate_in(&budget, 32 * 1024, &options).unwrap();
+/// assert_eq!(budget.used(), memory.capacity());
+/// assert_eq!(
+///     Memory::allocate_in(&budget, 64 * 1024, &options).unwrap_err(),
+///     AllocationError::BudgetExceeded
+/// );
+///
+/// drop(memory);
+/// assert_eq!(budget.used(), 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Budget {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl Budget {
+    /// Creates a budget of `limit` bytes.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                limit,
+                used: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Creates a budget of the cgroup v2 memory limit of the current process.
+    ///
+    /// Note that the limit covers all memory of the cgroup, including the heap of the process
+    /// and the page cache. Consider deriving a smaller budget using [`Budget::cgroup_limit`].
+    ///
+    /// Returns `None` if the process is not in a cgroup v2 hierarchy or no limit is set.
+    pub fn from_cgroup() -> Option<Self> {
+        Self::cgroup_limit().map(Self::new)
+    }
+
+    /// Reads the cgroup v2 memory limit of the current process, which is the lowest
+    /// `memory.max` of its cgroup and all of its ancestors.
+    ///
+    /// Returns `None` if the process is not in a cgroup v2 hierarchy or no limit is set.
+    pub fn cgroup_limit() -> Option<usize> {
+        cgroup::limits().into_iter().map(|limit| limit.max).min()
+    }
+
+    /// Returns the limit in bytes.
+    pub fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    /// Returns the number of bytes currently charged.
+    pub fn used(&self) -> usize {
+        self.inner.used.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes that can still be charged.
+    pub fn available(&self) -> usize {
+        self.limit().saturating_sub(self.used())
+    }
+
+    /// Charges `num_bytes`, failing if the limit would be exceeded.
+    pub(crate) fn try_charge(&self, num_bytes: usize) -> Result<(), AllocationError> {
+        self.inner
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(num_bytes)
+                    .filter(|&used| used <= self.inner.limit)
+            })
+            .map(|_| ())
+            .map_err(|_| AllocationError::BudgetExceeded)
+    }
+
+    /// Releases a charge of `num_bytes`.
+    pub(crate) fn release(&self, num_bytes: usize) {
+        self.inner.used.fetch_sub(num_bytes, Ordering::Relaxed);
+    }
+}
+
+impl Memory {
+    /// Allocates memory using the specified options, and charges its capacity to the budget.
+    ///
+    /// The charge is released when the memory is freed.
+    ///
+    /// ## Errors
+    /// Fails with [`AllocationError::BudgetExceeded`] if the capacity exceeds the remaining budget.
+    ///
+    /// ## Arguments
+    /// * `budget` - The budget to charge.
+    /// * `num_bytes` - The number of bytes to allocate.
+    /// * `options` - The allocation options.
+    pub fn allocate_in(
+        budget: &Budget,
+        num_bytes: usize,
+        options: &AllocationOptions,
+    ) -> Result<Self, AllocationError> {
+        // The capacity is at least the number of bytes, so this fails early without allocating.
+        if num_bytes > budget.available() {
+            return stats::record_result(Err(AllocationError::BudgetExceeded));
+        }
+
+        let mut memory = Self::allocate_with(num_bytes, options)?;
+        stats::record_result(budget.try_charge(memory.capacity).map(|_| {
+            memory.budget = Some(budget.clone());
+            memory
+        }))
+    }
+
+    /// Returns the budget the memory is charged to, if any.
+    #[inline(always)]
+    pub fn budget(&self) -> Option<&Budget> {
+        self.budget.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charge_and_release() {
+        let budget = Budget::new(100);
+        budget.try_charge(60).unwrap();
+        assert_eq!(budget.try_charge(41), Err(AllocationError::BudgetExceeded));
+        budget.try_charge(40).unwrap();
+        assert_eq!(budget.available(), 0);
+
+        budget.release(100);
+        assert_eq!(budget.used(), 0);
+        assert_eq!(
+            budget.try_charge(usize::MAX),
+            Err(AllocationError::BudgetExceeded)
+        );
+    }
+
+    #[test]
+    fn allocations_are_charged_until_freed() {
+        let budget = Budget::new(1 << 20);
+        let options = AllocationOptions::new().page_aligned(true);
+
+        let first = Memory::allocate_in(&budget, 1000, &options).unwrap();
+        assert_eq!(budget.used(), first.capacity());
+        assert!(first.budget().is_some());
+
+        let clone = budget.clone();
+        let mut second = Memory::allocate_in(&clone, 1000, &options).unwrap();
+        assert_eq!(budget.used(), first.capacity() + second.capacity());
+
+        second.free();
+        drop(first);
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn exceeding_allocations_fail() {
+        let budget = Budget::new(4096);
+        let options = AllocationOptions::new().page_aligned(true);
+        assert_eq!(
+            Memory::allocate_in(&budget, 8192, &options).unwrap_err(),
+            AllocationError::BudgetExceeded
+        );
+
+        // The capacity is charged, which exceeds the budget after rounding to pages.
+        let small = Budget::new(100);
+        assert_eq!(
+            Memory::allocate_in(&small, 100, &options).unwrap_err(),
+            AllocationError::BudgetExceeded
+        );
+        assert_eq!(small.used(), 0);
+    }
+}
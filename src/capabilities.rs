@@ -0,0 +1,287 @@
+//! Provides the [`Capabilities`] struct describing the features the library was built with
+//! and the memory management features supported by the host.
+//!
+//! The page configuration is taken from [`SystemInfo`], the number of NUMA nodes is read
+//! from `/sys/devices/system/node`. Support for the individual `madvise(2)` advices is
+//! determined by probing them on a scratch page.
+
+use crate::system_info::{SystemInfo, ThpMode};
+use std::fs;
+use std::path::Path;
+use std::ptr::null_mut;
+
+/// The library was built with the `ffi` crate feature.
+pub const FEATURE_FFI: u32 = 1 << 0;
+
+/// The library was built with the `legacy-symbols` crate feature.
+pub const FEATURE_LEGACY_SYMBOLS: u32 = 1 << 1;
+
+/// The library was built with the `stats` crate feature.
+pub const FEATURE_STATS: u32 = 1 << 2;
+
+/// The library was built with the `tracing` crate feature.
+pub const FEATURE_TRACING: u32 = 1 << 3;
+
+const NUMA_NODES: &str = "/sys/devices/system/node";
+
+/// The `madvise(2)` advices supported by the host kernel.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct MadviseSupport {
+    /// `MADV_SEQUENTIAL` is supported.
+    pub sequential: bool,
+    /// `MADV_HUGEPAGE` is supported.
+    pub huge_page: bool,
+    /// `MADV_FREE` is supported.
+    pub free: bool,
+    /// `MADV_DONTDUMP` is supported.
+    pub dont_dump: bool,
+    /// `MADV_DONTFORK` is supported.
+    pub dont_fork: bool,
+    /// `MADV_WIPEONFORK` is supported.
+    pub wipe_on_fork: bool,
+    /// `MADV_MERGEABLE` is supported, i.e. the kernel was built with KSM.
+    pub mergeable: bool,
+}
+
+impl MadviseSupport {
+    /// Determines the supported advices by applying each of them to a scratch page.
+    fn probe(page_size: usize) -> Self {
+        // SAFETY: Creates a fresh private anonymous mapping that is unmapped below.
+        let page = unsafe {
+            libc::mmap(
+                null_mut(),
+                page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if page == libc::MAP_FAILED {
+            return Self::default();
+        }
+
+        // SAFETY: `page` is a valid mapping of `page_size` bytes.
+        let supports = |advice| unsafe { libc::madvise(page, page_size, advice) == 0 };
+        let support = Self {
+            sequential: supports(libc::MADV_SEQUENTIAL),
+            huge_page: supports(libc::MADV_HUGEPAGE),
+            free: supports(libc::MADV_FREE),
+            dont_dump: supports(libc::MADV_DONTDUMP),
+            dont_fork: supports(libc::MADV_DONTFORK),
+            wipe_on_fork: supports(libc::MADV_WIPEONFORK),
+            mergeable: supports(libc::MADV_MERGEABLE),
+        };
+
+        // SAFETY: `page` was mapped above and is not referenced anymore.
+        unsafe {
+            libc::munmap(page, page_size);
+        }
+        support
+    }
+
+    /// Returns the supported advices as a bit mask of `MADVISE_*` flags, as used over FFI.
+    pub fn bits(&self) -> u32 {
+        [
+            (self.sequential, MADVISE_SEQUENTIAL),
+            (self.huge_page, MADVISE_HUGEPAGE),
+            (self.free, MADVISE_FREE),
+            (self.dont_dump, MADVISE_DONTDUMP),
+            (self.dont_fork, MADVISE_DONTFORK),
+            (self.wipe_on_fork, MADVISE_WIPEONFORK),
+            (self.mergeable, MADVISE_MERGEABLE),
+        ]
+        .iter()
+        .filter(|(supported, _)| *supported)
+        .fold(0, |bits, (_, flag)| bits | flag)
+    }
+}
+
+/// `MADV_SEQUENTIAL` is supported.
+pub const MADVISE_SEQUENTIAL: u32 = 1 << 0;
+/// `MADV_HUGEPAGE` is supported.
+pub const MADVISE_HUGEPAGE: u32 = 1 << 1;
+/// `MADV_FREE` is supported.
+pub const MADVISE_FREE: u32 = 1 << 2;
+/// `MADV_DONTDUMP` is supported.
+pub const MADVISE_DONTDUMP: u32 = 1 << 3;
+/// `MADV_DONTFORK` is supported.
+pub const MADVISE_DONTFORK: u32 = 1 << 4;
+/// `MADV_WIPEONFORK` is supported.
+pub const MADVISE_WIPEONFORK: u32 = 1 << 5;
+/// `MADV_MERGEABLE` is supported.
+pub const MADVISE_MERGEABLE: u32 = 1 << 6;
+
+/// The features of the library and the capabilities of the host it is running on.
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::Capabilities;
+/// let capabilities = Capabilities::detect();
+/// assert!(capabilities.page_size.is_power_of_two());
+/// assert!(capabilities.numa_nodes >= 1);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The major version of the library.
+    pub version_major: u32,
+    /// The minor version of the library.
+    pub version_minor: u32,
+    /// The patch version of the library.
+    pub version_patch: u32,
+    /// The crate features the library was built with, as a bit mask of `FEATURE_*` flags.
+    pub features: u32,
+    /// The size of a regular memory page in bytes.
+    pub page_size: usize,
+    /// The size of a Transparent Huge Page in bytes, or zero if unavailable.
+    pub huge_page_size: usize,
+    /// The sizes of the available `hugetlbfs` pages in bytes, in ascending order.
+    pub huge_page_sizes: Vec<usize>,
+    /// The configured Transparent Huge Page mode.
+    pub thp_mode: ThpMode,
+    /// The supported `madvise(2)` advices.
+    pub madvise: MadviseSupport,
+    /// The number of NUMA nodes; at least one.
+    pub numa_nodes: usize,
+}
+
+/// cbindgen:ignore
+impl Capabilities {
+    /// The library was built with the `ffi` crate feature.
+    pub const FEATURE_FFI: u32 = FEATURE_FFI;
+
+    /// The library was built with the `legacy-symbols` crate feature.
+    pub const FEATURE_LEGACY_SYMBOLS: u32 = FEATURE_LEGACY_SYMBOLS;
+
+    /// The library was built with the `stats` crate feature.
+    pub const FEATURE_STATS: u32 = FEATURE_STATS;
+
+    /// The library was built with the `tracing` crate feature.
+    pub const FEATURE_TRACING: u32 = FEATURE_TRACING;
+
+    /// Detects the capabilities of the library and the host.
+    pub fn detect() -> Self {
+        let system = SystemInfo::get();
+        Self {
+            version_major: parse_version(env!("CARGO_PKG_VERSION_MAJOR")),
+            version_minor: parse_version(env!("CARGO_PKG_VERSION_MINOR")),
+            version_patch: parse_version(env!("CARGO_PKG_VERSION_PATCH")),
+            features: features(),
+            page_size: system.page_size,
+            huge_page_size: system.huge_page_size,
+            huge_page_sizes: system.huge_tlb_page_sizes().collect(),
+            thp_mode: system.thp_mode,
+            madvise: MadviseSupport::probe(system.page_size),
+            numa_nodes: numa_nodes(Path::new(NUMA_NODES)),
+        }
+    }
+
+    /// Returns whether the library was built with the specified `FEATURE_*` flag.
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::Capabilities;
+    /// let capabilities = Capabilities::detect();
+    /// assert_eq!(capabilities.has_feature(Capabilities::FEATURE_FFI), cfg!(feature = "ffi"));
+    /// ```
+    pub fn has_feature(&self, feature: u32) -> bool {
+        (self.features & feature) == feature
+    }
+}
+
+/// Gets the crate features the library was built with.
+fn features() -> u32 {
+    let mut features = 0;
+    if cfg!(feature = "ffi") {
+        features |= FEATURE_FFI;
+    }
+    if cfg!(feature = "legacy-symbols") {
+        features |= FEATURE_LEGACY_SYMBOLS;
+    }
+    if cfg!(feature = "stats") {
+        features |= FEATURE_STATS;
+    }
+    if cfg!(feature = "tracing") {
+        features |= FEATURE_TRACING;
+    }
+    features
+}
+
+fn parse_version(value: &str) -> u32 {
+    value.parse().unwrap_or(0)
+}
+
+/// Counts the `node<N>` directory entries, falling back to a single node.
+fn numa_nodes(path: &Path) -> usize {
+    let count = fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix("node"))
+                .is_some_and(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .count();
+    count.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_reports_version() {
+        let capabilities = Capabilities::detect();
+        let version = format!(
+            "{}.{}.{}",
+            capabilities.version_major, capabilities.version_minor, capabilities.version_patch
+        );
+        assert!(env!("CARGO_PKG_VERSION").starts_with(&version));
+    }
+
+    #[test]
+    fn detect_reports_features() {
+        let capabilities = Capabilities::detect();
+        assert_eq!(capabilities.has_feature(FEATURE_FFI), cfg!(feature = "ffi"));
+        assert_eq!(
+            capabilities.has_feature(FEATURE_LEGACY_SYMBOLS),
+            cfg!(feature = "legacy-symbols")
+        );
+        assert_eq!(
+            capabilities.has_feature(FEATURE_STATS),
+            cfg!(feature = "stats")
+        );
+        assert_eq!(
+            capabilities.has_feature(FEATURE_TRACING),
+            cfg!(feature = "tracing")
+        );
+    }
+
+    #[test]
+    fn detect_reports_host() {
+        let capabilities = Capabilities::detect();
+        assert!(capabilities.page_size.is_power_of_two());
+        assert!(capabilities.numa_nodes >= 1);
+        assert!(capabilities.madvise.sequential);
+        assert!(capabilities
+            .huge_page_sizes
+            .windows(2)
+            .all(|sizes| sizes[0] < sizes[1]));
+        if capabilities.thp_mode != ThpMode::Unavailable {
+            assert!(capabilities.huge_page_size > capabilities.page_size);
+        }
+    }
+
+    #[test]
+    fn madvise_bits() {
+        let support = MadviseSupport {
+            sequential: true,
+            wipe_on_fork: true,
+            ..Default::default()
+        };
+        assert_eq!(support.bits(), MADVISE_SEQUENTIAL | MADVISE_WIPEONFORK);
+    }
+}
@@ -0,0 +1,104 @@
+//! Reads the memory limits of the cgroup v2 hierarchy of the current process.
+
+use std::fs;
+use std::path::Path;
+
+const PROC_CGROUP: &str = "/proc/self/cgroup";
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// The memory limit and usage of a cgroup.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) struct CgroupMemory {
+    /// The limit in bytes, read from `memory.max`.
+    pub(crate) max: usize,
+    /// The usage in bytes, read from `memory.current`.
+    pub(crate) current: usize,
+}
+
+impl CgroupMemory {
+    /// Returns the number of bytes that can still be charged to the cgroup.
+    pub(crate) fn remaining(&self) -> usize {
+        self.max.saturating_sub(self.current)
+    }
+}
+
+/// Reads the memory limits set on the cgroup of the current process and its ancestors.
+///
+/// Returns no limits if the process is not in a cgroup v2 hierarchy.
+pub(crate) fn limits() -> Vec<CgroupMemory> {
+    match fs::read_to_string(PROC_CGROUP) {
+        Ok(cgroup) => limits_in(Path::new(CGROUP_ROOT), &cgroup),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Collects the limits along the cgroup v2 path listed in `/proc/self/cgroup`, innermost first.
+fn limits_in(root: &Path, proc_cgroup: &str) -> Vec<CgroupMemory> {
+    // The cgroup v2 hierarchy is listed as `0::/path`.
+    let Some(path) = proc_cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+    else {
+        return Vec::new();
+    };
+
+    let mut directory = root.join(path.trim_start_matches('/'));
+    let mut limits = Vec::new();
+    loop {
+        // The value is `max` if no limit is set.
+        if let Ok(Ok(max)) =
+            fs::read_to_string(directory.join("memory.max")).map(|value| value.trim().parse())
+        {
+            let current = fs::read_to_string(directory.join("memory.current"))
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(0);
+            limits.push(CgroupMemory { max, current });
+        }
+        if directory == root || !directory.pop() {
+            return limits;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn limits_of_ancestors() {
+        let dir = TempDir::new("limits_of_ancestors");
+        let leaf = dir.0.join("system.slice/job.scope");
+        std::fs::create_dir_all(&leaf).unwrap();
+        std::fs::write(dir.0.join("system.slice/memory.max"), "1073741824\n").unwrap();
+        std::fs::write(dir.0.join("system.slice/memory.current"), "1048576\n").unwrap();
+        std::fs::write(leaf.join("memory.max"), "max\n").unwrap();
+
+        let cgroup = "0::/system.slice/job.scope\n";
+        assert_eq!(
+            limits_in(&dir.0, cgroup),
+            [CgroupMemory {
+                max: 1 << 30,
+                current: 1 << 20
+            }]
+        );
+
+        std::fs::write(leaf.join("memory.max"), "536870912\n").unwrap();
+        let limits = limits_in(&dir.0, cgroup);
+        assert_eq!(limits.len(), 2);
+        assert_eq!(limits[0].max, 1 << 29);
+        assert_eq!(limits[0].remaining(), 1 << 29);
+        assert_eq!(limits[1].remaining(), (1 << 30) - (1 << 20));
+    }
+
+    #[test]
+    fn no_limits() {
+        let dir = TempDir::new("no_limits");
+        std::fs::write(dir.0.join("memory.max"), "max\n").unwrap();
+        assert_eq!(limits_in(&dir.0, "0::/\n"), []);
+
+        // cgroup v1 hierarchies are not supported.
+        assert_eq!(limits_in(&dir.0, "4:memory:/job\n"), []);
+    }
+}
@@ -6,21 +6,56 @@
 //! # Structures
 //!
 //! - [`Memory`]: Holds information about the allocated memory, including status, flags, number of bytes, and address.
+//! - [`MemoryV2`]: Like [`Memory`], but additionally persists the alignment and backend used for the allocation.
+//! - [`Capabilities`]: Describes the features of the library and the capabilities of the host.
+//! - [`AllocEvent`]: Describes memory being obtained or released, passed to allocation hooks.
 //!
 //! # Functions
 //!
-//! - `version`: Returns a pointer to a C string containing the version of the library.
-//! - `allocate_block`: Allocates a memory block of the specified number of bytes, with options for sequential and clear allocation.
-//! - `free_block`: Frees a previously allocated memory block.
+//! - `alloc_madvise_version`: Returns a pointer to a C string containing the version of the library.
+//! - `alloc_madvise_allocate`: Allocates a memory block of the specified number of bytes, with options for sequential and clear allocation.
+//! - `alloc_madvise_free`: Frees a previously allocated memory block.
+//! - `alloc_madvise_allocate_v2`: Allocates a memory block, returning a [`MemoryV2`].
+//! - `alloc_madvise_free_v2`: Frees a memory block allocated by `alloc_madvise_allocate_v2`.
+//! - `alloc_madvise_map_file`: Maps a file into memory, returning a [`MemoryV2`].
+//! - `alloc_madvise_create_file`: Creates a sized file and maps it into memory, returning a [`MemoryV2`].
+//! - `alloc_madvise_flush`: Writes modifications of a file mapping back to the file.
+//! - `alloc_madvise_allocate_shared`: Allocates memory backed by a `memfd`, returning a [`MemoryV2`].
+//! - `alloc_madvise_from_fd`: Maps shared memory received from another process, returning a [`MemoryV2`].
+//! - `alloc_madvise_shm_create`: Creates a named shared memory segment, returning a [`MemoryV2`].
+//! - `alloc_madvise_shm_open`: Opens a named shared memory segment, returning a [`MemoryV2`].
+//! - `alloc_madvise_shm_unlink`: Removes the name of a shared memory segment.
+//! - `alloc_madvise_capabilities`: Fills a [`Capabilities`] struct.
+//! - `alloc_madvise_set_alloc_hook`: Sets a hook invoked whenever memory is obtained or released.
+//!
+//! All exported symbols are prefixed with `alloc_madvise_` in order to avoid collisions with
+//! other libraries linked into the same process. If the `legacy-symbols` crate feature is enabled
+//! (the default), the deprecated unprefixed names `version`, `allocate_block` and `free_block`
+//! are exported as well.
 //!
 //! # Safety
 //!
 //! All functions in this module are marked as `unsafe` because they involve raw pointers and FFI, which can lead to undefined behavior if misused.
 
-use crate::alloc_result::AllocResult;
-use std::mem::ManuallyDrop;
+use crate::alloc_result::{AllocResult, AllocationError};
+use crate::backend::Backend;
+use crate::hooks::{AllocEventKind, HookDecision};
+use crate::memfd::MemfdOptions;
+use crate::mmap::{Advice, MapMode};
+use crate::policy::{AlignmentPolicy, DefaultPolicy};
+use crate::secure::SecureOptions;
+use crate::shm::{SharedMemory, ShmOptions};
+use crate::system_info::SystemInfo;
+use std::ffi::CStr;
+use std::mem::{size_of, ManuallyDrop};
+use std::os::fd::BorrowedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
 use std::ptr::null_mut;
 
+/// The maximum number of `hugetlbfs` page sizes reported in [`Capabilities`].
+pub const MAX_HUGE_PAGE_SIZES: usize = 8;
+
 /// Information about the allocated memory.
 #[repr(C)]
 pub struct Memory {
@@ -34,21 +69,309 @@ pub struct Memory {
     pub address: *mut std::ffi::c_void,
 }
 
+/// Information about the allocated memory, including the metadata required for freeing it.
+///
+/// Unlike [`Memory`], freeing a [`MemoryV2`] does not need to recompute the alignment, so
+/// changing the alignment policy between allocation and deallocation is sound.
+#[repr(C)]
+pub struct MemoryV2 {
+    /// The allocation status: 0 if valid.
+    pub status: u32,
+    /// Allocation flags. Used internally when calling free.
+    pub flags: u32,
+    /// The `Backend` the memory was obtained from. Used internally when calling free.
+    pub backend: u32,
+    /// The file descriptor of shared memory or a file mapping, or `-1`. Closed when calling free.
+    pub fd: i32,
+    /// The number of usable bytes. May be reduced by the caller, but never exceeds `capacity`.
+    pub num_bytes: usize,
+    /// The number of reserved bytes. Used internally when calling free.
+    pub capacity: usize,
+    /// The alignment of the allocated memory. Used internally when calling free.
+    pub alignment: usize,
+    /// The address of the allocated memory.
+    pub address: *mut std::ffi::c_void,
+}
+
+/// Features of the library and capabilities of the host.
+///
+/// The struct is versioned by its size: callers set `struct_size` to the size of the
+/// struct they were compiled against, and the library only fills in the fields that fit.
+/// Fields are only ever appended in newer versions.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    /// The size of this struct in bytes. Set by the caller, updated to the number of bytes filled in.
+    pub struct_size: u32,
+    /// The major version of the library.
+    pub version_major: u32,
+    /// The minor version of the library.
+    pub version_minor: u32,
+    /// The patch version of the library.
+    pub version_patch: u32,
+    /// The crate features the library was built with, as a bit mask of `FEATURE_*` flags.
+    pub features: u32,
+    /// The configured Transparent Huge Page mode.
+    pub thp_mode: crate::system_info::ThpMode,
+    /// The supported `madvise` advices, as a bit mask of `MADVISE_*` flags.
+    pub madvise: u32,
+    /// The number of NUMA nodes.
+    pub numa_nodes: u32,
+    /// The size of a regular memory page in bytes.
+    pub page_size: u64,
+    /// The size of a Transparent Huge Page in bytes, or zero if unavailable.
+    pub huge_page_size: u64,
+    /// The number of valid entries in `huge_page_sizes`.
+    pub num_huge_page_sizes: u32,
+    /// The sizes of the available `hugetlbfs` pages in bytes, in ascending order.
+    pub huge_page_sizes: [u64; MAX_HUGE_PAGE_SIZES],
+}
+
+impl From<crate::capabilities::Capabilities> for Capabilities {
+    fn from(value: crate::capabilities::Capabilities) -> Self {
+        let mut huge_page_sizes = [0; MAX_HUGE_PAGE_SIZES];
+        let num_huge_page_sizes = value.huge_page_sizes.len().min(MAX_HUGE_PAGE_SIZES);
+        for (target, size) in huge_page_sizes.iter_mut().zip(&value.huge_page_sizes) {
+            *target = *size as u64;
+        }
+
+        Capabilities {
+            struct_size: size_of::<Capabilities>() as u32,
+            version_major: value.version_major,
+            version_minor: value.version_minor,
+            version_patch: value.version_patch,
+            features: value.features,
+            thp_mode: value.thp_mode,
+            madvise: value.madvise.bits(),
+            numa_nodes: value.numa_nodes as u32,
+            page_size: value.page_size as u64,
+            huge_page_size: value.huge_page_size as u64,
+            num_huge_page_sizes: num_huge_page_sizes as u32,
+            huge_page_sizes,
+        }
+    }
+}
+
+/// Global allocation statistics, see `alloc_madvise_get_stats`.
+///
+/// The struct is versioned by its size like `Capabilities`. Fields are only ever appended in newer versions.
+#[cfg(feature = "stats")]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AllocationStats {
+    /// The size of this struct in bytes. Set by the caller, updated to the number of bytes filled in.
+    pub struct_size: u32,
+    /// All memory.
+    pub total: crate::stats::Usage,
+    /// Memory advised to use Transparent Huge Pages.
+    pub huge_pages: crate::stats::Usage,
+    /// Memory using regular pages.
+    pub regular_pages: crate::stats::Usage,
+    /// Memory advised for sequential access.
+    pub sequential: crate::stats::Usage,
+    /// Memory advised for normal access.
+    pub normal: crate::stats::Usage,
+    /// Memory allocated from the heap.
+    pub heap: crate::stats::Usage,
+    /// File mappings.
+    pub file: crate::stats::Usage,
+    /// Shared memory backed by a `memfd`.
+    pub memfd: crate::stats::Usage,
+    /// Anonymous mappings.
+    pub anonymous: crate::stats::Usage,
+    /// Secret memory.
+    pub secret: crate::stats::Usage,
+    /// The failed allocations.
+    pub failures: crate::stats::Failures,
+}
+
+#[cfg(feature = "stats")]
+impl From<crate::stats::AllocationStats> for AllocationStats {
+    fn from(value: crate::stats::AllocationStats) -> Self {
+        AllocationStats {
+            struct_size: size_of::<AllocationStats>() as u32,
+            total: value.total,
+            huge_pages: value.huge_pages,
+            regular_pages: value.regular_pages,
+            sequential: value.sequential,
+            normal: value.normal,
+            heap: value.heap,
+            file: value.file,
+            memfd: value.memfd,
+            anonymous: value.anonymous,
+            secret: value.secret,
+            failures: value.failures,
+        }
+    }
+}
+
+/// Describes memory being obtained or released, see `alloc_madvise_set_alloc_hook`.
+///
+/// Fields are only ever appended in newer versions; `struct_size` holds the size of the struct
+/// passed by the library.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AllocEvent {
+    /// The size of this struct in bytes.
+    pub struct_size: u32,
+    /// Whether the memory is being obtained or released.
+    pub kind: AllocEventKind,
+    /// The mechanism the memory was obtained from.
+    pub backend: Backend,
+    /// The number of usable bytes.
+    pub num_bytes: usize,
+    /// The number of bytes reserved.
+    pub capacity: usize,
+    /// The alignment of the memory.
+    pub alignment: usize,
+    /// The address of the memory.
+    pub address: *mut std::ffi::c_void,
+}
+
+impl From<&crate::hooks::AllocEvent> for AllocEvent {
+    fn from(value: &crate::hooks::AllocEvent) -> Self {
+        AllocEvent {
+            struct_size: size_of::<AllocEvent>() as u32,
+            kind: value.kind,
+            backend: value.backend,
+            num_bytes: value.num_bytes,
+            capacity: value.capacity,
+            alignment: value.alignment,
+            address: value.address as *mut std::ffi::c_void,
+        }
+    }
+}
+
+/// A hook invoked whenever memory is obtained or released, see `alloc_madvise_set_alloc_hook`.
+///
+/// Receives the event and the context passed when setting the hook. Returns `false` to
+/// reject an allocation; the return value is ignored for releases.
+pub type AllocHook =
+    Option<unsafe extern "C" fn(event: *const AllocEvent, context: *mut std::ffi::c_void) -> bool>;
+
+/// The context of a hook set through FFI.
+struct HookContext(*mut std::ffi::c_void);
+
+// SAFETY: The caller of `alloc_madvise_set_alloc_hook` guarantees that the context can be used
+// from any thread.
+unsafe impl Send for HookContext {}
+
+// SAFETY: See above.
+unsafe impl Sync for HookContext {}
+
+impl HookContext {
+    fn get(&self) -> *mut std::ffi::c_void {
+        self.0
+    }
+}
+
+/// Options for allocating memory using `alloc_madvise_allocate_with`.
+///
+/// Fields are only ever appended in newer versions. Flags are enabled by any non-zero value.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AllocationOptions {
+    /// The size of this struct in bytes, as known to the caller. Fields beyond it take their defaults.
+    pub struct_size: u32,
+    /// Whether the memory access pattern is sequential mostly.
+    pub sequential: u8,
+    /// Whether to zero out the allocated memory.
+    pub clear: u8,
+    /// Whether the memory is obtained from a page-granular anonymous mapping.
+    pub page_aligned: u8,
+    /// Whether the memory is excluded from child processes created using `fork`.
+    pub dont_fork: u8,
+    /// Whether the memory is zeroed out in child processes created using `fork`.
+    pub wipe_on_fork: u8,
+    /// Whether the memory may be merged with identical pages by Kernel Samepage Merging.
+    pub mergeable: u8,
+    /// The name of the mapping shown in `/proc/<pid>/maps` as a NUL-terminated string, or null.
+    pub name: *const libc::c_char,
+}
+
+impl Default for AllocationOptions {
+    fn default() -> Self {
+        Self {
+            struct_size: size_of::<AllocationOptions>() as u32,
+            sequential: 0,
+            clear: 0,
+            page_aligned: 0,
+            dont_fork: 0,
+            wipe_on_fork: 0,
+            mergeable: 0,
+            name: std::ptr::null(),
+        }
+    }
+}
+
+impl AllocationOptions {
+    /// Reads the options from a caller-provided struct of `struct_size` bytes.
+    ///
+    /// Only complete fields are read; a partially covered field keeps its default.
+    unsafe fn read(options: *const AllocationOptions) -> Self {
+        let mut value = Self::default();
+        if options.is_null() {
+            return value;
+        }
+
+        // The flags are single bytes, so only the name can be covered partially.
+        let name = std::ptr::addr_of!(value.name) as usize - std::ptr::addr_of!(value) as usize;
+        let mut num_bytes = ((*options).struct_size as usize).min(size_of::<AllocationOptions>());
+        if num_bytes < name + size_of::<*const libc::c_char>() {
+            num_bytes = num_bytes.min(name);
+        }
+        std::ptr::copy_nonoverlapping(
+            options.cast::<u8>(),
+            (&mut value as *mut AllocationOptions).cast::<u8>(),
+            num_bytes,
+        );
+        value.struct_size = size_of::<AllocationOptions>() as u32;
+        value
+    }
+
+    /// Converts the options, failing if the name is not valid UTF-8.
+    unsafe fn to_options(self) -> Result<crate::options::AllocationOptions, AllocationError> {
+        let mut options = crate::options::AllocationOptions::new()
+            .sequential(self.sequential != 0)
+            .clear(self.clear != 0)
+            .page_aligned(self.page_aligned != 0)
+            .dont_fork(self.dont_fork != 0)
+            .wipe_on_fork(self.wipe_on_fork != 0)
+            .mergeable(self.mergeable != 0);
+        if !self.name.is_null() {
+            options = options.name(to_str(self.name)?);
+        }
+        Ok(options)
+    }
+}
+
 pub static VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
 
 /// Gets a version reference in order to identify the library version.
 #[no_mangle]
-pub unsafe extern "C" fn version() -> *const libc::c_char {
+pub unsafe extern "C" fn alloc_madvise_version() -> *const libc::c_char {
     VERSION.as_ptr() as *const libc::c_char
 }
 
 /// Allocates memory of the specified number of bytes.
 ///
 /// The optimal alignment will be determined by the number of bytes provided.
-/// If the amount of bytes is a multiple of 2MB, Huge/Large Page support is enabled.
+/// If the amount of bytes is a multiple of the huge page size, Huge/Large Page support is enabled.
+///
+/// The global alignment policy is not applied, since the alignment is not part of the returned
+/// struct and has to be derived from the number of bytes again when the memory is freed.
+/// Use `alloc_madvise_allocate_v2` to allocate using the global policy.
 #[no_mangle]
-pub unsafe extern "C" fn allocate_block(num_bytes: u32, sequential: bool, clear: bool) -> Memory {
-    match crate::memory::Memory::allocate(num_bytes as usize, sequential, clear) {
+pub unsafe extern "C" fn alloc_madvise_allocate(
+    num_bytes: u32,
+    sequential: bool,
+    clear: bool,
+) -> Memory {
+    let options = crate::options::AllocationOptions::new()
+        .sequential(sequential)
+        .clear(clear)
+        .policy(DefaultPolicy);
+    match crate::memory::Memory::allocate_with(num_bytes as usize, &options) {
         Ok(memory) => {
             let memory = ManuallyDrop::new(memory);
             Memory {
@@ -72,20 +395,461 @@ pub unsafe extern "C" fn allocate_block(num_bytes: u32, sequential: bool, clear:
 
 /// Frees memory of the specified number of bytes.
 ///
-/// The memory instance is required to be created by `allocate`.
+/// The memory instance is required to be created by `alloc_madvise_allocate`.
 #[no_mangle]
-pub unsafe extern "C" fn free_block(memory: Memory) {
-    // NOTE: If this method is called "free", it'll shadow the version from clib ... don't do that.
+pub unsafe extern "C" fn alloc_madvise_free(memory: Memory) {
     let mut wrapped: crate::memory::Memory = memory.into();
     wrapped.free();
 }
 
+/// Allocates memory of the specified number of bytes.
+///
+/// The alignment will be determined by the global alignment policy, which by default
+/// selects the optimal alignment for the number of bytes provided. If the amount of bytes
+/// is a multiple of the huge page size, Huge/Large Page support is enabled.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_allocate_v2(
+    num_bytes: usize,
+    sequential: bool,
+    clear: bool,
+) -> MemoryV2 {
+    MemoryV2::from_result(crate::memory::Memory::allocate(
+        num_bytes, sequential, clear,
+    ))
+}
+
+/// Allocates memory of the specified number of bytes using the specified options.
+///
+/// If `options` is null, the defaults are used. Set `struct_size` to the size of the struct
+/// known to the caller. Free the memory using `alloc_madvise_free_v2`. If the status is `4`,
+/// `errno` describes the error; `EINVAL` indicates an invalid name.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_allocate_with(
+    num_bytes: usize,
+    options: *const AllocationOptions,
+) -> MemoryV2 {
+    let result = AllocationOptions::read(options)
+        .to_options()
+        .and_then(|options| crate::memory::Memory::allocate_with(num_bytes, &options));
+    MemoryV2::from_result(result)
+}
+
+/// Frees memory of the specified number of bytes.
+///
+/// The memory instance is required to be created by `alloc_madvise_allocate_v2`.
+/// Memory with an unknown `backend` cannot be released and is leaked.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_free_v2(memory: MemoryV2) {
+    if let Ok(mut wrapped) = crate::memory::Memory::try_from(memory) {
+        wrapped.free();
+    }
+}
+
+/// Maps an existing file into memory.
+///
+/// `mode` is a `MapMode` and `advice` an `Advice`. The number of bytes is the size of the file.
+/// Free the memory using `alloc_madvise_free_v2`, which unmaps the file. If the status is `4`,
+/// `errno` describes the error; `EINVAL` indicates an unknown mode or advice.
+///
+/// The file is required not to be truncated while it is mapped.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_map_file(
+    path: *const libc::c_char,
+    mode: u32,
+    advice: u32,
+) -> MemoryV2 {
+    MemoryV2::from_result(MapMode::try_from(mode).and_then(|mode| {
+        let advice = Advice::try_from(advice)?;
+        crate::memory::Memory::map_file(to_path(path)?, mode, advice)
+    }))
+}
+
+/// Creates a file of the specified size, or truncates an existing one to it,
+/// and maps it into memory read-write.
+///
+/// `advice` is an `Advice`. Free the memory using `alloc_madvise_free_v2`, which unmaps the file.
+/// If the status is `4`, `errno` describes the error; `EINVAL` indicates an unknown advice.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_create_file(
+    path: *const libc::c_char,
+    num_bytes: usize,
+    advice: u32,
+) -> MemoryV2 {
+    MemoryV2::from_result(
+        Advice::try_from(advice).and_then(|advice| {
+            crate::memory::Memory::create_file(to_path(path)?, num_bytes, advice)
+        }),
+    )
+}
+
+/// Writes modifications of a range of a read-write file mapping back to the file
+/// and waits for completion.
+///
+/// Returns the status: 0 if successful. If the status is `4`, `errno` describes the error.
+/// The range is required to lie within the `num_bytes` of the memory.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_flush(
+    memory: *const MemoryV2,
+    offset: usize,
+    num_bytes: usize,
+) -> u32 {
+    if memory.is_null() || (*memory).address.is_null() {
+        return AllocResult::Empty as u32;
+    }
+
+    let wrapped = match crate::memory::Memory::try_from(std::ptr::read(memory)) {
+        Ok(wrapped) => ManuallyDrop::new(wrapped),
+        Err(e) => return to_status(e) as u32,
+    };
+    match wrapped.flush_range(offset, num_bytes) {
+        Ok(()) => AllocResult::Ok as u32,
+        Err(e) => to_status(e) as u32,
+    }
+}
+
+/// Allocates memory of the specified number of bytes backed by an anonymous `memfd`,
+/// which can be shared with other processes through the `fd` of the returned struct.
+///
+/// `seals` is a combination of `F_SEAL_*` flags applied to the `memfd`, e.g. `F_SEAL_SHRINK`.
+/// If `inheritable` is set, the descriptor is not closed when calling `exec`.
+/// Free the memory using `alloc_madvise_free_v2`, which closes the descriptor.
+/// If the status is `4`, `errno` describes the error.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_allocate_shared(
+    num_bytes: usize,
+    huge_tlb: bool,
+    seals: u32,
+    inheritable: bool,
+) -> MemoryV2 {
+    let mut options = MemfdOptions::new()
+        .huge_tlb(huge_tlb)
+        .inheritable(inheritable);
+    options.seals = seals as libc::c_int;
+    MemoryV2::from_result(crate::memory::Memory::allocate_shared(num_bytes, &options))
+}
+
+/// Allocates secure memory of the specified number of bytes for key material and other sensitive data.
+///
+/// The memory is locked into RAM, excluded from core dumps and wiped in child processes created
+/// using `fork`. If `memfd_secret` is set and the kernel supports it, the memory is additionally
+/// obtained from `memfd_secret`. Free the memory using `alloc_madvise_free_v2`, which overwrites
+/// it with zeros. If the status is `4`, `errno` describes the error.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_allocate_secure(
+    num_bytes: usize,
+    memfd_secret: bool,
+) -> MemoryV2 {
+    let options = SecureOptions::new().memfd_secret(memfd_secret);
+    MemoryV2::from_result(crate::memory::Memory::allocate_secure(num_bytes, &options))
+}
+
+/// Maps shared memory received from another process, aligned by the same rule as
+/// `alloc_madvise_allocate_shared` using the alignment policy of this process. The descriptor
+/// is duplicated, so the original can be closed after mapping. `mode` is a `MapMode`.
+///
+/// Free the memory using `alloc_madvise_free_v2`. If the status is `4`, `errno` describes
+/// the error; `EINVAL` indicates an unknown mode. The file is required not to be truncated
+/// while it is mapped.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_from_fd(fd: i32, num_bytes: usize, mode: u32) -> MemoryV2 {
+    if fd < 0 {
+        return MemoryV2::from_result(Err(AllocationError::OsError(libc::EBADF)));
+    }
+    let fd = BorrowedFd::borrow_raw(fd);
+    MemoryV2::from_result(
+        MapMode::try_from(mode)
+            .and_then(|mode| crate::memory::Memory::from_fd(fd, num_bytes, mode)),
+    )
+}
+
+/// Creates a private copy-on-write snapshot of shared memory or a file mapping.
+///
+/// Modifications of the original after the call are not visible in the snapshot, and vice versa.
+/// All pages are copied when the snapshot is created. Free the snapshot using `alloc_madvise_free_v2`.
+/// If the status is `4`, `errno` describes the error; `EINVAL` indicates that the memory is
+/// neither shared memory nor a file mapping.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_snapshot(memory: *const MemoryV2) -> MemoryV2 {
+    if memory.is_null() || (*memory).address.is_null() {
+        return MemoryV2::from_result(Err(AllocationError::EmptyAllocation));
+    }
+
+    MemoryV2::from_result(
+        crate::memory::Memory::try_from(std::ptr::read(memory))
+            .and_then(|wrapped| ManuallyDrop::new(wrapped).snapshot()),
+    )
+}
+
+/// Creates a named POSIX shared memory segment of the specified number of bytes and maps it read-write.
+///
+/// If `exclusive` is set, creation fails if the segment exists. `permissions` are the mode bits
+/// of a newly created segment, e.g. `0600`. Free the memory using `alloc_madvise_free_v2`, which
+/// unmaps but does not unlink the segment. If the status is `4`, `errno` describes the error.
+///
+/// Other processes are required not to truncate the segment while it is mapped.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_shm_create(
+    name: *const libc::c_char,
+    num_bytes: usize,
+    exclusive: bool,
+    permissions: u32,
+) -> MemoryV2 {
+    let options = ShmOptions::new()
+        .exclusive(exclusive)
+        .permissions(permissions);
+    MemoryV2::from_result(
+        to_str(name)
+            .and_then(|name| SharedMemory::create(name, num_bytes, &options))
+            .map(|mut shm| std::mem::take(&mut *shm)),
+    )
+}
+
+/// Opens a named POSIX shared memory segment and maps all of it using the specified `MapMode`.
+///
+/// Free the memory using `alloc_madvise_free_v2`. If the status is `4`, `errno` describes the error;
+/// `EINVAL` indicates an unknown mode. Other processes are required not to truncate the segment
+/// while it is mapped.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_shm_open(name: *const libc::c_char, mode: u32) -> MemoryV2 {
+    MemoryV2::from_result(
+        MapMode::try_from(mode)
+            .and_then(|mode| SharedMemory::open_with(to_str(name)?, mode))
+            .map(|mut shm| std::mem::take(&mut *shm)),
+    )
+}
+
+/// Removes the name of a POSIX shared memory segment. The memory is released once
+/// all processes detached.
+///
+/// Returns the status: 0 if successful. If the status is `4`, `errno` describes the error.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_shm_unlink(name: *const libc::c_char) -> u32 {
+    match to_str(name).and_then(SharedMemory::unlink) {
+        Ok(()) => AllocResult::Ok as u32,
+        Err(e) => to_status(e) as u32,
+    }
+}
+
+/// Fills in the features of the library and the capabilities of the host.
+///
+/// The caller is required to set `struct_size` to the size of the struct before calling.
+/// Only the fields fitting within `struct_size` bytes are written, and `struct_size` is
+/// updated to the number of bytes written.
+///
+/// Returns `false` if `capabilities` is null or `struct_size` is too small to hold `struct_size` itself.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_capabilities(capabilities: *mut Capabilities) -> bool {
+    if capabilities.is_null() {
+        return false;
+    }
+
+    // SAFETY: The caller guarantees that at least `struct_size` bytes are writable.
+    let requested = (*capabilities).struct_size as usize;
+    if requested < size_of::<u32>() {
+        return false;
+    }
+
+    let detected = Capabilities::from(crate::capabilities::Capabilities::detect());
+    let length = requested.min(size_of::<Capabilities>());
+
+    // SAFETY: The target holds at least `length` bytes, and the source is a valid `Capabilities`.
+    std::ptr::copy_nonoverlapping(
+        (&detected as *const Capabilities).cast::<u8>(),
+        capabilities.cast::<u8>(),
+        length,
+    );
+    (*capabilities).struct_size = length as u32;
+    true
+}
+
+/// Fills in the global allocation statistics.
+///
+/// The caller is required to set `struct_size` to the size of the struct before calling.
+/// Only the fields fitting within `struct_size` bytes are written, and `struct_size` is
+/// updated to the number of bytes written.
+///
+/// Returns `false` if `stats` is null or `struct_size` is too small to hold `struct_size` itself.
+#[cfg(feature = "stats")]
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_get_stats(stats: *mut AllocationStats) -> bool {
+    if stats.is_null() {
+        return false;
+    }
+
+    // SAFETY: The caller guarantees that at least `struct_size` bytes are writable.
+    let requested = (*stats).struct_size as usize;
+    if requested < size_of::<u32>() {
+        return false;
+    }
+
+    let snapshot = AllocationStats::from(crate::stats::snapshot());
+    let length = requested.min(size_of::<AllocationStats>());
+
+    // SAFETY: The target holds at least `length` bytes, and the source is a valid `AllocationStats`.
+    std::ptr::copy_nonoverlapping(
+        (&snapshot as *const AllocationStats).cast::<u8>(),
+        stats.cast::<u8>(),
+        length,
+    );
+    (*stats).struct_size = length as u32;
+    true
+}
+
+/// Sets the hook invoked whenever memory is obtained or released, replacing any previous hook.
+/// Passing a null `hook` removes it.
+///
+/// The hook is invoked once memory was obtained, and may reject it by returning `false`;
+/// the memory is then released and the allocation fails with status `8`. Every accepted
+/// allocation is matched by exactly one release event. The hook may be invoked concurrently
+/// from any thread, and the `context` is required to remain valid until the hook is replaced.
+#[no_mangle]
+pub unsafe extern "C" fn alloc_madvise_set_alloc_hook(
+    hook: AllocHook,
+    context: *mut std::ffi::c_void,
+) {
+    let Some(hook) = hook else {
+        crate::hooks::clear_alloc_hook();
+        return;
+    };
+
+    let context = HookContext(context);
+    crate::hooks::set_alloc_hook(move |event| {
+        let event = AllocEvent::from(event);
+        // SAFETY: The caller guarantees that the hook can be invoked with the context.
+        if unsafe { hook(&event, context.get()) } {
+            HookDecision::Allow
+        } else {
+            HookDecision::Reject
+        }
+    });
+}
+
+/// Gets a version reference in order to identify the library version.
+#[cfg(feature = "legacy-symbols")]
+#[no_mangle]
+#[deprecated(note = "Use alloc_madvise_version instead", since = "0.6.0")]
+pub unsafe extern "C" fn version() -> *const libc::c_char {
+    alloc_madvise_version()
+}
+
+/// Allocates memory of the specified number of bytes.
+///
+/// The optimal alignment will be determined by the number of bytes provided.
+/// If the amount of bytes is a multiple of 2MB, Huge/Large Page support is enabled.
+#[cfg(feature = "legacy-symbols")]
+#[no_mangle]
+#[deprecated(note = "Use alloc_madvise_allocate instead", since = "0.6.0")]
+pub unsafe extern "C" fn allocate_block(num_bytes: u32, sequential: bool, clear: bool) -> Memory {
+    alloc_madvise_allocate(num_bytes, sequential, clear)
+}
+
+/// Frees memory of the specified number of bytes.
+///
+/// The memory instance is required to be created by `allocate_block`.
+#[cfg(feature = "legacy-symbols")]
+#[no_mangle]
+#[deprecated(note = "Use alloc_madvise_free instead", since = "0.6.0")]
+pub unsafe extern "C" fn free_block(memory: Memory) {
+    // NOTE: If this method is called "free", it'll shadow the version from clib ... don't do that.
+    alloc_madvise_free(memory)
+}
+
+impl MemoryV2 {
+    /// Transfers ownership of the memory to the caller, or describes the error.
+    fn from_result(result: Result<crate::memory::Memory, AllocationError>) -> Self {
+        match result {
+            Ok(memory) => ManuallyDrop::new(memory).to_ffi(),
+            Err(e) => MemoryV2 {
+                status: to_status(e) as u32,
+                flags: 0,
+                backend: Backend::Heap as u32,
+                fd: -1,
+                num_bytes: 0,
+                capacity: 0,
+                alignment: 0,
+                address: null_mut(),
+            },
+        }
+    }
+}
+
+/// Converts the error to a status, setting `errno` for OS errors.
+fn to_status(error: AllocationError) -> AllocResult {
+    if let AllocationError::OsError(code) = error {
+        // SAFETY: `errno` is thread-local.
+        unsafe { *libc::__errno_location() = code };
+    }
+    error.into()
+}
+
+/// Converts a C string to a path.
+unsafe fn to_path<'a>(path: *const libc::c_char) -> Result<&'a Path, AllocationError> {
+    if path.is_null() {
+        return Err(AllocationError::OsError(libc::EFAULT));
+    }
+    Ok(Path::new(std::ffi::OsStr::from_bytes(
+        CStr::from_ptr(path).to_bytes(),
+    )))
+}
+
+/// Converts a C string to a string slice.
+unsafe fn to_str<'a>(name: *const libc::c_char) -> Result<&'a str, AllocationError> {
+    if name.is_null() {
+        return Err(AllocationError::OsError(libc::EFAULT));
+    }
+    CStr::from_ptr(name)
+        .to_str()
+        .map_err(|_| AllocationError::OsError(libc::EINVAL))
+}
+
+impl crate::memory::Memory {
+    /// Describes this instance as a [`MemoryV2`], without transferring ownership.
+    pub(crate) fn to_ffi(&self) -> MemoryV2 {
+        MemoryV2 {
+            status: AllocResult::Ok as u32,
+            flags: self.flags,
+            backend: self.backend as u32,
+            fd: self.fd,
+            num_bytes: self.num_bytes,
+            capacity: self.capacity,
+            alignment: self.alignment,
+            address: self.address,
+        }
+    }
+}
+
+impl TryFrom<MemoryV2> for crate::memory::Memory {
+    type Error = AllocationError;
+
+    /// Takes ownership of the memory described by the caller, failing with
+    /// [`AllocationError::OsError`] (`EINVAL`) if the backend is unknown.
+    fn try_from(val: MemoryV2) -> Result<Self, Self::Error> {
+        let mut memory = crate::memory::Memory::new(
+            AllocResult::from(val.status),
+            val.flags,
+            val.num_bytes,
+            val.capacity,
+            val.alignment,
+            Backend::try_from(val.backend)?,
+            val.address,
+        );
+        memory.fd = val.fd;
+        Ok(memory)
+    }
+}
+
 impl From<Memory> for crate::memory::Memory {
     fn from(val: Memory) -> Self {
+        // The alignment is not part of the FFI struct, so it is derived using the same
+        // fixed policy `alloc_madvise_allocate` used, regardless of the global policy.
+        let alignment = DefaultPolicy.hint(val.num_bytes as usize, SystemInfo::get());
         crate::memory::Memory::new(
             AllocResult::from(val.status),
             val.flags,
             val.num_bytes as usize,
+            val.num_bytes as usize,
+            alignment.alignment,
+            Backend::Heap,
             val.address,
         )
     }
@@ -97,7 +861,7 @@ mod tests {
     #[test]
     fn test_version() {
         unsafe {
-            let version_ptr = version();
+            let version_ptr = alloc_madvise_version();
             let version_cstr = std::ffi::CStr::from_ptr(version_ptr);
             assert_eq!(version_cstr.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
         }
@@ -106,18 +870,18 @@ mod tests {
     #[test]
     fn test_allocate_block_success() {
         unsafe {
-            let memory = allocate_block(1024, false, false);
+            let memory = alloc_madvise_allocate(1024, false, false);
             assert_eq!(memory.status, AllocResult::Ok as u32);
             assert_eq!(memory.num_bytes, 1024);
             assert!(!memory.address.is_null());
-            free_block(memory);
+            alloc_madvise_free(memory);
         }
     }
 
     #[test]
     fn test_allocate_block_failure() {
         unsafe {
-            let memory = allocate_block(0, false, false);
+            let memory = alloc_madvise_allocate(0, false, false);
             assert_ne!(memory.status, AllocResult::Ok as u32);
             assert_eq!(memory.num_bytes, 0);
             assert!(memory.address.is_null());
@@ -127,6 +891,388 @@ mod tests {
     #[test]
     fn test_free_block() {
         unsafe {
+            let memory = alloc_madvise_allocate(1024, false, false);
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+            assert_eq!(memory.num_bytes, 1024);
+            assert!(!memory.address.is_null());
+            alloc_madvise_free(memory);
+        }
+    }
+
+    #[test]
+    fn test_allocate_v2_success() {
+        unsafe {
+            let memory = alloc_madvise_allocate_v2(1024, false, true);
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+            assert_eq!(memory.num_bytes, 1024);
+            assert_eq!(memory.capacity, 1024);
+            assert_eq!(memory.backend, Backend::Heap as u32);
+            assert!(memory.alignment.is_power_of_two());
+            assert_eq!(memory.address as usize % memory.alignment, 0);
+            alloc_madvise_free_v2(memory);
+        }
+    }
+
+    #[test]
+    fn test_allocate_v2_failure() {
+        unsafe {
+            let memory = alloc_madvise_allocate_v2(0, false, false);
+            assert_ne!(memory.status, AllocResult::Ok as u32);
+            assert_eq!(memory.num_bytes, 0);
+            assert_eq!(memory.capacity, 0);
+            assert_eq!(memory.alignment, 0);
+            assert!(memory.address.is_null());
+            alloc_madvise_free_v2(memory);
+        }
+    }
+
+    #[test]
+    fn test_v2_persists_alignment() {
+        use crate::options::AllocationOptions;
+        use crate::policy::PageAligned;
+
+        // A page-aligned block would be freed with the wrong layout if the
+        // alignment was recomputed from the number of bytes.
+        let options = AllocationOptions::new().policy(PageAligned);
+        let memory = crate::memory::Memory::allocate_with(1024, &options).unwrap();
+        let memory = ManuallyDrop::new(memory).to_ffi();
+        assert_eq!(memory.alignment, crate::SystemInfo::get().page_size);
+
+        let wrapped = crate::memory::Memory::try_from(memory).unwrap();
+        assert_eq!(wrapped.alignment(), crate::SystemInfo::get().page_size);
+        assert_eq!(wrapped.len(), 1024);
+    }
+
+    #[test]
+    fn test_v2_frees_capacity() {
+        unsafe {
+            let mut memory = alloc_madvise_allocate_v2(4096, false, true);
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+
+            // Shrinking the length must not affect the layout used for freeing.
+            memory.num_bytes = 100;
+            let wrapped = ManuallyDrop::new(crate::memory::Memory::try_from(memory).unwrap());
+            assert_eq!(wrapped.len(), 100);
+            assert_eq!(wrapped.capacity(), 4096);
+            alloc_madvise_free_v2(wrapped.to_ffi());
+        }
+    }
+
+    #[test]
+    fn test_v2_rejects_unknown_backend() {
+        unsafe {
+            let mut memory = alloc_madvise_allocate_v2(4096, false, false);
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+            let backend = std::mem::replace(&mut memory.backend, u32::MAX);
+
+            assert_eq!(
+                alloc_madvise_flush(&memory, 0, 0),
+                AllocResult::OsError as u32
+            );
+            let snapshot = alloc_madvise_snapshot(&memory);
+            assert_eq!(snapshot.status, AllocResult::OsError as u32);
+            assert_eq!(*libc::__errno_location(), libc::EINVAL);
+
+            // Memory with an unknown backend is left alone, so it can still be freed properly.
+            alloc_madvise_free_v2(std::ptr::read(&memory));
+            alloc_madvise_free_v2(MemoryV2 { backend, ..memory });
+        }
+    }
+
+    #[test]
+    fn test_map_file() {
+        let dir = crate::test_support::TempDir::new("ffi_map_file");
+        let path = dir.0.join("file");
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap();
+
+        unsafe {
+            let memory = alloc_madvise_create_file(c_path.as_ptr(), 4096, Advice::Normal as u32);
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+            assert_eq!(memory.backend, Backend::File as u32);
+            assert_eq!(memory.num_bytes, 4096);
+            memory.address.cast::<u8>().write(42);
+            assert_eq!(alloc_madvise_flush(&memory, 0, 1), AllocResult::Ok as u32);
+            alloc_madvise_free_v2(memory);
+
+            let memory = alloc_madvise_map_file(
+                c_path.as_ptr(),
+                MapMode::ReadOnly as u32,
+                Advice::Normal as u32,
+            );
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+            assert_eq!(memory.address.cast::<u8>().read(), 42);
+            alloc_madvise_free_v2(memory);
+        }
+    }
+
+    #[test]
+    fn test_map_file_failure() {
+        unsafe {
+            let memory = alloc_madvise_map_file(
+                CStr::from_bytes_with_nul(b"/nonexistent/alloc-madvise\0")
+                    .unwrap()
+                    .as_ptr(),
+                MapMode::ReadOnly as u32,
+                Advice::Normal as u32,
+            );
+            assert_eq!(memory.status, AllocResult::OsError as u32);
+            assert_eq!(*libc::__errno_location(), libc::ENOENT);
+            assert!(memory.address.is_null());
+
+            let memory = alloc_madvise_map_file(
+                std::ptr::null(),
+                MapMode::ReadOnly as u32,
+                Advice::Normal as u32,
+            );
+            assert_eq!(memory.status, AllocResult::OsError as u32);
+            assert_eq!(
+                alloc_madvise_flush(&memory, 0, 0),
+                AllocResult::Empty as u32
+            );
+
+            // Unknown modes and advices are rejected rather than converted.
+            let path = CStr::from_bytes_with_nul(b"/dev/null\0").unwrap().as_ptr();
+            let memory = alloc_madvise_map_file(path, 3, Advice::Normal as u32);
+            assert_eq!(memory.status, AllocResult::OsError as u32);
+            assert_eq!(*libc::__errno_location(), libc::EINVAL);
+            let memory = alloc_madvise_map_file(path, MapMode::ReadOnly as u32, u32::MAX);
+            assert_eq!(memory.status, AllocResult::OsError as u32);
+            assert_eq!(*libc::__errno_location(), libc::EINVAL);
+            let memory = alloc_madvise_create_file(path, 4096, 4);
+            assert_eq!(memory.status, AllocResult::OsError as u32);
+            assert_eq!(*libc::__errno_location(), libc::EINVAL);
+        }
+    }
+
+    #[test]
+    fn test_allocate_shared() {
+        unsafe {
+            let memory =
+                alloc_madvise_allocate_shared(4096, false, libc::F_SEAL_SHRINK as u32, false);
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+            assert_eq!(memory.backend, Backend::Memfd as u32);
+            assert!(memory.fd >= 0);
+            assert_eq!(
+                libc::fcntl(memory.fd, libc::F_GET_SEALS),
+                libc::F_SEAL_SHRINK
+            );
+            memory.address.cast::<u8>().write(42);
+
+            let other = alloc_madvise_from_fd(memory.fd, 4096, MapMode::ReadOnly as u32);
+            assert_eq!(other.status, AllocResult::Ok as u32);
+            assert!(other.fd >= 0);
+            assert_ne!(other.fd, memory.fd);
+            assert_eq!(other.address.cast::<u8>().read(), 42);
+
+            let invalid = alloc_madvise_from_fd(memory.fd, 4096, 3);
+            assert_eq!(invalid.status, AllocResult::OsError as u32);
+            assert_eq!(*libc::__errno_location(), libc::EINVAL);
+
+            let snapshot = alloc_madvise_snapshot(&memory);
+            assert_eq!(snapshot.status, AllocResult::Ok as u32);
+            assert_eq!(snapshot.fd, -1);
+            memory.address.cast::<u8>().write(43);
+            assert_eq!(snapshot.address.cast::<u8>().read(), 42);
+
+            alloc_madvise_free_v2(memory);
+            alloc_madvise_free_v2(other);
+            alloc_madvise_free_v2(snapshot);
+
+            let empty = alloc_madvise_snapshot(std::ptr::null());
+            assert_eq!(empty.status, AllocResult::Empty as u32);
+
+            let invalid = alloc_madvise_from_fd(-1, 4096, MapMode::ReadOnly as u32);
+            assert_eq!(invalid.status, AllocResult::OsError as u32);
+            assert_eq!(*libc::__errno_location(), libc::EBADF);
+        }
+    }
+
+    #[test]
+    fn test_allocate_with() {
+        let name = std::ffi::CString::new("ffi-block").unwrap();
+        let options = AllocationOptions {
+            clear: 1,
+            wipe_on_fork: 0xff,
+            name: name.as_ptr(),
+            ..AllocationOptions::default()
+        };
+
+        unsafe {
+            let memory = alloc_madvise_allocate_with(100, &options);
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+            assert_eq!(memory.backend, Backend::Anonymous as u32);
+            assert_eq!(memory.num_bytes, 100);
+            assert_ne!(memory.flags & crate::memory::ALLOC_FLAGS_WIPE_ON_FORK, 0);
+            alloc_madvise_free_v2(memory);
+
+            let memory = alloc_madvise_allocate_with(100, std::ptr::null());
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+            assert_eq!(memory.backend, Backend::Heap as u32);
+            alloc_madvise_free_v2(memory);
+
+            // Fields beyond the struct size of the caller are ignored.
+            let truncated = AllocationOptions {
+                struct_size: 4,
+                ..options
+            };
+            let memory = alloc_madvise_allocate_with(100, &truncated);
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+            assert_eq!(memory.backend, Backend::Heap as u32);
+            alloc_madvise_free_v2(memory);
+
+            let invalid = std::ffi::CString::new("[invalid]").unwrap();
+            let options = AllocationOptions {
+                name: invalid.as_ptr(),
+                ..AllocationOptions::default()
+            };
+            let memory = alloc_madvise_allocate_with(100, &options);
+            assert_eq!(memory.status, AllocResult::OsError as u32);
+            assert_eq!(*libc::__errno_location(), libc::EINVAL);
+
+            // A partially covered name is ignored rather than mixed with the default.
+            let name_offset =
+                std::ptr::addr_of!(options.name) as usize - std::ptr::addr_of!(options) as usize;
+            let partial = AllocationOptions {
+                struct_size: name_offset as u32 + 4,
+                ..options
+            };
+            let memory = alloc_madvise_allocate_with(100, &partial);
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+            alloc_madvise_free_v2(memory);
+        }
+    }
+
+    #[test]
+    fn test_allocate_secure() {
+        unsafe {
+            let memory = alloc_madvise_allocate_secure(100, false);
+            assert_eq!(memory.status, AllocResult::Ok as u32);
+            assert_eq!(memory.backend, Backend::Anonymous as u32);
+            assert_eq!(memory.num_bytes, 100);
+            assert_eq!(memory.fd, -1);
+            memory.address.cast::<u8>().write(42);
+            alloc_madvise_free_v2(memory);
+
+            let empty = alloc_madvise_allocate_secure(0, true);
+            assert_eq!(empty.status, AllocResult::Empty as u32);
+        }
+    }
+
+    #[test]
+    fn test_shm() {
+        let name = format!("/alloc-madvise-ffi-{}", std::process::id());
+        let c_name = std::ffi::CString::new(name).unwrap();
+
+        unsafe {
+            let created = alloc_madvise_shm_create(c_name.as_ptr(), 4096, true, 0o600);
+            assert_eq!(created.status, AllocResult::Ok as u32);
+            assert_eq!(created.num_bytes, 4096);
+            created.address.cast::<u8>().write(42);
+
+            let again = alloc_madvise_shm_create(c_name.as_ptr(), 4096, true, 0o600);
+            assert_eq!(again.status, AllocResult::OsError as u32);
+            assert_eq!(*libc::__errno_location(), libc::EEXIST);
+
+            let invalid = alloc_madvise_shm_open(c_name.as_ptr(), u32::MAX);
+            assert_eq!(invalid.status, AllocResult::OsError as u32);
+            assert_eq!(*libc::__errno_location(), libc::EINVAL);
+
+            let opened = alloc_madvise_shm_open(c_name.as_ptr(), MapMode::ReadOnly as u32);
+            assert_eq!(opened.status, AllocResult::Ok as u32);
+            assert_eq!(opened.num_bytes, 4096);
+            assert_eq!(opened.address.cast::<u8>().read(), 42);
+
+            alloc_madvise_free_v2(created);
+            alloc_madvise_free_v2(opened);
+
+            assert_eq!(
+                alloc_madvise_shm_unlink(c_name.as_ptr()),
+                AllocResult::Ok as u32
+            );
+            assert_eq!(
+                alloc_madvise_shm_unlink(c_name.as_ptr()),
+                AllocResult::OsError as u32
+            );
+            assert_eq!(
+                alloc_madvise_shm_unlink(std::ptr::null()),
+                AllocResult::OsError as u32
+            );
+        }
+    }
+
+    #[test]
+    fn test_capabilities() {
+        unsafe {
+            let mut capabilities: Capabilities = std::mem::zeroed();
+            capabilities.struct_size = size_of::<Capabilities>() as u32;
+            assert!(alloc_madvise_capabilities(&mut capabilities));
+
+            assert_eq!(capabilities.struct_size as usize, size_of::<Capabilities>());
+            assert_eq!(
+                capabilities.version_major.to_string(),
+                env!("CARGO_PKG_VERSION_MAJOR")
+            );
+            assert_ne!(capabilities.features & crate::capabilities::FEATURE_FFI, 0);
+            assert!(capabilities.page_size.is_power_of_two());
+            assert!(capabilities.numa_nodes >= 1);
+            assert!(capabilities.num_huge_page_sizes as usize <= MAX_HUGE_PAGE_SIZES);
+        }
+    }
+
+    #[test]
+    fn test_capabilities_partial() {
+        unsafe {
+            let mut capabilities: Capabilities = std::mem::zeroed();
+            capabilities.struct_size = (4 * size_of::<u32>()) as u32;
+            assert!(alloc_madvise_capabilities(&mut capabilities));
+
+            // Only the version is written for older (smaller) struct versions.
+            assert_eq!(capabilities.struct_size as usize, 4 * size_of::<u32>());
+            assert_eq!(
+                capabilities.version_major.to_string(),
+                env!("CARGO_PKG_VERSION_MAJOR")
+            );
+            assert_eq!(capabilities.features, 0);
+            assert_eq!(capabilities.page_size, 0);
+        }
+    }
+
+    #[test]
+    fn test_capabilities_invalid() {
+        unsafe {
+            assert!(!alloc_madvise_capabilities(null_mut()));
+
+            let mut capabilities: Capabilities = std::mem::zeroed();
+            assert!(!alloc_madvise_capabilities(&mut capabilities));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn test_get_stats() {
+        unsafe {
+            let memory = alloc_madvise_allocate_v2(4096, true, false);
+            let mut stats: AllocationStats = std::mem::zeroed();
+            stats.struct_size = size_of::<AllocationStats>() as u32;
+            assert!(alloc_madvise_get_stats(&mut stats));
+            assert_eq!(stats.struct_size as usize, size_of::<AllocationStats>());
+            assert!(stats.total.live_blocks >= 1);
+            assert!(stats.sequential.current_bytes >= 4096);
+            alloc_madvise_free_v2(memory);
+
+            assert!(!alloc_madvise_get_stats(null_mut()));
+            let mut stats: AllocationStats = std::mem::zeroed();
+            assert!(!alloc_madvise_get_stats(&mut stats));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "legacy-symbols")]
+    #[allow(deprecated)]
+    fn test_legacy_symbols() {
+        unsafe {
+            assert_eq!(version(), alloc_madvise_version());
+
             let memory = allocate_block(1024, false, false);
             assert_eq!(memory.status, AllocResult::Ok as u32);
             assert_eq!(memory.num_bytes, 1024);
@@ -0,0 +1,177 @@
+//! Provides fork-safety controls for [`Memory`].
+//!
+//! By default, `fork(2)` duplicates all mappings of the parent into the child. For large
+//! allocations this makes forking slow, since the page tables are copied, and it exposes
+//! the contents of the memory to the child. Memory can therefore be excluded from the child
+//! (`MADV_DONTFORK`), or be provided to it zeroed out (`MADV_WIPEONFORK`).
+//!
+//! These advices apply to whole pages. Since heap memory shares its pages with other
+//! allocations, they can only be applied to page-granular memory, which is obtained by
+//! setting [`AllocationOptions::dont_fork`](crate::AllocationOptions::dont_fork) or
+//! [`AllocationOptions::wipe_on_fork`](crate::AllocationOptions::wipe_on_fork).
+
+use crate::alloc_result::AllocationError;
+use crate::backend::Backend;
+use crate::memory::{Memory, ALLOC_FLAGS_DONT_FORK, ALLOC_FLAGS_WIPE_ON_FORK};
+use crate::trace;
+
+impl Memory {
+    /// Sets whether the memory is excluded from child processes created using `fork`
+    /// (`MADV_DONTFORK`), or inherited by them (`MADV_DOFORK`).
+    ///
+    /// Accessing the memory in the child process raises `SIGSEGV`.
+    ///
+    /// ## Errors
+    /// Fails with `EINVAL` for heap memory, whose pages are shared with other allocations.
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::{AllocationOptions, Memory};
+    /// let options = AllocationOptions::new().dont_fork(true);
+    /// let mut memory = Memory::allocate_with(1024, &options).unwrap();
+    /// assert!(memory.is_dont_fork());
+    ///
+    /// memory.set_dont_fork(false).unwrap();
+    /// assert!(!memory.is_dont_fork());
+    /// ```
+    pub fn set_dont_fork(&mut self, dont_fork: bool) -> Result<(), AllocationError> {
+        let advice = if dont_fork {
+            libc::MADV_DONTFORK
+        } else {
+            libc::MADV_DOFORK
+        };
+        self.advise_fork(advice, ALLOC_FLAGS_DONT_FORK, dont_fork)
+    }
+
+    /// Sets whether the memory is zeroed out in child processes created using `fork`
+    /// (`MADV_WIPEONFORK`), or inherited with its contents (`MADV_KEEPONFORK`).
+    ///
+    /// ## Errors
+    /// Fails with `EINVAL` for heap memory, whose pages are shared with other allocations,
+    /// as well as for shared memory and file mappings, which cannot be wiped.
+    pub fn set_wipe_on_fork(&mut self, wipe_on_fork: bool) -> Result<(), AllocationError> {
+        let advice = if wipe_on_fork {
+            libc::MADV_WIPEONFORK
+        } else {
+            libc::MADV_KEEPONFORK
+        };
+        self.advise_fork(advice, ALLOC_FLAGS_WIPE_ON_FORK, wipe_on_fork)
+    }
+
+    /// Returns whether the memory is excluded from child processes created using `fork`.
+    #[inline(always)]
+    pub fn is_dont_fork(&self) -> bool {
+        (self.flags & ALLOC_FLAGS_DONT_FORK) == ALLOC_FLAGS_DONT_FORK
+    }
+
+    /// Returns whether the memory is zeroed out in child processes created using `fork`.
+    #[inline(always)]
+    pub fn is_wipe_on_fork(&self) -> bool {
+        (self.flags & ALLOC_FLAGS_WIPE_ON_FORK) == ALLOC_FLAGS_WIPE_ON_FORK
+    }
+
+    fn advise_fork(
+        &mut self,
+        advice: libc::c_int,
+        flag: u32,
+        enabled: bool,
+    ) -> Result<(), AllocationError> {
+        if self.address.is_null() {
+            return Err(AllocationError::EmptyAllocation);
+        }
+        if self.backend == Backend::Heap {
+            return Err(AllocationError::OsError(libc::EINVAL));
+        }
+
+        // SAFETY: Mappings are page-aligned and span their capacity.
+        if unsafe { trace::madvise(self.address, self.capacity, advice) } != 0 {
+            return Err(AllocationError::last_os_error());
+        }
+
+        if enabled {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::AllocationOptions;
+
+    /// Forks and reads the first byte of `memory` in the child.
+    ///
+    /// ## Returns
+    /// The byte read by the child, or `None` if the child was killed by `SIGSEGV`.
+    fn read_in_child(memory: &Memory) -> Option<u8> {
+        let ptr = memory.to_ptr_const().cast::<u8>();
+        unsafe {
+            let pid = libc::fork();
+            assert!(pid >= 0, "fork failed");
+            if pid == 0 {
+                libc::_exit(ptr.read_volatile() as libc::c_int);
+            }
+
+            let mut status = 0;
+            assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+            if libc::WIFSIGNALED(status) {
+                assert_eq!(libc::WTERMSIG(status), libc::SIGSEGV);
+                return None;
+            }
+            assert!(libc::WIFEXITED(status));
+            Some(libc::WEXITSTATUS(status) as u8)
+        }
+    }
+
+    fn write_first_byte(memory: &mut Memory, value: u8) {
+        let data: &mut [u8] = memory.as_mut();
+        data[0] = value;
+    }
+
+    #[test]
+    fn child_inherits_memory_by_default() {
+        let options = AllocationOptions::new().dont_fork(true);
+        let mut memory = Memory::allocate_with(1024, &options).unwrap();
+        assert_eq!(memory.backend(), Backend::Anonymous);
+        memory.set_dont_fork(false).unwrap();
+        write_first_byte(&mut memory, 42);
+
+        assert_eq!(read_in_child(&memory), Some(42));
+    }
+
+    #[test]
+    fn child_does_not_see_dont_fork_memory() {
+        let options = AllocationOptions::new().dont_fork(true);
+        let mut memory = Memory::allocate_with(1024, &options).unwrap();
+        assert!(memory.is_dont_fork());
+        write_first_byte(&mut memory, 42);
+
+        assert_eq!(read_in_child(&memory), None);
+    }
+
+    #[test]
+    fn child_sees_wiped_memory() {
+        let options = AllocationOptions::new().wipe_on_fork(true);
+        let mut memory = Memory::allocate_with(1024, &options).unwrap();
+        assert!(memory.is_wipe_on_fork());
+        write_first_byte(&mut memory, 42);
+        assert_eq!(read_in_child(&memory), Some(0));
+
+        memory.set_wipe_on_fork(false).unwrap();
+        assert!(!memory.is_wipe_on_fork());
+        assert_eq!(read_in_child(&memory), Some(42));
+    }
+
+    #[test]
+    fn heap_memory_is_rejected() {
+        let mut memory = Memory::allocate(1024, false, false).unwrap();
+        assert_eq!(
+            memory.set_dont_fork(true),
+            Err(AllocationError::OsError(libc::EINVAL))
+        );
+        assert!(!memory.is_dont_fork());
+    }
+}
@@ -0,0 +1,148 @@
+//! Provides process-wide allocation hooks, e.g. for enforcing memory budgets.
+//!
+//! A hook set using [`set_alloc_hook`] is invoked whenever memory is obtained or released
+//! through this crate, including allocations made through FFI. It can reject allocations,
+//! which then fail with [`AllocationError::Rejected`].
+//!
+//! The hook is invoked once the memory was obtained, such that the event describes the
+//! actual capacity, alignment and backend. Rejected memory is released again right away,
+//! without a [`AllocEventKind::Free`] event. Every accepted allocation is therefore matched
+//! by exactly one release event.
+//!
+//! # Functions
+//! - [`set_alloc_hook`]: Sets the hook.
+//! - [`clear_alloc_hook`]: Removes the hook.
+//!
+//! # Structs
+//! - [`AllocEvent`]: Describes memory being obtained or released.
+//!
+//! # Enums
+//! - [`AllocEventKind`]: Whether memory is being obtained or released.
+//! - [`HookDecision`]: Whether to accept an allocation.
+
+use crate::alloc_result::AllocationError;
+use crate::backend::Backend;
+use crate::memory::Memory;
+use std::sync::{Arc, RwLock};
+
+type SharedHook = Arc<dyn Fn(&AllocEvent) -> HookDecision + Send + Sync>;
+
+static ALLOC_HOOK: RwLock<Option<SharedHook>> = RwLock::new(None);
+
+/// Whether memory is being obtained or released.
+#[repr(u32)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum AllocEventKind {
+    /// Memory was obtained and is about to be returned to the caller.
+    Allocate = 0,
+    /// Memory is about to be released.
+    Free = 1,
+}
+
+/// Whether to accept an allocation. The decision is ignored for releases.
+#[repr(u32)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum HookDecision {
+    /// The allocation succeeds.
+    Allow = 0,
+    /// The memory is released and the allocation fails with [`AllocationError::Rejected`].
+    Reject = 1,
+}
+
+/// Describes memory being obtained or released.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct AllocEvent {
+    /// Whether the memory is being obtained or released.
+    pub kind: AllocEventKind,
+    /// The address of the memory.
+    pub address: usize,
+    /// The number of usable bytes, see [`Memory::len`].
+    pub num_bytes: usize,
+    /// The number of bytes reserved, see [`Memory::capacity`].
+    pub capacity: usize,
+    /// The alignment of the memory, see [`Memory::alignment`].
+    pub alignment: usize,
+    /// The mechanism the memory was obtained from.
+    pub backend: Backend,
+}
+
+impl AllocEvent {
+    fn new(kind: AllocEventKind, memory: &Memory) -> Self {
+        Self {
+            kind,
+            address: memory.address as usize,
+            num_bytes: memory.num_bytes,
+            capacity: memory.capacity,
+            alignment: memory.alignment,
+            backend: memory.backend,
+        }
+    }
+}
+
+/// Sets the hook invoked whenever memory is obtained or released, replacing any previous hook.
+///
+/// The hook may be invoked concurrently from any thread. Memory allocated by the hook
+/// itself is reported to it as well.
+///
+/// ## Example
+/// ```
+/// use alloc_madvise::{clear_alloc_hook, set_alloc_hook, AllocEventKind, AllocationError, HookDecision, Memory};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static USED: AtomicUsize = AtomicUsize::new(0);
+/// const BUDGET: usize = 1 << 20;
+///
+/// set_alloc_hook(|event| match event.kind {
+///     AllocEventKind::Allocate => {
+///         if USED.fetch_add(event.capacity, Ordering::Relaxed) + event.capacity > BUDGET {
+///             USED.fetch_sub(event.capacity, Ordering::Relaxed);
+///             return HookDecision::Reject;
+///         }
+///         HookDecision::Allow
+///     }
+///     AllocEventKind::Free => {
+///         USED.fetch_sub(event.capacity, Ordering::Relaxed);
+///         HookDecision::Allow
+///     }
+/// });
+///
+/// let memory = Memory::allocate(1024, false, false).unwrap();
+/// assert_eq!(
+///     Memory::allocate(2 * BUDGET, false, false).unwrap_err(),
+///     AllocationError::Rejected
+/// );
+/// clear_alloc_hook();
+/// ```
+pub fn set_alloc_hook<F>(hook: F)
+where
+    F: Fn(&AllocEvent) -> HookDecision + Send + Sync + 'static,
+{
+    *ALLOC_HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(hook));
+}
+
+/// Removes the hook set using [`set_alloc_hook`].
+pub fn clear_alloc_hook() {
+    *ALLOC_HOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Invokes the hook, if any. The lock is not held while it runs, so it may change the hook.
+fn invoke(event: &AllocEvent) -> HookDecision {
+    let hook = ALLOC_HOOK.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match hook {
+        Some(hook) => hook(event),
+        None => HookDecision::Allow,
+    }
+}
+
+/// Asks the hook whether memory that was just obtained may be handed out.
+pub(crate) fn admit(memory: &Memory) -> Result<(), AllocationError> {
+    match invoke(&AllocEvent::new(AllocEventKind::Allocate, memory)) {
+        HookDecision::Allow => Ok(()),
+        HookDecision::Reject => Err(AllocationError::Rejected),
+    }
+}
+
+/// Notifies the hook that memory is about to be released.
+pub(crate) fn release(memory: &Memory) {
+    invoke(&AllocEvent::new(AllocEventKind::Free, memory));
+}
@@ -0,0 +1,224 @@
+//! Provides Kernel Samepage Merging (KSM) controls for [`Memory`], and [`KsmStatus`].
+//!
+//! Memory advised with `MADV_MERGEABLE` is scanned by the `ksmd` kernel thread, which
+//! merges pages of identical content into a single copy-on-write page. This saves memory
+//! when many processes hold the same data, e.g. replicas of the same model weights.
+//! Merging only takes place while KSM is running on the host, see [`KsmStatus::read`].
+//!
+//! KSM only applies to private anonymous memory, which is obtained by setting
+//! [`AllocationOptions::mergeable`](crate::AllocationOptions::mergeable).
+//!
+//! # Structs
+//! - [`KsmStatus`]: The state and statistics of KSM on the host.
+//!
+//! # Enums
+//! - [`KsmRun`]: The run mode of `ksmd`.
+
+use crate::alloc_result::AllocationError;
+use crate::backend::Backend;
+use crate::memory::{Memory, ALLOC_FLAGS_MERGEABLE};
+use crate::system_info::{read_usize, SystemInfo};
+use crate::trace;
+use std::path::Path;
+
+const KSM: &str = "/sys/kernel/mm/ksm";
+
+/// The run mode of the `ksmd` kernel thread.
+///
+/// See `/sys/kernel/mm/ksm/run`.
+#[repr(u32)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum KsmRun {
+    /// Merging is stopped, but merged pages are kept.
+    Stopped = 0,
+    /// Mergeable memory is scanned and merged.
+    Running = 1,
+    /// Merging is stopped and all merged pages are being unmerged.
+    Unmerging = 2,
+}
+
+/// The state and statistics of Kernel Samepage Merging on the host.
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::KsmStatus;
+/// if let Some(status) = KsmStatus::read() {
+///     println!("KSM {:?}, saving {} bytes", status.run, status.saved_bytes());
+/// }
+/// ```
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct KsmStatus {
+    /// The run mode of `ksmd`.
+    pub run: KsmRun,
+    /// The number of shared pages in use.
+    pub pages_shared: usize,
+    /// The number of additional sites sharing them, i.e. the number of pages saved.
+    pub pages_sharing: usize,
+    /// The number of pages that are unique but repeatedly checked for merging.
+    pub pages_unshared: usize,
+    /// The number of pages changing too fast to be merged.
+    pub pages_volatile: usize,
+    /// The number of times all mergeable memory was scanned.
+    pub full_scans: usize,
+}
+
+impl KsmStatus {
+    /// Reads the status from `/sys/kernel/mm/ksm`.
+    ///
+    /// Returns `None` if the kernel was built without KSM.
+    pub fn read() -> Option<Self> {
+        Self::read_from(Path::new(KSM))
+    }
+
+    fn read_from(path: &Path) -> Option<Self> {
+        let run = match read_usize(&path.join("run"))? {
+            0 => KsmRun::Stopped,
+            1 => KsmRun::Running,
+            _ => KsmRun::Unmerging,
+        };
+        let counter = |name: &str| read_usize(&path.join(name)).unwrap_or(0);
+        Some(Self {
+            run,
+            pages_shared: counter("pages_shared"),
+            pages_sharing: counter("pages_sharing"),
+            pages_unshared: counter("pages_unshared"),
+            pages_volatile: counter("pages_volatile"),
+            full_scans: counter("full_scans"),
+        })
+    }
+
+    /// Returns whether mergeable memory is currently being merged.
+    pub fn is_active(&self) -> bool {
+        self.run == KsmRun::Running
+    }
+
+    /// Returns the approximate number of bytes saved by merging.
+    pub fn saved_bytes(&self) -> usize {
+        self.pages_sharing
+            .saturating_mul(SystemInfo::get().page_size)
+    }
+}
+
+impl Memory {
+    /// Sets whether the memory may be merged with identical pages (`MADV_MERGEABLE`),
+    /// or unmerges it again (`MADV_UNMERGEABLE`).
+    ///
+    /// ## Errors
+    /// Fails with `EINVAL` for heap memory, whose pages are shared with other allocations,
+    /// as well as for shared memory and file mappings. Fails with `EINVAL` if the kernel
+    /// was built without KSM, see [`MadviseSupport::mergeable`](crate::MadviseSupport::mergeable).
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::{AllocationOptions, Capabilities, Memory};
+    /// let options = AllocationOptions::new().page_aligned(true);
+    /// let mut memory = Memory::allocate_with(1024, &options).unwrap();
+    ///
+    /// if Capabilities::detect().madvise.mergeable {
+    ///     memory.set_mergeable(true).unwrap();
+    ///     assert!(memory.is_mergeable());
+    /// }
+    /// ```
+    pub fn set_mergeable(&mut self, mergeable: bool) -> Result<(), AllocationError> {
+        if self.address.is_null() {
+            return Err(AllocationError::EmptyAllocation);
+        }
+        if self.backend != Backend::Anonymous {
+            return Err(AllocationError::OsError(libc::EINVAL));
+        }
+
+        let advice = if mergeable {
+            libc::MADV_MERGEABLE
+        } else {
+            libc::MADV_UNMERGEABLE
+        };
+        // SAFETY: Anonymous mappings are page-aligned and span their capacity.
+        if unsafe { trace::madvise(self.address, self.capacity, advice) } != 0 {
+            return Err(AllocationError::last_os_error());
+        }
+
+        if mergeable {
+            self.flags |= ALLOC_FLAGS_MERGEABLE;
+        } else {
+            self.flags &= !ALLOC_FLAGS_MERGEABLE;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the memory may be merged with identical pages.
+    #[inline(always)]
+    pub fn is_mergeable(&self) -> bool {
+        (self.flags & ALLOC_FLAGS_MERGEABLE) == ALLOC_FLAGS_MERGEABLE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capabilities::Capabilities;
+    use crate::options::AllocationOptions;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn read_status() {
+        let dir = TempDir::new("read_status");
+        for (name, value) in [
+            ("run", "1\n"),
+            ("pages_shared", "10\n"),
+            ("pages_sharing", "25\n"),
+            ("pages_unshared", "3\n"),
+            ("full_scans", "7\n"),
+        ] {
+            std::fs::write(dir.0.join(name), value).unwrap();
+        }
+
+        let status = KsmStatus::read_from(&dir.0).unwrap();
+        assert!(status.is_active());
+        assert_eq!(status.pages_shared, 10);
+        assert_eq!(status.pages_sharing, 25);
+        assert_eq!(status.pages_unshared, 3);
+        assert_eq!(status.pages_volatile, 0);
+        assert_eq!(status.full_scans, 7);
+        assert_eq!(status.saved_bytes(), 25 * SystemInfo::get().page_size);
+    }
+
+    #[test]
+    fn read_status_without_ksm() {
+        let dir = TempDir::new("read_status_without_ksm");
+        assert_eq!(KsmStatus::read_from(&dir.0), None);
+    }
+
+    #[test]
+    fn read_host_status() {
+        let status = KsmStatus::read();
+        assert_eq!(status.is_some(), Path::new(KSM).exists());
+    }
+
+    #[test]
+    fn mergeable_option_advises_memory() {
+        let options = AllocationOptions::new().mergeable(true);
+        let result = Memory::allocate_with(8192, &options);
+        if !Capabilities::detect().madvise.mergeable {
+            assert_eq!(
+                result.expect_err("KSM is unsupported"),
+                AllocationError::OsError(libc::EINVAL)
+            );
+            return;
+        }
+
+        let mut memory = result.unwrap();
+        assert_eq!(memory.backend(), Backend::Anonymous);
+        assert!(memory.is_mergeable());
+        memory.set_mergeable(false).unwrap();
+        assert!(!memory.is_mergeable());
+    }
+
+    #[test]
+    fn heap_memory_is_rejected() {
+        let mut memory = Memory::allocate(1024, false, false).unwrap();
+        assert_eq!(
+            memory.set_mergeable(true),
+            Err(AllocationError::OsError(libc::EINVAL))
+        );
+    }
+}
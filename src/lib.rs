@@ -7,6 +7,23 @@
 //!
 //! - [`Memory`] - A safe wrapper around aligned memory allocations
 //! - [`AllocationError`] - Error type for memory allocation failures
+//! - [`AllocationOptions`] - Options controlling an allocation
+//! - [`Memory::map_file`] - Maps a file into a [`Memory`] instance; see [`MapMode`] and [`Advice`]
+//! - [`Memory::allocate_shared`] - Allocates memory that can be shared with other processes; see [`MemfdOptions`]
+//! - [`SharedMemory`] - A named POSIX shared memory segment; see [`ShmOptions`]
+//! - [`Memory::allocate_secure`] - Allocates locked memory for sensitive data that is zeroized when freed; see [`SecureOptions`]
+//! - [`Memory::protect`] - Changes the protection of memory; see [`Protection`] and [`FrozenMemory`]
+//! - [`Memory::set_mergeable`] - Opts memory into Kernel Samepage Merging; see [`KsmStatus`]
+//! - [`Memory::residency`] and [`Memory::huge_page_coverage`] - Report how memory is backed by physical pages
+//! - [`Memory::set_name`] - Names anonymous memory for profiling; see [`AllocationOptions::name`]
+//! - [`MirroredMemory`] - Memory mapped twice back-to-back, and the [`RingBuffer`] built on it
+//! - [`Memory::allocate_in`] - Charges allocations against a [`Budget`], e.g. derived from the cgroup memory limit
+//! - [`Memory::can_allocate`] - Checks whether the host can back an allocation before making it; see [`Preflight`]
+//! - [`MemoryPool`] - Caches freed blocks by size class for reuse; see [`PoolOptions`] and [`PooledMemory`]
+//! - [`set_alloc_hook`] - Observes and vetoes allocations, e.g. for enforcing memory budgets; see [`AllocEvent`]
+//! - [`AlignmentPolicy`] - Decides the alignment of an allocation; see [`set_global_policy`]
+//! - [`Capabilities`] - The features of the library and the memory management capabilities of the host
+//! - [`SystemInfo`] - The memory page configuration of the host
 //!
 //! # Example
 //!
@@ -34,6 +51,11 @@
 //! # Features
 //!
 //! - `ffi`: Enables FFI bindings for C interoperability (disabled by default)
+//! - `legacy-symbols`: Additionally exports the deprecated unprefixed FFI symbols
+//!   `version`, `allocate_block` and `free_block` (enabled by default)
+//! - `stats`: Collects global allocation statistics, see `stats::snapshot` (disabled by default)
+//! - `tracing`: Emits [`tracing`](https://docs.rs/tracing) spans and events for allocations,
+//!   `madvise` calls and releases with the `alloc_madvise` target (disabled by default)
 #![allow(unsafe_code)]
 
 #[cfg(feature = "ffi")]
@@ -42,7 +64,58 @@ mod ffi;
 mod alignment;
 mod alloc_free;
 mod alloc_result;
+mod backend;
+mod budget;
+mod capabilities;
+mod cgroup;
+mod fork;
+mod hooks;
+mod ksm;
+mod memfd;
 mod memory;
+mod mirrored;
+mod mmap;
+mod options;
+mod policy;
+mod pool;
+mod preflight;
+mod protect;
+mod residency;
+mod ring_buffer;
+mod secure;
+mod shm;
+#[cfg(feature = "stats")]
+pub mod stats;
+/// cbindgen:ignore
+#[cfg(not(feature = "stats"))]
+mod stats;
+mod system_info;
+#[cfg(test)]
+mod test_support;
+mod trace;
+mod vma_name;
 
+pub use alignment::AlignmentHint;
 pub use alloc_result::AllocationError;
+pub use backend::Backend;
+pub use budget::Budget;
+pub use capabilities::{Capabilities, MadviseSupport};
+pub use hooks::{clear_alloc_hook, set_alloc_hook, AllocEvent, AllocEventKind, HookDecision};
+pub use ksm::{KsmRun, KsmStatus};
+pub use memfd::MemfdOptions;
 pub use memory::Memory;
+pub use mirrored::MirroredMemory;
+pub use mmap::{Advice, MapMode};
+pub use options::AllocationOptions;
+pub use policy::{
+    reset_global_policy, set_global_policy, AlignmentPolicy, CacheLineAligned, DefaultPolicy,
+    DirectIoAligned, HugePageThreshold, PageAligned,
+};
+pub use pool::{MemoryPool, PoolOptions, PooledMemory};
+pub use preflight::{Overcommit, Preflight, PreflightVerdict};
+pub use protect::{FrozenMemory, Protection};
+pub use residency::{HugePageCoverage, Residency};
+pub use ring_buffer::{Consumer, Producer, RingBuffer};
+pub use secure::SecureOptions;
+pub use shm::{SharedMemory, ShmOptions};
+pub use system_info::{HugeTlbPool, SystemInfo, ThpDefrag, ThpMode};
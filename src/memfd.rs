@@ -0,0 +1,380 @@
+//! Provides [`Memory`] backed by an anonymous `memfd`, which can be shared with other processes.
+//!
+//! The file descriptor of the memory is available through [`Memory::fd`] and can be inherited
+//! by child processes or passed over a Unix domain socket. The receiving side maps it using
+//! [`Memory::from_fd`], which aligns the mapping by the same rule as the sending side.
+//!
+//! # Structs
+//! - [`MemfdOptions`]: Options for creating the `memfd`, such as `hugetlbfs` backing and seals.
+
+use crate::alloc_result::AllocationError;
+use crate::backend::Backend;
+use crate::memory::Memory;
+use crate::mmap::{self, Advice, MapMode};
+use crate::stats;
+use crate::trace;
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+
+/// The name of the `memfd` if none is specified, visible in `/proc/<pid>/fd`.
+const DEFAULT_NAME: &str = "alloc-madvise";
+
+/// Options for allocating shared memory using [`Memory::allocate_shared`].
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::{MemfdOptions, Memory};
+/// let options = MemfdOptions::new().name("worker-buffer").seal_shrink(true);
+/// let memory = Memory::allocate_shared(1024, &options).unwrap();
+/// assert!(memory.fd().is_some());
+/// ```
+#[derive(Debug, Clone)]
+pub struct MemfdOptions {
+    pub(crate) name: String,
+    pub(crate) huge_tlb: bool,
+    pub(crate) seals: libc::c_int,
+    pub(crate) inheritable: bool,
+    pub(crate) advice: Advice,
+}
+
+impl Default for MemfdOptions {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_NAME.to_string(),
+            huge_tlb: false,
+            seals: 0,
+            inheritable: false,
+            advice: Advice::Normal,
+        }
+    }
+}
+
+impl MemfdOptions {
+    /// Creates options for an unsealed, non-inheritable `memfd` using regular pages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the name of the `memfd`, which is shown in `/proc/<pid>/fd` for debugging purposes.
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Sets whether the memory is backed by the default `hugetlbfs` pool (`MFD_HUGETLB`).
+    ///
+    /// The capacity is rounded up to a multiple of the huge page size. Allocation fails
+    /// if the pool does not hold enough free pages; see [`SystemInfo::huge_tlb_pools`](crate::SystemInfo::huge_tlb_pools).
+    pub fn huge_tlb(mut self, huge_tlb: bool) -> Self {
+        self.huge_tlb = huge_tlb;
+        self
+    }
+
+    /// Sets whether the size of the `memfd` can no longer be reduced (`F_SEAL_SHRINK`).
+    ///
+    /// This guarantees the receiving side of the descriptor that accessing the mapped
+    /// memory never raises `SIGBUS`.
+    pub fn seal_shrink(self, seal: bool) -> Self {
+        self.seal(libc::F_SEAL_SHRINK, seal)
+    }
+
+    /// Sets whether the size of the `memfd` can no longer be increased (`F_SEAL_GROW`).
+    pub fn seal_grow(self, seal: bool) -> Self {
+        self.seal(libc::F_SEAL_GROW, seal)
+    }
+
+    /// Sets whether the descriptor is inherited by child processes created using `exec`,
+    /// i.e. whether `MFD_CLOEXEC` is omitted.
+    pub fn inheritable(mut self, inheritable: bool) -> Self {
+        self.inheritable = inheritable;
+        self
+    }
+
+    /// Sets the expected access pattern.
+    pub fn advice(mut self, advice: Advice) -> Self {
+        self.advice = advice;
+        self
+    }
+
+    fn seal(mut self, seal: libc::c_int, enabled: bool) -> Self {
+        if enabled {
+            self.seals |= seal;
+        } else {
+            self.seals &= !seal;
+        }
+        self
+    }
+}
+
+impl Memory {
+    /// Allocates memory of the specified number of bytes backed by an anonymous `memfd`,
+    /// which can be shared with other processes through its [file descriptor](Memory::fd).
+    ///
+    /// The memory is zeroed out and aligned as decided by the global alignment policy,
+    /// but at least to the (huge) page size. The descriptor is closed when the memory is freed;
+    /// mappings in other processes remain valid.
+    ///
+    /// Unless the `memfd` is sealed using [`MemfdOptions::seal_shrink`], processes the
+    /// descriptor is shared with are required not to truncate it.
+    ///
+    /// ## Arguments
+    /// * `num_bytes` - The number of bytes to allocate.
+    /// * `options` - The options for creating the `memfd`.
+    pub fn allocate_shared(
+        num_bytes: usize,
+        options: &MemfdOptions,
+    ) -> Result<Self, AllocationError> {
+        let operation = trace::Operation::allocate("allocate_shared", num_bytes);
+        let result = Self::allocate_shared_unrecorded(num_bytes, options);
+        operation.allocated(&result);
+        stats::record_result(result)
+    }
+
+    fn allocate_shared_unrecorded(
+        num_bytes: usize,
+        options: &MemfdOptions,
+    ) -> Result<Self, AllocationError> {
+        if num_bytes == 0 {
+            return Err(AllocationError::EmptyAllocation);
+        }
+
+        let fd = open_memfd(options)?;
+        let size = mmap::round_up(num_bytes, mmap::mapping_granularity(fd.as_raw_fd())?);
+        resize_and_seal(&fd, size, options.seals)?;
+
+        // SAFETY: The file was sized above and is sealed against shrinking or owned exclusively.
+        let mut memory = unsafe {
+            Self::map_fd(
+                fd.as_raw_fd(),
+                num_bytes,
+                MapMode::ReadWrite,
+                options.advice,
+                Backend::Memfd,
+            )?
+        };
+        memory.fd = fd.into_raw_fd();
+        Ok(memory)
+    }
+
+    /// Maps shared memory received from another process, e.g. one allocated using
+    /// [`Memory::allocate_shared`].
+    ///
+    /// The mapping is aligned by the same rule as [`Memory::allocate_shared`]: to the alignment
+    /// the global policy of this process chooses for the mapped size, but at least to the (huge)
+    /// page size of the file. It therefore matches the alignment of the sending side as long as
+    /// both processes use the same policy. The descriptor is duplicated, so the original can be
+    /// closed after mapping.
+    ///
+    /// Descriptors of a `memfd` are mapped as [`Backend::Memfd`], other files as [`Backend::File`].
+    ///
+    /// ## Arguments
+    /// * `fd` - The file descriptor to map.
+    /// * `num_bytes` - The number of bytes to map, at most the size of the file.
+    /// * `mode` - Whether to map the memory read-only, read-write or copy-on-write.
+    ///
+    /// ## Errors
+    /// Returns [`AllocationError::OsError`] with `EINVAL` if the file is smaller than `num_bytes`.
+    ///
+    /// ## Safety
+    /// The file is required not to be truncated while it is mapped, e.g. because it is
+    /// sealed with `F_SEAL_SHRINK`. The memory may be modified by other processes at any time.
+    pub unsafe fn from_fd(
+        fd: BorrowedFd<'_>,
+        num_bytes: usize,
+        mode: MapMode,
+    ) -> Result<Self, AllocationError> {
+        // SAFETY: `stat` is plain old data, and is only read if `fstat` succeeds.
+        let mut stat: libc::stat = std::mem::zeroed();
+        if libc::fstat(fd.as_raw_fd(), &mut stat) != 0 {
+            return Err(AllocationError::last_os_error());
+        }
+        if usize::try_from(stat.st_size).map_or(true, |size| size < num_bytes) {
+            return Err(AllocationError::OsError(libc::EINVAL));
+        }
+
+        let fd = fd.try_clone_to_owned()?;
+        let backend = if is_memfd(fd.as_raw_fd()) {
+            Backend::Memfd
+        } else {
+            Backend::File
+        };
+        let mut memory = Self::map_fd(fd.as_raw_fd(), num_bytes, mode, Advice::Normal, backend)?;
+        memory.fd = fd.into_raw_fd();
+        Ok(memory)
+    }
+}
+
+/// Gets whether the descriptor refers to a `memfd`, whose path is shown as `/memfd:<name>`.
+fn is_memfd(fd: RawFd) -> bool {
+    std::fs::read_link(format!("/proc/self/fd/{fd}"))
+        .is_ok_and(|path| path.as_os_str().as_bytes().starts_with(b"/memfd:"))
+}
+
+/// Creates an empty anonymous `memfd` as configured by the options.
+pub(crate) fn open_memfd(options: &MemfdOptions) -> Result<OwnedFd, AllocationError> {
+    let name =
+        CString::new(options.name.as_str()).map_err(|_| AllocationError::OsError(libc::EINVAL))?;
+
+    let mut flags = libc::MFD_ALLOW_SEALING;
+    if !options.inheritable {
+        flags |= libc::MFD_CLOEXEC;
+    }
+    if options.huge_tlb {
+        flags |= libc::MFD_HUGETLB;
+    }
+
+    // SAFETY: `name` is a valid C string.
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), flags) };
+    if fd < 0 {
+        return Err(AllocationError::last_os_error());
+    }
+
+    // SAFETY: The descriptor was just created and is owned by nobody else.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Sets the size of a `memfd` and applies the `F_SEAL_*` seals.
+pub(crate) fn resize_and_seal(
+    fd: &OwnedFd,
+    num_bytes: usize,
+    seals: libc::c_int,
+) -> Result<(), AllocationError> {
+    let size =
+        libc::off_t::try_from(num_bytes).map_err(|_| AllocationError::OsError(libc::EFBIG))?;
+
+    // SAFETY: The descriptor is valid.
+    if unsafe { libc::ftruncate(fd.as_raw_fd(), size) } != 0 {
+        return Err(AllocationError::last_os_error());
+    }
+
+    // SAFETY: The descriptor is valid.
+    if seals != 0 && unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_ADD_SEALS, seals) } != 0 {
+        return Err(AllocationError::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system_info::SystemInfo;
+    use crate::test_support::TempDir;
+    use std::os::fd::AsFd;
+
+    #[test]
+    fn shared_memory_is_visible_through_fd() {
+        let mut memory =
+            Memory::allocate_shared(10_000, &MemfdOptions::new()).expect("allocation failed");
+        assert_eq!(memory.backend(), Backend::Memfd);
+        assert_eq!(memory.len(), 10_000);
+        assert_eq!(memory.to_ptr_const() as usize % memory.alignment(), 0);
+        assert!(memory.alignment() >= SystemInfo::get().page_size);
+
+        let data: &mut [u8] = memory.as_mut();
+        assert!(data.iter().all(|&value| value == 0));
+        data[9_999] = 42;
+
+        let fd = memory.fd().expect("memfd has a descriptor");
+        let mut other =
+            unsafe { Memory::from_fd(fd, 10_000, MapMode::ReadWrite) }.expect("mapping failed");
+        assert_eq!(other.alignment(), memory.alignment());
+        assert_eq!(other.backend(), Backend::Memfd);
+        assert!(other.fd().is_some());
+
+        let view: &mut [u8] = other.as_mut();
+        assert_eq!(view[9_999], 42);
+        view[0] = 7;
+
+        let data: &[u8] = memory.as_ref();
+        assert_eq!(data[0], 7);
+    }
+
+    #[test]
+    fn shared_memory_outlives_descriptor() {
+        let memory = Memory::allocate_shared(4096, &MemfdOptions::new()).unwrap();
+        let mut other =
+            unsafe { Memory::from_fd(memory.fd().unwrap(), 4096, MapMode::ReadWrite) }.unwrap();
+        drop(memory);
+
+        let view: &mut [u32] = other.as_mut();
+        view[1023] = 0xdead_beef;
+        assert_eq!(view[1023], 0xdead_beef);
+    }
+
+    #[test]
+    fn seals_prevent_shrinking() {
+        let options = MemfdOptions::new().seal_shrink(true).seal_grow(true);
+        let memory = Memory::allocate_shared(4096, &options).unwrap();
+        let fd = memory.fd().unwrap().as_raw_fd();
+
+        let seals = unsafe { libc::fcntl(fd, libc::F_GET_SEALS) };
+        assert_eq!(seals, libc::F_SEAL_SHRINK | libc::F_SEAL_GROW);
+        assert_ne!(unsafe { libc::ftruncate(fd, 0) }, 0);
+    }
+
+    #[test]
+    fn descriptor_is_close_on_exec_unless_inheritable() {
+        let memory = Memory::allocate_shared(1, &MemfdOptions::new()).unwrap();
+        let flags = unsafe { libc::fcntl(memory.fd().unwrap().as_raw_fd(), libc::F_GETFD) };
+        assert_eq!(flags & libc::FD_CLOEXEC, libc::FD_CLOEXEC);
+
+        let options = MemfdOptions::new().inheritable(true);
+        let memory = Memory::allocate_shared(1, &options).unwrap();
+        let flags = unsafe { libc::fcntl(memory.fd().unwrap().as_raw_fd(), libc::F_GETFD) };
+        assert_eq!(flags & libc::FD_CLOEXEC, 0);
+    }
+
+    #[test]
+    fn huge_tlb_is_huge_page_aligned() {
+        let options = MemfdOptions::new().huge_tlb(true);
+        match Memory::allocate_shared(1, &options) {
+            Ok(memory) => {
+                assert!(memory.alignment() > SystemInfo::get().page_size);
+                assert_eq!(memory.capacity() % memory.alignment(), 0);
+                assert_eq!(memory.len(), 1);
+            }
+            // The hugetlbfs pool is empty or unsupported on this host.
+            Err(err) => assert!(matches!(err, AllocationError::OsError(_))),
+        }
+    }
+
+    #[test]
+    fn from_fd_alignment_follows_local_policy() {
+        let page_size = SystemInfo::get().page_size;
+        let size = 3 * page_size;
+        let memory = Memory::allocate_shared(size, &MemfdOptions::new()).unwrap();
+        let other =
+            unsafe { Memory::from_fd(memory.fd().unwrap(), size, MapMode::ReadOnly) }.unwrap();
+
+        let expected = crate::policy::resolve(size, None).alignment.max(page_size);
+        assert_eq!(other.alignment(), expected);
+        assert_eq!(other.to_ptr_const() as usize % expected, 0);
+    }
+
+    #[test]
+    fn from_fd_of_file_is_file_backed() {
+        let dir = TempDir::new("from_fd_of_file_is_file_backed");
+        let path = dir.0.join("file");
+        std::fs::write(&path, [1u8; 64]).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+
+        let memory = unsafe { Memory::from_fd(file.as_fd(), 64, MapMode::ReadOnly) }.unwrap();
+        assert_eq!(memory.backend(), Backend::File);
+    }
+
+    #[test]
+    fn from_fd_rejects_short_files() {
+        let memory = Memory::allocate_shared(4096, &MemfdOptions::new()).unwrap();
+        let err = unsafe { Memory::from_fd(memory.fd().unwrap(), 4097, MapMode::ReadOnly) }
+            .expect_err("the file is too short");
+        assert_eq!(err, AllocationError::OsError(libc::EINVAL));
+    }
+
+    #[test]
+    fn invalid_name_fails() {
+        let options = MemfdOptions::new().name("nul\0byte");
+        let err = Memory::allocate_shared(1, &options).expect_err("name is invalid");
+        assert_eq!(err, AllocationError::OsError(libc::EINVAL));
+    }
+}
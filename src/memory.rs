@@ -6,15 +6,42 @@
 //! - `ALLOC_FLAGS_NONE`: No special instructions.
 //! - `ALLOC_FLAGS_HUGE_PAGES`: Indicates that huge pages should be used.
 //! - `ALLOC_FLAGS_SEQUENTIAL`: Indicates that memory access is mainly sequential rather than random-access.
+//! - `ALLOC_FLAGS_READ_ONLY`: Indicates that the memory must not be written to.
+//! - `ALLOC_FLAGS_SECURE`: Indicates that the memory is zeroized when freed.
+//! - `ALLOC_FLAGS_DONT_FORK`: Indicates that the memory is not inherited by child processes.
+//! - `ALLOC_FLAGS_WIPE_ON_FORK`: Indicates that the memory is zeroed out in child processes.
+//! - `ALLOC_FLAGS_PROTECTED`: Indicates that the protection of the memory was changed.
+//! - `ALLOC_FLAGS_MERGEABLE`: Indicates that the memory may be merged with identical pages.
+//! - `ALLOC_FLAGS_RANDOM`: Indicates that memory access is mainly random.
+//! - `ALLOC_FLAGS_NAMED`: Indicates that the mapping of the memory was named.
 //!
 //! # Structs
 //! - `Memory`: Represents an allocated memory block with methods for allocation, deallocation, and accessing the memory as slices.
 //!
 //! # Methods
 //! - `Memory::allocate`: Allocates memory of the specified number of bytes with optional sequential access pattern and zeroing out.
+//! - `Memory::allocate_with`: Allocates memory of the specified number of bytes using [`AllocationOptions`].
 //! - `Memory::free`: Frees the allocated memory.
-//! - `Memory::len`: Returns the number of bytes allocated.
-//! - `Memory::is_empty`: Returns whether this instance has zero bytes allocated.
+//! - `Memory::len`: Returns the number of usable bytes.
+//! - `Memory::capacity`: Returns the number of bytes reserved.
+//! - `Memory::set_len`: Sets the number of usable bytes within the capacity.
+//! - `Memory::truncate`: Shortens the number of usable bytes.
+//! - `Memory::is_empty`: Returns whether this instance has zero usable bytes.
+//! - `Memory::alignment`: Returns the alignment the memory was allocated with.
+//! - `Memory::backend`: Returns the mechanism the memory was obtained from.
+//! - `Memory::advise`: Advises the kernel about the expected access pattern.
+//! - `Memory::fd`: Returns the file descriptor of shared memory or a file mapping.
+//! - `Memory::snapshot`: Creates a private copy-on-write snapshot of shared memory or a file mapping.
+//! - `Memory::is_read_only`: Returns whether the memory must not be written to.
+//! - `Memory::is_secure`: Returns whether the memory is zeroized when freed.
+//! - `Memory::set_dont_fork`: Excludes the memory from child processes.
+//! - `Memory::set_wipe_on_fork`: Zeroes out the memory in child processes.
+//! - `Memory::protect`: Changes the protection of a range of the memory.
+//! - `Memory::freeze`: Makes the memory read-only, producing a `FrozenMemory`.
+//! - `Memory::set_mergeable`: Allows merging the memory with identical pages.
+//! - `Memory::residency`: Reports the pages of the memory resident in RAM.
+//! - `Memory::huge_page_coverage`: Reports how much of the memory is backed by huge pages.
+//! - `Memory::set_name`: Names the mapping of the memory.
 //! - `Memory::as_ptr`: Returns a pointer to the data buffer.
 //! - `Memory::as_ptr_mut`: Returns a mutable pointer to the data buffer.
 //!
@@ -47,21 +74,54 @@
 //! - The `madvise` function is used to give advice about the use of memory. The safety of this function relies on the correctness of the pointer and size provided.
 //! - The `free` method ensures that the memory is properly deallocated and the fields are zeroed out to prevent use-after-free errors.
 
-use crate::alignment::AlignmentHint;
 use crate::alloc_free::{alloc_aligned, free_aligned};
 use crate::alloc_result::{AllocResult, AllocationError};
-use libc::madvise;
+use crate::backend::Backend;
+use crate::budget::Budget;
+use crate::hooks;
+use crate::mmap;
+use crate::options::AllocationOptions;
+use crate::policy;
+use crate::secure;
+use crate::stats;
+use crate::system_info::SystemInfo;
+use crate::trace;
 use std::ffi::c_void;
+use std::os::fd::{BorrowedFd, RawFd};
 use std::ptr::{null_mut, NonNull};
 
 /// No special instructions.
-const ALLOC_FLAGS_NONE: u32 = 0;
+pub(crate) const ALLOC_FLAGS_NONE: u32 = 0;
 
 /// Indicates that huge pages should be used.
-const ALLOC_FLAGS_HUGE_PAGES: u32 = 1 << 0;
+pub(crate) const ALLOC_FLAGS_HUGE_PAGES: u32 = 1 << 0;
 
 /// Indicates that memory access is mainly sequential rather than random-access.
-const ALLOC_FLAGS_SEQUENTIAL: u32 = 1 << 1;
+pub(crate) const ALLOC_FLAGS_SEQUENTIAL: u32 = 1 << 1;
+
+/// Indicates that the memory must not be written to.
+pub(crate) const ALLOC_FLAGS_READ_ONLY: u32 = 1 << 2;
+
+/// Indicates that the memory is zeroized when freed. See [`Memory::allocate_secure`].
+pub(crate) const ALLOC_FLAGS_SECURE: u32 = 1 << 3;
+
+/// Indicates that the memory is not inherited by child processes. See [`Memory::set_dont_fork`].
+pub(crate) const ALLOC_FLAGS_DONT_FORK: u32 = 1 << 4;
+
+/// Indicates that the memory is zeroed out in child processes. See [`Memory::set_wipe_on_fork`].
+pub(crate) const ALLOC_FLAGS_WIPE_ON_FORK: u32 = 1 << 5;
+
+/// Indicates that the protection of the memory was changed. See [`Memory::protect`].
+pub(crate) const ALLOC_FLAGS_PROTECTED: u32 = 1 << 6;
+
+/// Indicates that the memory may be merged with identical pages. See [`Memory::set_mergeable`].
+pub(crate) const ALLOC_FLAGS_MERGEABLE: u32 = 1 << 7;
+
+/// Indicates that memory access is mainly random. See [`Memory::advise`].
+pub(crate) const ALLOC_FLAGS_RANDOM: u32 = 1 << 8;
+
+/// Indicates that the mapping of the memory was named. See [`Memory::set_name`].
+pub(crate) const ALLOC_FLAGS_NAMED: u32 = 1 << 9;
 
 /// Allocated memory.
 ///
@@ -90,14 +150,22 @@ const ALLOC_FLAGS_SEQUENTIAL: u32 = 1 << 1;
 pub struct Memory {
     pub(crate) flags: u32,
     pub(crate) num_bytes: usize,
+    pub(crate) capacity: usize,
+    pub(crate) alignment: usize,
+    pub(crate) backend: Backend,
+    pub(crate) fd: RawFd,
     pub(crate) address: *mut c_void,
+    pub(crate) budget: Option<Budget>,
 }
 
 impl Memory {
     /// Allocates memory of the specified number of bytes.
     ///
     /// The optimal alignment will be determined by the number of bytes provided.
-    /// If the amount of bytes is a multiple of 2MB, Huge/Large Page support is enabled.
+    /// If the amount of bytes is a multiple of the Transparent Huge Page size
+    /// (e.g. 2MB on x86-64) and THP is enabled on the host, Huge/Large Page support is enabled.
+    /// See [`SystemInfo`](crate::SystemInfo). If a global alignment policy was set
+    /// using [`set_global_policy`](crate::set_global_policy), it is used instead.
     ///
     /// ## Arguments
     /// * `num_bytes` - The number of bytes to allocate.
@@ -107,13 +175,70 @@ impl Memory {
         num_bytes: usize,
         sequential: bool,
         clear: bool,
+    ) -> Result<Self, AllocationError> {
+        let options = AllocationOptions::new().sequential(sequential).clear(clear);
+        Self::allocate_with(num_bytes, &options)
+    }
+
+    /// Allocates memory of the specified number of bytes using the specified options.
+    ///
+    /// The alignment will be determined by the alignment policy of the options,
+    /// or by the global alignment policy if none was specified. If requested by the options,
+    /// the reserved size may be rounded up to a multiple of the huge page size; see
+    /// [`AllocationOptions::round_to_huge_pages`].
+    ///
+    /// ## Arguments
+    /// * `num_bytes` - The number of bytes to allocate.
+    /// * `options` - The allocation options.
+    pub fn allocate_with(
+        num_bytes: usize,
+        options: &AllocationOptions,
+    ) -> Result<Self, AllocationError> {
+        let operation = trace::Operation::allocate("allocate_with", num_bytes);
+        let result = Self::allocate_unrecorded(num_bytes, options);
+        operation.allocated(&result);
+        stats::record_result(result)
+    }
+
+    fn allocate_unrecorded(
+        num_bytes: usize,
+        options: &AllocationOptions,
     ) -> Result<Self, AllocationError> {
         if num_bytes == 0 {
             return Err(AllocationError::EmptyAllocation);
         }
 
-        let alignment = AlignmentHint::new(num_bytes);
-        let ptr = alloc_aligned(num_bytes, alignment.alignment, clear)?;
+        let sequential = options.sequential;
+        let mut capacity = options.capacity_for(num_bytes, SystemInfo::get());
+        let mut alignment = policy::resolve(capacity, options.policy.as_deref());
+        if options.preflight {
+            Self::can_allocate(capacity).check()?;
+        }
+
+        // Page-granular advice requires a mapping that does not share pages with other allocations.
+        let backend = if options.requires_mapping() {
+            Backend::Anonymous
+        } else {
+            Backend::Heap
+        };
+        let ptr = match backend {
+            Backend::Anonymous => {
+                let page_size = SystemInfo::get().page_size;
+                capacity = capacity
+                    .checked_next_multiple_of(page_size)
+                    .ok_or(AllocationError::OsError(libc::ENOMEM))?;
+                alignment.alignment =
+                    mmap::mapping_alignment(capacity, alignment.alignment, page_size)?;
+                mmap::map_aligned(
+                    capacity,
+                    alignment.alignment,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                )?
+            }
+            _ => alloc_aligned(capacity, alignment.alignment, options.clear)?,
+        };
 
         let ptr: *mut c_void = ptr.as_ptr().cast::<c_void>();
 
@@ -129,63 +254,142 @@ impl Memory {
             ALLOC_FLAGS_NONE
         };
 
+        let (mut advice_address, mut advice_len) = (ptr, capacity);
         if alignment.use_huge_pages {
-            advice |= libc::MADV_HUGEPAGE;
-            flags |= ALLOC_FLAGS_HUGE_PAGES;
+            // Heap blocks may share pages with other allocations, so huge pages are only
+            // advised for the whole huge pages within the block.
+            let range = match backend {
+                Backend::Heap => SystemInfo::get()
+                    .transparent_huge_page_size()
+                    .and_then(|huge_page_size| aligned_interior(ptr, capacity, huge_page_size)),
+                _ => Some((ptr, capacity)),
+            };
+            if let Some((address, len)) = range {
+                advice |= libc::MADV_HUGEPAGE;
+                flags |= ALLOC_FLAGS_HUGE_PAGES;
+                (advice_address, advice_len) = (address, len);
+            }
         };
 
         if advice != 0 {
             // See https://www.man7.org/linux/man-pages/man2/madvise.2.html
-            // SAFETY: `ptr` came from alloc_aligned(num_bytes, alignment)
+            // SAFETY: The range lies within the block obtained above.
             unsafe {
-                madvise(ptr, num_bytes, advice);
+                trace::madvise(advice_address, advice_len, advice);
             }
         }
 
-        Ok(Self::new(AllocResult::Ok, flags, num_bytes, ptr))
+        let mut memory = Self::allocated(
+            flags,
+            num_bytes,
+            capacity,
+            alignment.alignment,
+            backend,
+            ptr,
+        )?;
+        if options.dont_fork {
+            memory.set_dont_fork(true)?;
+        }
+        if options.wipe_on_fork {
+            memory.set_wipe_on_fork(true)?;
+        }
+        if options.mergeable {
+            memory.set_mergeable(true)?;
+        }
+        if let Some(name) = &options.name {
+            memory.set_name(name)?;
+        }
+        Ok(memory)
     }
 
     /// Frees memory of the specified number of bytes.
     ///
     /// The memory instance is required to be created by `allocate`.
+    /// The memory is released using the capacity, alignment and backend it was allocated with,
+    /// regardless of the current length or the currently configured alignment policy.
     pub fn free(&mut self) {
         if self.address.is_null() {
             return;
         }
 
-        let alignment = AlignmentHint::new(self.num_bytes);
+        let operation = trace::Operation::free(self);
+        stats::record_release(self);
+        hooks::release(self);
+        if let Some(budget) = self.budget.take() {
+            budget.release(self.capacity);
+        }
+        self.release();
+        operation.freed();
+    }
 
+    /// Releases the memory without reporting it to the statistics, tracing or hooks.
+    fn release(&mut self) {
         debug_assert_ne!(self.address, null_mut());
         let ptr = core::ptr::NonNull::new(self.address);
 
-        if (self.flags & ALLOC_FLAGS_HUGE_PAGES) == ALLOC_FLAGS_HUGE_PAGES {
-            debug_assert!(alignment.use_huge_pages);
-
-            // See https://www.man7.org/linux/man-pages/man2/madvise.2.html
-            // SAFETY: `ptr` came from alloc_aligned(num_bytes, alignment)
+        if self.is_secure() {
+            // SAFETY: Secure memory is page-granular, and writable up to its capacity
+            // once its protection is restored.
             unsafe {
-                madvise(self.address, self.num_bytes, libc::MADV_FREE);
+                if (self.flags & ALLOC_FLAGS_PROTECTED) == ALLOC_FLAGS_PROTECTED {
+                    self.unprotect();
+                }
+                secure::zeroize(self.address, self.capacity);
             }
         }
 
-        // SAFETY:
-        // - `ptr` is checked for null before
-        // - `num_bytes` and `alignment` are required to be correct by the caller
-        unsafe {
-            free_aligned(ptr, self.num_bytes, alignment.alignment);
+        if self.backend == Backend::Heap
+            && (self.flags & ALLOC_FLAGS_HUGE_PAGES) == ALLOC_FLAGS_HUGE_PAGES
+        {
+            // Only whole pages of the block are freed, as the kernel rounds the range up
+            // and would discard the contents of other allocations sharing the last page.
+            let page_size = SystemInfo::get().page_size;
+            if let Some((address, len)) = aligned_interior(self.address, self.capacity, page_size) {
+                // See https://www.man7.org/linux/man-pages/man2/madvise.2.html
+                // SAFETY: The range lies within the block, which is not used anymore.
+                unsafe {
+                    trace::madvise(address, len, libc::MADV_FREE);
+                }
+            }
+        }
+
+        match self.backend {
+            // SAFETY:
+            // - `ptr` is checked for null before
+            // - `capacity` and `alignment` were persisted at allocation time
+            Backend::Heap => unsafe {
+                free_aligned(ptr, self.capacity, self.alignment);
+            },
+            // SAFETY:
+            // - `ptr` is checked for null before
+            // - `capacity` is the length the mapping was created with
+            // - `fd` is owned by this instance, if any
+            Backend::File | Backend::Memfd | Backend::Anonymous | Backend::Secret => unsafe {
+                mmap::unmap(ptr.expect("ptr is null"), self.capacity);
+                if self.fd >= 0 {
+                    libc::close(self.fd);
+                }
+            },
         }
 
         // Zero out the fields.
         self.address = null_mut();
         self.num_bytes = 0;
+        self.capacity = 0;
+        self.alignment = 0;
+        self.fd = -1;
     }
 
     pub(crate) fn new(
         status: AllocResult,
         flags: u32,
         num_bytes: usize,
+        capacity: usize,
+        alignment: usize,
+        backend: Backend,
         address: *mut c_void,
     ) -> Self {
+        debug_assert!(num_bytes <= capacity, "Length exceeds capacity");
         debug_assert!(
             status == AllocResult::Ok && !address.is_null() || address.is_null(),
             "Found null pointer when allocation status was okay"
@@ -193,8 +397,41 @@ impl Memory {
         Memory {
             flags,
             num_bytes,
+            capacity,
+            alignment,
+            backend,
+            fd: -1,
+            address,
+            budget: None,
+        }
+    }
+
+    /// Creates an instance for memory that was just obtained, and records it in the statistics.
+    ///
+    /// The memory is released again if the allocation hook rejects it.
+    pub(crate) fn allocated(
+        flags: u32,
+        num_bytes: usize,
+        capacity: usize,
+        alignment: usize,
+        backend: Backend,
+        address: *mut c_void,
+    ) -> Result<Self, AllocationError> {
+        let mut memory = Self::new(
+            AllocResult::Ok,
+            flags,
+            num_bytes,
+            capacity,
+            alignment,
+            backend,
             address,
+        );
+        if let Err(error) = hooks::admit(&memory) {
+            memory.release();
+            return Err(error);
         }
+        stats::record_allocation(&memory);
+        Ok(memory)
     }
 
     pub(crate) fn from_error(status: AllocResult) -> Self {
@@ -202,19 +439,119 @@ impl Memory {
         Memory {
             flags: 0,
             num_bytes: 0,
+            capacity: 0,
+            alignment: 0,
+            backend: Backend::Heap,
+            fd: -1,
             address: null_mut(),
+            budget: None,
         }
     }
 
-    /// Returns the number of bytes allocated.
+    /// Returns the number of usable bytes.
+    ///
+    /// This is the number of bytes requested, unless changed using [`Memory::set_len`]
+    /// or [`Memory::truncate`]. Slices obtained through [`AsRef`] and [`AsMut`] are
+    /// bounded by this length.
     #[inline(always)]
     pub fn len(&self) -> usize {
         self.num_bytes
     }
 
-    /// Returns whether this instance has zero bytes allocated.
+    /// Returns the number of bytes reserved.
+    ///
+    /// This is at least [`Memory::len`], and larger if the size was rounded up to a
+    /// multiple of the huge page size; see [`AllocationOptions::round_to_huge_pages`].
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sets the number of usable bytes.
+    ///
+    /// ## Panics
+    /// Panics if `new_len` exceeds the [capacity](Memory::capacity).
+    ///
+    /// ## Safety
+    /// If the length grows, the bytes between the old and the new length are required to be
+    /// initialized, e.g. because the memory was allocated with `clear` set.
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::{AllocationOptions, Memory};
+    /// let options = AllocationOptions::new().clear(true);
+    /// let mut memory = Memory::allocate_with(1024, &options).unwrap();
+    ///
+    /// memory.truncate(512);
+    /// assert_eq!(memory.len(), 512);
+    ///
+    /// // SAFETY: The memory was zeroed out on allocation.
+    /// unsafe { memory.set_len(1024) };
+    /// let data: &[u8] = memory.as_ref();
+    /// assert_eq!(data.len(), 1024);
+    /// ```
+    #[inline]
+    pub unsafe fn set_len(&mut self, new_len: usize) {
+        assert!(
+            new_len <= self.capacity,
+            "Length {new_len} exceeds capacity {}",
+            self.capacity
+        );
+        self.num_bytes = new_len;
+    }
+
+    /// Shortens the number of usable bytes to `len`.
+    ///
+    /// If `len` is not less than the current length, this has no effect.
+    /// The capacity is unaffected; the memory is only released by [`Memory::free`].
+    #[inline]
+    pub fn truncate(&mut self, len: usize) {
+        self.num_bytes = self.num_bytes.min(len);
+    }
+
+    /// Returns the alignment the memory was allocated with, or zero if nothing is allocated.
+    #[inline(always)]
+    pub fn alignment(&self) -> usize {
+        self.alignment
+    }
+
+    /// Returns the mechanism the memory was obtained from.
+    #[inline(always)]
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Returns the file descriptor of shared memory or a file mapping, which can be passed
+    /// to other processes in order to map the memory using [`Memory::from_fd`].
+    ///
+    /// Returns `None` for memory allocated from the heap and for [snapshots](Memory::snapshot).
+    /// The descriptor is closed when the memory is freed.
+    #[inline(always)]
+    pub fn fd(&self) -> Option<BorrowedFd<'_>> {
+        if self.fd < 0 {
+            return None;
+        }
+        // SAFETY: The descriptor is owned by this instance and open until it is freed.
+        Some(unsafe { BorrowedFd::borrow_raw(self.fd) })
+    }
+
+    /// Returns whether the memory must not be written to, e.g. because it is a
+    /// read-only file mapping. Obtaining a mutable slice of such memory panics.
+    #[inline(always)]
+    pub fn is_read_only(&self) -> bool {
+        (self.flags & ALLOC_FLAGS_READ_ONLY) == ALLOC_FLAGS_READ_ONLY
+    }
+
+    /// Returns whether the memory is locked, excluded from core dumps and zeroized when freed.
+    /// See [`Memory::allocate_secure`].
+    #[inline(always)]
+    pub fn is_secure(&self) -> bool {
+        (self.flags & ALLOC_FLAGS_SECURE) == ALLOC_FLAGS_SECURE
+    }
+
+    /// Returns whether this instance has zero usable bytes.
     pub fn is_empty(&self) -> bool {
-        debug_assert!(self.num_bytes > 0 || self.address.is_null());
+        debug_assert!(self.capacity > 0 || self.address.is_null());
         self.num_bytes == 0
     }
 
@@ -294,6 +631,23 @@ impl Memory {
     }
 }
 
+/// Gets the largest part of `[address, address + len)` whose bounds are multiples of `granularity`.
+fn aligned_interior(
+    address: *mut c_void,
+    len: usize,
+    granularity: usize,
+) -> Option<(*mut c_void, usize)> {
+    let start = (address as usize).checked_next_multiple_of(granularity)?;
+    let end = (address as usize).checked_add(len)? / granularity * granularity;
+    (end > start).then(|| {
+        let offset = start - address as usize;
+        (
+            address.cast::<u8>().wrapping_add(offset).cast(),
+            end - start,
+        )
+    })
+}
+
 impl Default for Memory {
     fn default() -> Self {
         Memory::from_error(AllocResult::Empty)
@@ -322,6 +676,7 @@ macro_rules! impl_asref_slice {
         impl AsMut<[$type]> for Memory {
             #[inline(always)]
             fn as_mut(&mut self) -> &mut [$type] {
+                assert!(!self.is_read_only(), "Memory is read-only");
                 let ptr: *mut $type = self.address.cast();
                 let len = self.num_bytes / std::mem::size_of::<$type>();
                 unsafe { &mut *std::ptr::slice_from_raw_parts_mut(ptr, len) }
@@ -347,18 +702,63 @@ mod tests {
     const SIXTY_FOUR_BYTES: usize = 64;
 
     #[test]
-    fn alloc_4mb_is_2mb_aligned_hugepage() {
-        const SIZE: usize = TWO_MEGABYTES * 2;
-        let memory = Memory::allocate(SIZE, true, true).expect("allocation failed");
+    fn aligned_interior_excludes_partial_pages() {
+        let address = 4096 as *mut c_void;
+        assert_eq!(aligned_interior(address, 8192, 4096), Some((address, 8192)));
+        assert_eq!(
+            aligned_interior(4097 as *mut c_void, 3 * 4096, 4096),
+            Some((8192 as *mut c_void, 8192))
+        );
+        assert_eq!(aligned_interior(address, 1000, 4096), None);
+    }
 
-        assert_ne!(memory.address, null_mut());
-        assert_eq!((memory.address as usize) % TWO_MEGABYTES, 0);
-        assert_eq!(memory.len(), SIZE);
-        assert!(!memory.is_empty());
+    #[test]
+    fn small_heap_blocks_do_not_use_huge_pages() {
+        let options = AllocationOptions::new().policy(crate::HugePageThreshold::new(0));
+        let memory = Memory::allocate_with(1000, &options).expect("allocation failed");
+        assert_eq!(memory.backend, Backend::Heap);
+        assert_eq!(memory.flags & ALLOC_FLAGS_HUGE_PAGES, 0);
+
+        let Some(huge_page_size) = SystemInfo::get().transparent_huge_page_size() else {
+            return;
+        };
+        let memory =
+            Memory::allocate_with(2 * huge_page_size + 1, &options).expect("allocation failed");
         assert_eq!(
             memory.flags & ALLOC_FLAGS_HUGE_PAGES,
             ALLOC_FLAGS_HUGE_PAGES
         );
+    }
+
+    /// Gets the size of the allocations that are a number of huge pages on this host,
+    /// e.g. 2 MiB on x86-64, falling back to 2 MiB if Transparent Huge Pages are unavailable.
+    fn huge_pages(count: usize) -> usize {
+        let huge_page_size = SystemInfo::get().transparent_huge_page_size();
+        count * huge_page_size.unwrap_or(TWO_MEGABYTES)
+    }
+
+    /// Asserts that the memory is aligned to and uses huge pages, unless the host
+    /// does not support Transparent Huge Pages.
+    fn assert_huge_pages(memory: &Memory) {
+        let Some(huge_page_size) = SystemInfo::get().transparent_huge_page_size() else {
+            return;
+        };
+        assert_eq!((memory.address as usize) % huge_page_size, 0);
+        assert_eq!(
+            memory.flags & ALLOC_FLAGS_HUGE_PAGES,
+            ALLOC_FLAGS_HUGE_PAGES
+        );
+    }
+
+    #[test]
+    fn alloc_4mb_is_2mb_aligned_hugepage() {
+        let size = huge_pages(2);
+        let memory = Memory::allocate(size, true, true).expect("allocation failed");
+
+        assert_ne!(memory.address, null_mut());
+        assert_eq!(memory.len(), size);
+        assert!(!memory.is_empty());
+        assert_huge_pages(&memory);
         assert_eq!(
             memory.flags & ALLOC_FLAGS_SEQUENTIAL,
             ALLOC_FLAGS_SEQUENTIAL
@@ -367,17 +767,13 @@ mod tests {
 
     #[test]
     fn alloc_4mb_nonsequential_is_2mb_aligned_hugepage() {
-        const SIZE: usize = TWO_MEGABYTES * 2;
-        let memory = Memory::allocate(SIZE, false, false).expect("allocation failed");
+        let size = huge_pages(2);
+        let memory = Memory::allocate(size, false, false).expect("allocation failed");
 
         assert_ne!(memory.address, null_mut());
-        assert_eq!((memory.address as usize) % TWO_MEGABYTES, 0);
-        assert_eq!(memory.len(), SIZE);
+        assert_eq!(memory.len(), size);
         assert!(!memory.is_empty());
-        assert_eq!(
-            memory.flags & ALLOC_FLAGS_HUGE_PAGES,
-            ALLOC_FLAGS_HUGE_PAGES
-        );
+        assert_huge_pages(&memory);
         assert_ne!(
             memory.flags & ALLOC_FLAGS_SEQUENTIAL,
             ALLOC_FLAGS_SEQUENTIAL
@@ -386,17 +782,13 @@ mod tests {
 
     #[test]
     fn alloc_2mb_is_2mb_aligned_hugepage() {
-        const SIZE: usize = TWO_MEGABYTES;
-        let memory = Memory::allocate(SIZE, true, true).expect("allocation failed");
+        let size = huge_pages(1);
+        let memory = Memory::allocate(size, true, true).expect("allocation failed");
 
         assert_ne!(memory.address, null_mut());
-        assert_eq!((memory.address as usize) % TWO_MEGABYTES, 0);
-        assert_eq!(memory.len(), SIZE);
+        assert_eq!(memory.len(), size);
         assert!(!memory.is_empty());
-        assert_eq!(
-            memory.flags & ALLOC_FLAGS_HUGE_PAGES,
-            ALLOC_FLAGS_HUGE_PAGES
-        );
+        assert_huge_pages(&memory);
     }
 
     #[test]
@@ -483,4 +875,176 @@ mod tests {
         assert_eq!(reference[2], 0.0);
         assert_eq!(reference.len(), memory.len() / std::mem::size_of::<f32>());
     }
+
+    #[test]
+    fn alloc_with_policy_stores_alignment() {
+        use crate::policy::PageAligned;
+
+        const SIZE: usize = TWO_MEGABYTES * 2;
+        let options = AllocationOptions::new().clear(true).policy(PageAligned);
+        let memory = Memory::allocate_with(SIZE, &options).expect("allocation failed");
+
+        let page_size = crate::SystemInfo::get().page_size;
+        assert_eq!(memory.alignment(), page_size);
+        assert_eq!((memory.address as usize) % page_size, 0);
+        assert_eq!(memory.len(), SIZE);
+        assert_ne!(
+            memory.flags & ALLOC_FLAGS_HUGE_PAGES,
+            ALLOC_FLAGS_HUGE_PAGES
+        );
+    }
+
+    #[test]
+    fn alloc_with_invalid_policy_fails() {
+        use crate::alignment::AlignmentHint;
+        use crate::policy::AlignmentPolicy;
+        use crate::SystemInfo;
+
+        struct ThreeBytes;
+
+        impl AlignmentPolicy for ThreeBytes {
+            fn hint(&self, _num_bytes: usize, _system: &SystemInfo) -> AlignmentHint {
+                AlignmentHint {
+                    alignment: 3,
+                    use_huge_pages: false,
+                }
+            }
+        }
+
+        let options = AllocationOptions::new().policy(ThreeBytes);
+        let err = Memory::allocate_with(1024, &options).expect_err("alignment is invalid");
+        assert!(matches!(err, AllocationError::InvalidAlignment(_)));
+    }
+
+    #[test]
+    fn mapping_with_invalid_policy_fails() {
+        use crate::alignment::AlignmentHint;
+        use crate::policy::AlignmentPolicy;
+        use crate::SystemInfo;
+
+        struct ThreePages;
+
+        impl AlignmentPolicy for ThreePages {
+            fn hint(&self, _num_bytes: usize, system: &SystemInfo) -> AlignmentHint {
+                AlignmentHint {
+                    alignment: 3 * system.page_size,
+                    use_huge_pages: false,
+                }
+            }
+        }
+
+        let options = AllocationOptions::new()
+            .page_aligned(true)
+            .policy(ThreePages);
+        let err = Memory::allocate_with(1024, &options).expect_err("alignment is invalid");
+        assert!(matches!(err, AllocationError::InvalidAlignment(_)));
+    }
+
+    #[test]
+    fn mapping_options_validate_policy() {
+        use crate::alignment::AlignmentHint;
+        use crate::policy::AlignmentPolicy;
+        use crate::SystemInfo;
+
+        struct ThreeBytes;
+
+        impl AlignmentPolicy for ThreeBytes {
+            fn hint(&self, _num_bytes: usize, _system: &SystemInfo) -> AlignmentHint {
+                AlignmentHint {
+                    alignment: 3,
+                    use_huge_pages: false,
+                }
+            }
+        }
+
+        // Mappings fail like heap allocations rather than raising the alignment to a page.
+        let options = AllocationOptions::new().policy(ThreeBytes);
+        for options in [
+            options.clone().page_aligned(true),
+            options.clone().dont_fork(true),
+            options.clone().wipe_on_fork(true),
+            options.clone().mergeable(true),
+            options.clone().name("invalid-policy"),
+        ] {
+            let err = Memory::allocate_with(1024, &options).expect_err("alignment is invalid");
+            assert!(matches!(err, AllocationError::InvalidAlignment(_)));
+        }
+    }
+
+    #[test]
+    fn alloc_rounded_to_huge_pages() {
+        let size = huge_pages(2) + 1;
+        let options = AllocationOptions::new().round_to_huge_pages(0.5);
+        let mut memory = Memory::allocate_with(size, &options).expect("allocation failed");
+
+        assert_eq!(memory.len(), size);
+        let reference: &mut [u8] = memory.as_mut();
+        assert_eq!(reference.len(), size);
+        reference[size - 1] = 0x42;
+
+        let Some(huge_page_size) = SystemInfo::get().transparent_huge_page_size() else {
+            assert_eq!(memory.capacity(), size);
+            return;
+        };
+        assert_eq!(memory.capacity(), 3 * huge_page_size);
+        assert_eq!((memory.address as usize) % huge_page_size, 0);
+        assert_eq!(
+            memory.flags & ALLOC_FLAGS_HUGE_PAGES,
+            ALLOC_FLAGS_HUGE_PAGES
+        );
+    }
+
+    #[test]
+    fn alloc_not_rounded_by_default() {
+        const SIZE: usize = TWO_MEGABYTES * 2 + 1;
+        let memory = Memory::allocate(SIZE, true, true).expect("allocation failed");
+
+        assert_eq!(memory.len(), SIZE);
+        assert_eq!(memory.capacity(), SIZE);
+        assert_ne!(
+            memory.flags & ALLOC_FLAGS_HUGE_PAGES,
+            ALLOC_FLAGS_HUGE_PAGES
+        );
+    }
+
+    #[test]
+    fn truncate_bounds_slices() {
+        const SIZE: usize = 1024;
+        let mut memory = Memory::allocate(SIZE, false, true).expect("allocation failed");
+
+        memory.truncate(SIZE / 2);
+        assert_eq!(memory.len(), SIZE / 2);
+        assert_eq!(memory.capacity(), SIZE);
+        let reference: &[u32] = memory.as_ref();
+        assert_eq!(reference.len(), SIZE / 2 / std::mem::size_of::<u32>());
+
+        memory.truncate(SIZE);
+        assert_eq!(memory.len(), SIZE / 2);
+    }
+
+    #[test]
+    fn set_len_within_capacity() {
+        const SIZE: usize = TWO_MEGABYTES * 2 + 1;
+        let options = AllocationOptions::new()
+            .clear(true)
+            .round_to_huge_pages(0.5);
+        let mut memory = Memory::allocate_with(SIZE, &options).expect("allocation failed");
+
+        let capacity = memory.capacity();
+        unsafe { memory.set_len(capacity) };
+        let reference: &mut [u8] = memory.as_mut();
+        assert_eq!(reference.len(), capacity);
+        reference[capacity - 1] = 0x42;
+
+        unsafe { memory.set_len(0) };
+        assert!(memory.is_empty());
+        assert_eq!(memory.capacity(), capacity);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds capacity")]
+    fn set_len_beyond_capacity_panics() {
+        let mut memory = Memory::allocate(1024, false, true).expect("allocation failed");
+        unsafe { memory.set_len(1025) };
+    }
 }
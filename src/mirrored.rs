@@ -0,0 +1,262 @@
+//! Provides [`MirroredMemory`], a region of memory mapped twice back-to-back.
+//!
+//! The pages of an anonymous `memfd` are mapped at two adjacent virtual address ranges,
+//! such that the byte at offset `len() + i` is the byte at offset `i`. Data wrapping around
+//! the end of the region can thus be accessed contiguously, which is the foundation of the
+//! [`RingBuffer`](crate::RingBuffer).
+//!
+//! # Structs
+//! - [`MirroredMemory`]: The double-mapped region.
+
+use crate::alloc_result::AllocationError;
+use crate::memfd::{open_memfd, resize_and_seal, MemfdOptions};
+use crate::mmap::{self, map_aligned};
+use crate::policy;
+use crate::trace;
+use std::ffi::c_void;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::ptr::NonNull;
+
+/// Memory whose pages are mapped twice back-to-back.
+///
+/// The region is page-granular, or huge-page granular if [`MemfdOptions::huge_tlb`] is set,
+/// and aligned as decided by the global alignment policy.
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::{MemfdOptions, MirroredMemory};
+/// let memory = MirroredMemory::allocate(1000, &MemfdOptions::new()).unwrap();
+/// assert!(memory.len() >= 1000);
+///
+/// unsafe {
+///     let ptr = memory.to_ptr_mut().cast::<u8>();
+///     ptr.write(42);
+///     assert_eq!(ptr.add(memory.len()).read(), 42);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct MirroredMemory {
+    address: NonNull<c_void>,
+    num_bytes: usize,
+    alignment: usize,
+    fd: OwnedFd,
+}
+
+// SAFETY: The mapping is owned by the instance; all access goes through raw pointers.
+unsafe impl Send for MirroredMemory {}
+
+// SAFETY: Shared references only hand out raw pointers.
+unsafe impl Sync for MirroredMemory {}
+
+impl MirroredMemory {
+    /// Allocates a zeroed-out region of at least `num_bytes`, mapped twice back-to-back.
+    ///
+    /// The size is rounded up to a multiple of the (huge) page size.
+    ///
+    /// ## Arguments
+    /// * `num_bytes` - The minimum size of the region.
+    /// * `options` - The options for creating the backing `memfd`.
+    pub fn allocate(num_bytes: usize, options: &MemfdOptions) -> Result<Self, AllocationError> {
+        Self::allocate_granular(num_bytes, 1, options)
+    }
+
+    /// Allocates a region of at least `num_bytes` whose size is a multiple of `multiple`
+    /// as well as of the (huge) page size.
+    pub(crate) fn allocate_granular(
+        num_bytes: usize,
+        multiple: usize,
+        options: &MemfdOptions,
+    ) -> Result<Self, AllocationError> {
+        if num_bytes == 0 {
+            return Err(AllocationError::EmptyAllocation);
+        }
+
+        let fd = open_memfd(options)?;
+        let page_size = mmap::mapping_granularity(fd.as_raw_fd())?;
+        let granularity = lcm(page_size, multiple);
+        let num_bytes = num_bytes
+            .checked_next_multiple_of(granularity)
+            .ok_or(AllocationError::OsError(libc::ENOMEM))?;
+        let reserved_bytes = num_bytes
+            .checked_mul(2)
+            .ok_or(AllocationError::OsError(libc::ENOMEM))?;
+        resize_and_seal(&fd, num_bytes, options.seals)?;
+
+        // The mirrors are mapped at multiples of the (huge) page size, which `hugetlbfs`
+        // requires to be aligned to its page size.
+        let alignment = mmap::mapping_alignment(
+            num_bytes,
+            policy::resolve(num_bytes, None).alignment,
+            page_size,
+        )?;
+        let reserved = map_aligned(
+            reserved_bytes,
+            alignment,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+            -1,
+        )?;
+
+        for offset in [0, num_bytes] {
+            // SAFETY: The target range lies within the reservation, which is replaced.
+            let ptr = unsafe {
+                libc::mmap(
+                    reserved.as_ptr().cast::<u8>().add(offset).cast(),
+                    num_bytes,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED | libc::MAP_FIXED,
+                    fd.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                let error = AllocationError::last_os_error();
+                // SAFETY: The reservation is owned by this function.
+                unsafe { mmap::unmap(reserved, reserved_bytes) };
+                return Err(error);
+            }
+        }
+
+        let advice = options.advice;
+        if advice != crate::Advice::Normal {
+            // SAFETY: The range was mapped above.
+            unsafe { trace::madvise(reserved.as_ptr(), reserved_bytes, advice.to_madvise()) };
+        }
+
+        Ok(Self {
+            address: reserved,
+            num_bytes,
+            alignment,
+            fd,
+        })
+    }
+
+    /// Returns the size of the region in bytes. The mirror starts at this offset.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.num_bytes
+    }
+
+    /// Returns whether the region is empty, which is never the case.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.num_bytes == 0
+    }
+
+    /// Returns the alignment of the region.
+    #[inline(always)]
+    pub fn alignment(&self) -> usize {
+        self.alignment
+    }
+
+    /// Returns a pointer to the start of the region. Reading up to `2 * len()` bytes is valid.
+    ///
+    /// ## Safety
+    /// Since both halves refer to the same memory, references to the two halves alias.
+    /// Creating overlapping references is undefined behavior if one of them is mutable.
+    #[inline(always)]
+    pub fn to_ptr_const(&self) -> *const c_void {
+        self.address.as_ptr().cast_const()
+    }
+
+    /// Returns a mutable pointer to the start of the region. Writing up to `2 * len()` bytes is valid.
+    ///
+    /// ## Safety
+    /// See [`MirroredMemory::to_ptr_const`].
+    #[inline(always)]
+    pub fn to_ptr_mut(&self) -> *mut c_void {
+        self.address.as_ptr()
+    }
+}
+
+impl AsFd for MirroredMemory {
+    /// Returns the descriptor of the backing `memfd`, which can be mapped by other
+    /// processes using [`Memory::from_fd`](crate::Memory::from_fd).
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Drop for MirroredMemory {
+    fn drop(&mut self) {
+        // SAFETY: Both halves were mapped within the reservation.
+        unsafe { mmap::unmap(self.address, 2 * self.num_bytes) };
+    }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    a / gcd(a, b) * b
+}
+
+fn gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use crate::system_info::SystemInfo;
+
+    #[test]
+    fn halves_are_mirrored() {
+        let memory = MirroredMemory::allocate(1, &MemfdOptions::new()).unwrap();
+        let page_size = SystemInfo::get().page_size;
+        assert_eq!(memory.len(), page_size);
+        assert_eq!(memory.to_ptr_const() as usize % memory.alignment(), 0);
+
+        unsafe {
+            let ptr = memory.to_ptr_mut().cast::<u8>();
+            assert_eq!(ptr.add(page_size - 1).read(), 0);
+
+            ptr.write(1);
+            assert_eq!(ptr.add(page_size).read(), 1);
+
+            ptr.add(2 * page_size - 1).write(2);
+            assert_eq!(ptr.add(page_size - 1).read(), 2);
+        }
+    }
+
+    #[test]
+    fn size_is_multiple_of_element_size() {
+        let memory = MirroredMemory::allocate_granular(1, 12, &MemfdOptions::new()).unwrap();
+        assert_eq!(memory.len() % 12, 0);
+        assert_eq!(memory.len() % SystemInfo::get().page_size, 0);
+    }
+
+    #[test]
+    fn huge_tlb_halves_are_mirrored() {
+        // The hugetlbfs pool is empty or unsupported on this host.
+        if !Memory::can_allocate(1).fits_huge_pages() {
+            return;
+        }
+
+        let options = MemfdOptions::new().huge_tlb(true);
+        let memory = MirroredMemory::allocate(1, &options).unwrap();
+        assert!(memory.len() > SystemInfo::get().page_size);
+        assert_eq!(memory.alignment() % memory.len(), 0);
+
+        let ptr = memory.to_ptr_mut().cast::<u8>();
+        // SAFETY: Both offsets lie within the mirrored region.
+        unsafe {
+            ptr.write(42);
+            assert_eq!(ptr.add(memory.len()).read(), 42);
+        }
+    }
+
+    #[test]
+    fn empty_allocation_fails() {
+        let err = MirroredMemory::allocate(0, &MemfdOptions::new()).expect_err("empty");
+        assert_eq!(err, AllocationError::EmptyAllocation);
+    }
+
+    #[test]
+    fn least_common_multiple() {
+        assert_eq!(lcm(4096, 1), 4096);
+        assert_eq!(lcm(4096, 12), 12288);
+        assert_eq!(lcm(4096, 8192), 8192);
+    }
+}
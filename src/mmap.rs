@@ -0,0 +1,806 @@
+//! Provides memory-mapped file backing for [`Memory`].
+//!
+//! Files are mapped using `mmap(2)` at the alignment chosen by the global alignment policy,
+//! but at least at page granularity, and unmapped using `munmap(2)` when the [`Memory`] is freed.
+//! Modifications of shared mappings are written back using `msync(2)`; see [`Memory::flush`].
+//!
+//! # Enums
+//! - [`MapMode`]: Whether a file is mapped read-only, read-write or copy-on-write.
+//! - [`Advice`]: The expected access pattern of a mapping.
+
+use crate::alloc_result::AllocationError;
+use crate::backend::Backend;
+use crate::memory::{
+    Memory, ALLOC_FLAGS_NONE, ALLOC_FLAGS_RANDOM, ALLOC_FLAGS_READ_ONLY, ALLOC_FLAGS_SEQUENTIAL,
+};
+use crate::policy;
+use crate::stats;
+use crate::system_info::SystemInfo;
+use crate::trace;
+use std::alloc::Layout;
+use std::ffi::c_void;
+use std::fs::OpenOptions;
+use std::os::fd::{AsRawFd, IntoRawFd, RawFd};
+use std::path::Path;
+use std::ptr::{null_mut, NonNull};
+
+/// The file system type of `hugetlbfs`, see `statfs(2)`.
+const HUGETLBFS_MAGIC: u32 = 0x9584_58f6;
+
+/// How a file is mapped into memory.
+#[repr(u32)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum MapMode {
+    /// The mapping can only be read. Obtaining a mutable slice panics.
+    ReadOnly = 0,
+    /// Modifications are written back to the file and visible to other mappings of it.
+    ReadWrite = 1,
+    /// Modifications are private to the mapping and never written back to the file.
+    CopyOnWrite = 2,
+}
+
+/// The expected access pattern of a mapping, passed to `madvise(2)`.
+#[repr(u32)]
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum Advice {
+    /// No special treatment (`MADV_NORMAL`).
+    #[default]
+    Normal = 0,
+    /// Pages are accessed in sequential order, e.g. when scanning (`MADV_SEQUENTIAL`).
+    Sequential = 1,
+    /// Pages are accessed in random order; read-ahead is disabled (`MADV_RANDOM`).
+    Random = 2,
+    /// Pages will be accessed soon and are read ahead (`MADV_WILLNEED`).
+    WillNeed = 3,
+}
+
+impl Advice {
+    pub(crate) fn to_madvise(self) -> libc::c_int {
+        match self {
+            Advice::Normal => libc::MADV_NORMAL,
+            Advice::Sequential => libc::MADV_SEQUENTIAL,
+            Advice::Random => libc::MADV_RANDOM,
+            Advice::WillNeed => libc::MADV_WILLNEED,
+        }
+    }
+}
+
+impl TryFrom<u32> for MapMode {
+    type Error = AllocationError;
+
+    /// Converts a raw mode, failing with [`AllocationError::OsError`] (`EINVAL`) for unknown values.
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(MapMode::ReadOnly),
+            1 => Ok(MapMode::ReadWrite),
+            2 => Ok(MapMode::CopyOnWrite),
+            _ => Err(AllocationError::OsError(libc::EINVAL)),
+        }
+    }
+}
+
+impl TryFrom<u32> for Advice {
+    type Error = AllocationError;
+
+    /// Converts a raw advice, failing with [`AllocationError::OsError`] (`EINVAL`) for unknown values.
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Advice::Normal),
+            1 => Ok(Advice::Sequential),
+            2 => Ok(Advice::Random),
+            3 => Ok(Advice::WillNeed),
+            _ => Err(AllocationError::OsError(libc::EINVAL)),
+        }
+    }
+}
+
+impl Memory {
+    /// Maps an existing file into memory.
+    ///
+    /// The length of the memory is the size of the file at the time of mapping. The mapping
+    /// is aligned as decided by the global alignment policy, but at least to the page size.
+    ///
+    /// ## Arguments
+    /// * `path` - The file to map.
+    /// * `mode` - Whether to map the file read-only, read-write or copy-on-write.
+    /// * `advice` - The expected access pattern, e.g. [`Advice::Sequential`] for scans.
+    ///
+    /// ## Errors
+    /// Returns [`AllocationError::EmptyAllocation`] if the file is empty, and
+    /// [`AllocationError::OsError`] if it cannot be opened or mapped.
+    ///
+    /// ## Safety
+    /// The file is required not to be truncated while it is mapped; accessing pages beyond
+    /// the end of the file raises `SIGBUS`. If the file is modified by other processes, the
+    /// contents of the memory may change at any time.
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::{Advice, MapMode, Memory};
+    /// let path = std::env::temp_dir().join(format!("alloc-madvise-doc-{}", std::process::id()));
+    /// std::fs::write(&path, [1u8, 2, 3, 4]).unwrap();
+    ///
+    /// let memory = unsafe { Memory::map_file(&path, MapMode::ReadOnly, Advice::Sequential) }.unwrap();
+    /// let data: &[u8] = memory.as_ref();
+    /// assert_eq!(data, &[1, 2, 3, 4]);
+    /// # drop(memory);
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub unsafe fn map_file<P: AsRef<Path>>(
+        path: P,
+        mode: MapMode,
+        advice: Advice,
+    ) -> Result<Self, AllocationError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(mode == MapMode::ReadWrite)
+            .open(path)?;
+        let num_bytes = usize::try_from(file.metadata()?.len())
+            .map_err(|_| AllocationError::OsError(libc::EFBIG))?;
+        let mut memory = Self::map_fd(file.as_raw_fd(), num_bytes, mode, advice, Backend::File)?;
+        memory.fd = file.into_raw_fd();
+        Ok(memory)
+    }
+
+    /// Creates a file of the specified size, or truncates an existing one to it,
+    /// and maps it into memory read-write.
+    ///
+    /// The contents of a newly created file are zero.
+    ///
+    /// ## Arguments
+    /// * `path` - The file to create.
+    /// * `num_bytes` - The size of the file.
+    /// * `advice` - The expected access pattern.
+    ///
+    /// ## Safety
+    /// See [`Memory::map_file`].
+    pub unsafe fn create_file<P: AsRef<Path>>(
+        path: P,
+        num_bytes: usize,
+        advice: Advice,
+    ) -> Result<Self, AllocationError> {
+        if num_bytes == 0 {
+            return Err(AllocationError::EmptyAllocation);
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(num_bytes as u64)?;
+        let mut memory = Self::map_fd(
+            file.as_raw_fd(),
+            num_bytes,
+            MapMode::ReadWrite,
+            advice,
+            Backend::File,
+        )?;
+        memory.fd = file.into_raw_fd();
+        Ok(memory)
+    }
+
+    /// Creates a private copy-on-write snapshot of shared memory or a file mapping.
+    ///
+    /// The snapshot is a `MAP_PRIVATE` mapping of the same file, aligned like the original.
+    /// Modifications of the snapshot are never visible to the original, and vice versa.
+    /// Its pages are released when the snapshot is freed.
+    ///
+    /// Note that on Linux, pages of a private mapping that were not yet written to reflect
+    /// later modifications of the file. In order for the snapshot to be consistent, all of its
+    /// pages are therefore copied by the kernel when it is created (`MADV_POPULATE_WRITE`),
+    /// which requires as much memory as the original. If a point-in-time view is required,
+    /// the original is required not to be modified while the snapshot is created.
+    ///
+    /// ## Errors
+    /// Returns [`AllocationError::OsError`] with `EINVAL` unless the memory was obtained
+    /// using [`Memory::allocate_shared`], [`Memory::map_file`], [`Memory::create_file`],
+    /// [`Memory::from_fd`] or [`SharedMemory`](crate::SharedMemory).
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::{MemfdOptions, Memory};
+    /// let mut memory = Memory::allocate_shared(4096, &MemfdOptions::new()).unwrap();
+    /// let data: &mut [u8] = memory.as_mut();
+    /// data[0] = 1;
+    ///
+    /// let snapshot = memory.snapshot().unwrap();
+    /// let data: &mut [u8] = memory.as_mut();
+    /// data[0] = 2;
+    ///
+    /// let view: &[u8] = snapshot.as_ref();
+    /// assert_eq!(view[0], 1);
+    /// ```
+    pub fn snapshot(&self) -> Result<Memory, AllocationError> {
+        if !matches!(self.backend, Backend::File | Backend::Memfd) || self.fd < 0 {
+            return Err(AllocationError::OsError(libc::EINVAL));
+        }
+
+        let ptr = map_aligned(
+            self.capacity,
+            self.alignment,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE,
+            self.fd,
+        )?;
+
+        // SAFETY: The mapping was created above and is not yet shared.
+        if let Err(error) = unsafe { populate_write(ptr, self.capacity) } {
+            // SAFETY: See above.
+            unsafe { unmap(ptr, self.capacity) };
+            return Err(error);
+        }
+
+        // Only the access pattern is carried over, as it is advised again below. Other advice
+        // and protections apply to the original mapping only.
+        let flags = self.flags & (ALLOC_FLAGS_SEQUENTIAL | ALLOC_FLAGS_RANDOM);
+        let advice = if flags & ALLOC_FLAGS_SEQUENTIAL != 0 {
+            libc::MADV_SEQUENTIAL
+        } else if flags & ALLOC_FLAGS_RANDOM != 0 {
+            libc::MADV_RANDOM
+        } else {
+            libc::MADV_NORMAL
+        };
+        if advice != libc::MADV_NORMAL {
+            // SAFETY: See above.
+            unsafe { trace::madvise(ptr.as_ptr(), self.capacity, advice) };
+        }
+
+        Self::allocated(
+            flags,
+            self.num_bytes,
+            self.capacity,
+            self.alignment,
+            Backend::File,
+            ptr.as_ptr(),
+        )
+    }
+
+    /// Advises the kernel about the expected access pattern of the memory.
+    ///
+    /// Since `madvise(2)` operates on whole pages, the advice also applies to the
+    /// remainder of the first and last page if the memory is not page-aligned.
+    pub fn advise(&mut self, advice: Advice) -> Result<(), AllocationError> {
+        if self.address.is_null() {
+            return Ok(());
+        }
+
+        let start = self.address as usize;
+        let aligned = start & !(SystemInfo::get().page_size - 1);
+
+        // SAFETY: The range covers the memory and the pages it overlaps with.
+        let result = unsafe {
+            trace::madvise(
+                aligned as *mut c_void,
+                self.capacity + (start - aligned),
+                advice.to_madvise(),
+            )
+        };
+        if result != 0 {
+            return Err(AllocationError::last_os_error());
+        }
+
+        let flags = self.flags;
+        self.flags &= !(ALLOC_FLAGS_SEQUENTIAL | ALLOC_FLAGS_RANDOM);
+        match advice {
+            Advice::Sequential => self.flags |= ALLOC_FLAGS_SEQUENTIAL,
+            Advice::Random => self.flags |= ALLOC_FLAGS_RANDOM,
+            Advice::Normal | Advice::WillNeed => {}
+        }
+        stats::record_flags_change(self, flags);
+        Ok(())
+    }
+
+    /// Writes modifications of the memory back to the mapped file and waits for completion.
+    ///
+    /// This has no effect unless the memory is a [`MapMode::ReadWrite`] file mapping.
+    pub fn flush(&self) -> Result<(), AllocationError> {
+        self.flush_range(0, self.num_bytes)
+    }
+
+    /// Writes modifications of a range of the memory back to the mapped file and waits for completion.
+    ///
+    /// This has no effect unless the memory is a [`MapMode::ReadWrite`] file mapping.
+    ///
+    /// ## Arguments
+    /// * `offset` - The start of the range in bytes.
+    /// * `num_bytes` - The length of the range in bytes.
+    ///
+    /// ## Panics
+    /// Panics if the range exceeds the [length](Memory::len) of the memory.
+    pub fn flush_range(&self, offset: usize, num_bytes: usize) -> Result<(), AllocationError> {
+        assert!(
+            offset
+                .checked_add(num_bytes)
+                .is_some_and(|end| end <= self.num_bytes),
+            "Range {offset}+{num_bytes} exceeds length {}",
+            self.num_bytes
+        );
+
+        if self.backend != Backend::File || self.is_read_only() || num_bytes == 0 {
+            return Ok(());
+        }
+
+        // msync(2) requires a page-aligned address.
+        let start = self.address as usize + offset;
+        let aligned = start & !(SystemInfo::get().page_size - 1);
+
+        // SAFETY: The range lies within the mapping.
+        let result = unsafe {
+            libc::msync(
+                aligned as *mut c_void,
+                num_bytes + (start - aligned),
+                libc::MS_SYNC,
+            )
+        };
+        if result != 0 {
+            return Err(AllocationError::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Maps `num_bytes` of an open file at the alignment chosen by the global policy.
+    ///
+    /// Files on `hugetlbfs` are mapped at the alignment and granularity of their page size.
+    /// The memory is attributed to `backend`.
+    pub(crate) unsafe fn map_fd(
+        fd: RawFd,
+        num_bytes: usize,
+        mode: MapMode,
+        advice: Advice,
+        backend: Backend,
+    ) -> Result<Self, AllocationError> {
+        if num_bytes == 0 {
+            return Err(AllocationError::EmptyAllocation);
+        }
+
+        let (protection, sharing) = match mode {
+            MapMode::ReadOnly => (libc::PROT_READ, libc::MAP_SHARED),
+            MapMode::ReadWrite => (libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED),
+            MapMode::CopyOnWrite => (libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE),
+        };
+
+        let granularity = mapping_granularity(fd)?;
+        let capacity = round_up(num_bytes, granularity);
+        let alignment = mapping_alignment(
+            capacity,
+            policy::resolve(capacity, None).alignment,
+            granularity,
+        )?;
+        let ptr = map_aligned(capacity, alignment, protection, sharing, fd)?.as_ptr();
+
+        let advice = advice.to_madvise();
+        if advice != libc::MADV_NORMAL {
+            // See https://www.man7.org/linux/man-pages/man2/madvise.2.html
+            // SAFETY: `ptr` came from map_aligned(capacity, alignment)
+            unsafe {
+                trace::madvise(ptr, capacity, advice);
+            }
+        }
+
+        let mut flags = ALLOC_FLAGS_NONE;
+        if advice == libc::MADV_SEQUENTIAL {
+            flags |= ALLOC_FLAGS_SEQUENTIAL;
+        }
+        if mode == MapMode::ReadOnly {
+            flags |= ALLOC_FLAGS_READ_ONLY;
+        }
+
+        Self::allocated(flags, num_bytes, capacity, alignment, backend, ptr)
+    }
+}
+
+/// Raises the alignment chosen by a policy to the granularity of a mapping.
+///
+/// ## Errors
+/// Fails with [`AllocationError::InvalidAlignment`] if the alignment is not a power of two,
+/// as heap allocations do, rather than letting the granularity mask it.
+pub(crate) fn mapping_alignment(
+    num_bytes: usize,
+    alignment: usize,
+    granularity: usize,
+) -> Result<usize, AllocationError> {
+    Layout::from_size_align(num_bytes, alignment)?;
+    Ok(alignment.max(granularity))
+}
+
+/// Maps `num_bytes` of `fd` (or anonymous memory if `fd` is `-1`) at an address aligned
+/// to `alignment`, which is required to be a power-of-two multiple of the page size.
+///
+/// If the alignment exceeds the page size, a larger region is reserved first and the
+/// excess at both ends is unmapped again.
+///
+/// ## Errors
+/// Fails with [`AllocationError::InvalidAlignment`] if the alignment is not a power of two,
+/// and with [`AllocationError::OsError`] (`EINVAL`) if it is smaller than the page size.
+pub(crate) fn map_aligned(
+    num_bytes: usize,
+    alignment: usize,
+    protection: libc::c_int,
+    flags: libc::c_int,
+    fd: RawFd,
+) -> Result<NonNull<c_void>, AllocationError> {
+    let page_size = SystemInfo::get().page_size;
+    // Any other alignment would place the fixed mapping outside the reservation.
+    Layout::from_size_align(num_bytes, alignment)?;
+    if alignment < page_size {
+        return Err(AllocationError::OsError(libc::EINVAL));
+    }
+
+    if alignment == page_size {
+        // SAFETY: Creating a new mapping at an address chosen by the kernel.
+        let ptr = unsafe { libc::mmap(null_mut(), num_bytes, protection, flags, fd, 0) };
+        return checked_mapping(ptr);
+    }
+
+    let reserved_bytes = num_bytes
+        .checked_add(alignment)
+        .ok_or(AllocationError::OsError(libc::ENOMEM))?;
+
+    // SAFETY: Reserving address space at an address chosen by the kernel.
+    let reserved = checked_mapping(unsafe {
+        libc::mmap(
+            null_mut(),
+            reserved_bytes,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+            -1,
+            0,
+        )
+    })?
+    .as_ptr() as usize;
+
+    let start = (reserved + alignment - 1) & !(alignment - 1);
+    let end = start + round_up(num_bytes, page_size);
+
+    // SAFETY: The target range lies within the reservation, which is replaced.
+    let ptr = unsafe {
+        libc::mmap(
+            start as *mut c_void,
+            num_bytes,
+            protection,
+            flags | libc::MAP_FIXED,
+            fd,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        let error = AllocationError::last_os_error();
+        // SAFETY: The reservation is unused.
+        unsafe { libc::munmap(reserved as *mut c_void, reserved_bytes) };
+        return Err(error);
+    }
+
+    // SAFETY: Both ranges are unused parts of the reservation.
+    unsafe {
+        if start > reserved {
+            libc::munmap(reserved as *mut c_void, start - reserved);
+        }
+        if reserved + reserved_bytes > end {
+            libc::munmap(end as *mut c_void, reserved + reserved_bytes - end);
+        }
+    }
+
+    checked_mapping(ptr)
+}
+
+/// Faults in all pages of a private mapping for writing, breaking copy-on-write sharing.
+///
+/// ## Safety
+/// `ptr` and `num_bytes` are required to describe a writable mapping that is not accessed concurrently.
+unsafe fn populate_write(ptr: NonNull<c_void>, num_bytes: usize) -> Result<(), AllocationError> {
+    if trace::madvise(ptr.as_ptr(), num_bytes, libc::MADV_POPULATE_WRITE) == 0 {
+        return Ok(());
+    }
+
+    let error = AllocationError::last_os_error();
+    if error != AllocationError::OsError(libc::EINVAL) {
+        return Err(error);
+    }
+
+    // Kernels before 5.14 do not support MADV_POPULATE_WRITE; write to every page instead.
+    let page_size = SystemInfo::get().page_size;
+    let base = ptr.as_ptr().cast::<u8>();
+    for offset in (0..num_bytes).step_by(page_size) {
+        let byte = base.add(offset);
+        byte.write_volatile(byte.read_volatile());
+    }
+    Ok(())
+}
+
+/// Unmaps memory obtained from [`map_aligned`].
+///
+/// ## Safety
+/// `ptr` and `num_bytes` are required to describe a mapping created by [`map_aligned`].
+pub(crate) unsafe fn unmap(ptr: NonNull<c_void>, num_bytes: usize) {
+    // SAFETY: See above.
+    let result = libc::munmap(ptr.as_ptr(), num_bytes);
+    debug_assert_eq!(result, 0, "munmap failed");
+}
+
+/// Gets the granularity at which `fd` can be mapped: the page size of `hugetlbfs`
+/// files, and the regular page size otherwise.
+pub(crate) fn mapping_granularity(fd: RawFd) -> Result<usize, AllocationError> {
+    let page_size = SystemInfo::get().page_size;
+
+    // SAFETY: `statfs` is plain old data, and is only read if `fstatfs` succeeds.
+    let mut statfs: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatfs(fd, &mut statfs) } != 0 {
+        return Err(AllocationError::last_os_error());
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    if statfs.f_type as u32 != HUGETLBFS_MAGIC {
+        return Ok(page_size);
+    }
+
+    // hugetlbfs reports the huge page size as the block size.
+    let block_size = usize::try_from(statfs.f_bsize).unwrap_or(0);
+    if block_size.is_power_of_two() {
+        Ok(block_size.max(page_size))
+    } else {
+        Ok(page_size)
+    }
+}
+
+fn checked_mapping(ptr: *mut c_void) -> Result<NonNull<c_void>, AllocationError> {
+    if ptr == libc::MAP_FAILED {
+        return Err(AllocationError::last_os_error());
+    }
+    NonNull::new(ptr).ok_or(AllocationError::OsError(libc::ENOMEM))
+}
+
+pub(crate) fn round_up(value: usize, multiple: usize) -> usize {
+    value.div_ceil(multiple) * multiple
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn map_read_only() {
+        let dir = TempDir::new("map_read_only");
+        let path = dir.0.join("file");
+        let contents: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+        std::fs::write(&path, &contents).unwrap();
+
+        let memory = unsafe { Memory::map_file(&path, MapMode::ReadOnly, Advice::Sequential) }
+            .expect("mapping failed");
+        assert_eq!(memory.backend(), Backend::File);
+        assert_eq!(memory.len(), contents.len());
+        assert!(memory.is_read_only());
+        assert_eq!(
+            memory.flags & ALLOC_FLAGS_SEQUENTIAL,
+            ALLOC_FLAGS_SEQUENTIAL
+        );
+        assert_eq!(memory.to_ptr_const() as usize % memory.alignment(), 0);
+
+        let data: &[u8] = memory.as_ref();
+        assert_eq!(data, contents.as_slice());
+        memory.flush().expect("flush is a no-op");
+    }
+
+    #[test]
+    #[should_panic(expected = "read-only")]
+    fn map_read_only_as_mut_panics() {
+        let dir = TempDir::new("map_read_only_as_mut_panics");
+        let path = dir.0.join("file");
+        std::fs::write(&path, [0u8; 16]).unwrap();
+
+        let mut memory =
+            unsafe { Memory::map_file(&path, MapMode::ReadOnly, Advice::Normal) }.unwrap();
+        let _: &mut [u8] = memory.as_mut();
+    }
+
+    #[test]
+    fn map_read_write_writes_back() {
+        let dir = TempDir::new("map_read_write_writes_back");
+        let path = dir.0.join("file");
+        let page_size = SystemInfo::get().page_size;
+
+        let mut memory = unsafe { Memory::create_file(&path, 3 * page_size, Advice::Random) }
+            .expect("mapping failed");
+        assert_eq!(memory.len(), 3 * page_size);
+        assert!(!memory.is_read_only());
+
+        let data: &mut [f32] = memory.as_mut();
+        assert!(data.iter().all(|&value| value == 0.0));
+        data[0] = 1.5;
+        data[page_size / 4 + 1] = 2.5;
+        memory.flush_range(page_size + 4, 4).expect("flush failed");
+        memory.flush().expect("flush failed");
+        drop(memory);
+
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!(contents.len(), 3 * page_size);
+        assert_eq!(contents[..4], 1.5f32.to_ne_bytes());
+        assert_eq!(contents[page_size + 4..page_size + 8], 2.5f32.to_ne_bytes());
+    }
+
+    #[test]
+    fn map_copy_on_write_is_private() {
+        let dir = TempDir::new("map_copy_on_write_is_private");
+        let path = dir.0.join("file");
+        std::fs::write(&path, [7u8; 64]).unwrap();
+
+        let mut memory =
+            unsafe { Memory::map_file(&path, MapMode::CopyOnWrite, Advice::Normal) }.unwrap();
+        let data: &mut [u8] = memory.as_mut();
+        data[0] = 42;
+        memory.flush().unwrap();
+        drop(memory);
+
+        assert_eq!(std::fs::read(&path).unwrap(), [7u8; 64]);
+    }
+
+    #[test]
+    fn snapshot_of_file_is_isolated() {
+        let dir = TempDir::new("snapshot_of_file_is_isolated");
+        let path = dir.0.join("file");
+        let mut memory = unsafe { Memory::create_file(&path, 10_000, Advice::Normal) }.unwrap();
+        assert!(memory.fd().is_some());
+        let data: &mut [u8] = memory.as_mut();
+        data[0] = 1;
+        data[9_999] = 2;
+
+        let mut snapshot = memory.snapshot().unwrap();
+        assert_eq!(snapshot.len(), 10_000);
+        assert_eq!(snapshot.alignment(), memory.alignment());
+        assert!(snapshot.fd().is_none());
+
+        let data: &mut [u8] = memory.as_mut();
+        data[0] = 3;
+        let view: &mut [u8] = snapshot.as_mut();
+        assert_eq!(view[..1], [1]);
+        assert_eq!(view[9_999], 2);
+        view[9_999] = 4;
+
+        let data: &[u8] = memory.as_ref();
+        assert_eq!(data[9_999], 2);
+        drop(snapshot);
+        drop(memory);
+
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!((contents[0], contents[9_999]), (3, 2));
+    }
+
+    #[test]
+    fn snapshot_of_read_only_mapping_is_writable() {
+        let dir = TempDir::new("snapshot_of_read_only_mapping_is_writable");
+        let path = dir.0.join("file");
+        std::fs::write(&path, [7u8; 64]).unwrap();
+
+        let memory = unsafe { Memory::map_file(&path, MapMode::ReadOnly, Advice::Normal) }.unwrap();
+        let mut snapshot = memory.snapshot().unwrap();
+        assert!(!snapshot.is_read_only());
+        let view: &mut [u8] = snapshot.as_mut();
+        view[0] = 42;
+
+        let data: &[u8] = memory.as_ref();
+        assert_eq!(data[0], 7);
+    }
+
+    #[test]
+    fn snapshot_only_keeps_access_pattern() {
+        let mut memory = Memory::allocate_shared(4096, &crate::MemfdOptions::new()).unwrap();
+        memory.advise(Advice::Sequential).unwrap();
+        memory.set_dont_fork(true).unwrap();
+        memory
+            .protect(0..4096, crate::Protection::ReadOnly)
+            .unwrap();
+
+        let snapshot = memory.snapshot().unwrap();
+        assert_eq!(snapshot.flags, ALLOC_FLAGS_SEQUENTIAL);
+        assert!(!snapshot.is_dont_fork());
+        assert!(!snapshot.is_read_only());
+    }
+
+    #[test]
+    fn snapshot_of_heap_memory_fails() {
+        let memory = Memory::allocate(1024, false, false).unwrap();
+        let err = memory.snapshot().expect_err("heap memory has no file");
+        assert_eq!(err, AllocationError::OsError(libc::EINVAL));
+    }
+
+    #[test]
+    fn map_empty_file_fails() {
+        let dir = TempDir::new("map_empty_file_fails");
+        let path = dir.0.join("file");
+        std::fs::write(&path, []).unwrap();
+
+        let err = unsafe { Memory::map_file(&path, MapMode::ReadOnly, Advice::Normal) }
+            .expect_err("the file is empty");
+        assert_eq!(err, AllocationError::EmptyAllocation);
+    }
+
+    #[test]
+    fn map_missing_file_fails() {
+        let dir = TempDir::new("map_missing_file_fails");
+        let path = dir.0.join("file");
+        let err = unsafe { Memory::map_file(&path, MapMode::ReadOnly, Advice::Normal) }
+            .expect_err("the file does not exist");
+        assert_eq!(err, AllocationError::OsError(libc::ENOENT));
+    }
+
+    #[test]
+    fn advise_updates_flags() {
+        let mut memory = Memory::allocate(1000, false, false).unwrap();
+        memory.advise(Advice::Sequential).expect("advice failed");
+        assert_eq!(
+            memory.flags & ALLOC_FLAGS_SEQUENTIAL,
+            ALLOC_FLAGS_SEQUENTIAL
+        );
+        memory.advise(Advice::WillNeed).expect("advice failed");
+        assert_eq!(memory.flags & ALLOC_FLAGS_SEQUENTIAL, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds length")]
+    fn flush_range_out_of_bounds_panics() {
+        let memory = Memory::allocate(1024, false, false).unwrap();
+        let _ = memory.flush_range(1000, 25);
+    }
+
+    #[test]
+    fn map_aligned_to_huge_pages() {
+        let huge_page_size = 2 * 1024 * 1024;
+        let ptr = map_aligned(
+            4096,
+            huge_page_size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+        )
+        .expect("mapping failed");
+        assert_eq!(ptr.as_ptr() as usize % huge_page_size, 0);
+        unsafe { unmap(ptr, 4096) };
+    }
+
+    #[test]
+    fn raw_modes_and_advices() {
+        for mode in [MapMode::ReadOnly, MapMode::ReadWrite, MapMode::CopyOnWrite] {
+            assert_eq!(MapMode::try_from(mode as u32), Ok(mode));
+        }
+        for advice in [
+            Advice::Normal,
+            Advice::Sequential,
+            Advice::Random,
+            Advice::WillNeed,
+        ] {
+            assert_eq!(Advice::try_from(advice as u32), Ok(advice));
+        }
+        assert_eq!(
+            MapMode::try_from(3),
+            Err(AllocationError::OsError(libc::EINVAL))
+        );
+        assert_eq!(
+            Advice::try_from(u32::MAX),
+            Err(AllocationError::OsError(libc::EINVAL))
+        );
+    }
+
+    #[test]
+    fn map_aligned_rejects_invalid_alignment() {
+        let page_size = SystemInfo::get().page_size;
+        let map = |alignment| {
+            map_aligned(
+                4096,
+                alignment,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+            )
+        };
+        assert!(matches!(
+            map(3 * page_size),
+            Err(AllocationError::InvalidAlignment(_))
+        ));
+        assert_eq!(
+            map(page_size / 2),
+            Err(AllocationError::OsError(libc::EINVAL))
+        );
+    }
+}
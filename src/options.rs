@@ -0,0 +1,288 @@
+//! Provides the [`AllocationOptions`] struct controlling how [`Memory`](crate::Memory)
+//! is allocated.
+
+use crate::policy::AlignmentPolicy;
+use crate::system_info::SystemInfo;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+/// Options for allocating [`Memory`](crate::Memory).
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::{AllocationOptions, Memory, PageAligned};
+/// let options = AllocationOptions::new()
+///     .sequential(true)
+///     .clear(true)
+///     .policy(PageAligned);
+///
+/// let memory = Memory::allocate_with(1024, &options).unwrap();
+/// assert_eq!(memory.len(), 1024);
+/// ```
+#[derive(Clone, Default)]
+pub struct AllocationOptions {
+    pub(crate) sequential: bool,
+    pub(crate) clear: bool,
+    pub(crate) policy: Option<Arc<dyn AlignmentPolicy>>,
+    pub(crate) max_huge_page_waste: Option<f64>,
+    pub(crate) dont_fork: bool,
+    pub(crate) wipe_on_fork: bool,
+    pub(crate) page_aligned: bool,
+    pub(crate) mergeable: bool,
+    pub(crate) name: Option<String>,
+    pub(crate) preflight: bool,
+}
+
+impl AllocationOptions {
+    /// Creates options for non-sequential, uncleared memory using the global alignment policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether or not the memory access pattern is sequential mostly.
+    pub fn sequential(mut self, sequential: bool) -> Self {
+        self.sequential = sequential;
+        self
+    }
+
+    /// Sets whether or not to zero out the allocated memory.
+    pub fn clear(mut self, clear: bool) -> Self {
+        self.clear = clear;
+        self
+    }
+
+    /// Sets the alignment policy for this allocation, overriding the global policy.
+    ///
+    /// See [`set_global_policy`](crate::set_global_policy).
+    pub fn policy<P: AlignmentPolicy + 'static>(mut self, policy: P) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Rounds the reserved size up to the next multiple of the huge page size if
+    /// no more than `max_waste_ratio` of the reserved memory would be unused.
+    ///
+    /// This allows near-miss sizes to still be backed by huge pages. The requested
+    /// size is reported by [`Memory::len`](crate::Memory::len), the reserved size by
+    /// [`Memory::capacity`](crate::Memory::capacity). Sizes are not rounded if
+    /// Transparent Huge Pages are unavailable.
+    ///
+    /// ## Arguments
+    /// * `max_waste_ratio` - The maximum fraction of unused reserved memory, between `0.0` and `1.0`.
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::{AllocationOptions, Memory, SystemInfo};
+    /// let options = AllocationOptions::new().round_to_huge_pages(0.5);
+    /// let memory = Memory::allocate_with(4 * 1024 * 1024 + 1, &options).unwrap();
+    /// assert_eq!(memory.len(), 4 * 1024 * 1024 + 1);
+    ///
+    /// if let Some(huge_page_size) = SystemInfo::get().transparent_huge_page_size() {
+    ///     assert_eq!(memory.capacity() % huge_page_size, 0);
+    /// }
+    /// ```
+    pub fn round_to_huge_pages(mut self, max_waste_ratio: f64) -> Self {
+        self.max_huge_page_waste = Some(max_waste_ratio.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Sets whether the memory is excluded from child processes created using `fork`.
+    ///
+    /// The memory is obtained from an anonymous mapping rather than the heap, and its
+    /// capacity is rounded up to a multiple of the page size. See [`Memory::set_dont_fork`](crate::Memory::set_dont_fork).
+    pub fn dont_fork(mut self, dont_fork: bool) -> Self {
+        self.dont_fork = dont_fork;
+        self
+    }
+
+    /// Sets whether the memory is zeroed out in child processes created using `fork`.
+    ///
+    /// The memory is obtained from an anonymous mapping rather than the heap, and its
+    /// capacity is rounded up to a multiple of the page size. See [`Memory::set_wipe_on_fork`](crate::Memory::set_wipe_on_fork).
+    pub fn wipe_on_fork(mut self, wipe_on_fork: bool) -> Self {
+        self.wipe_on_fork = wipe_on_fork;
+        self
+    }
+
+    /// Sets whether the memory is obtained from an anonymous mapping rather than the heap,
+    /// guaranteeing that it starts at a page boundary and does not share its pages with
+    /// other allocations. The capacity is rounded up to a multiple of the page size.
+    ///
+    /// This is required for changing the protection of the memory using [`Memory::protect`](crate::Memory::protect).
+    pub fn page_aligned(mut self, page_aligned: bool) -> Self {
+        self.page_aligned = page_aligned;
+        self
+    }
+
+    /// Sets whether the memory may be merged with identical pages by Kernel Samepage Merging.
+    ///
+    /// The memory is obtained from an anonymous mapping rather than the heap, and its
+    /// capacity is rounded up to a multiple of the page size. See [`Memory::set_mergeable`](crate::Memory::set_mergeable).
+    pub fn mergeable(mut self, mergeable: bool) -> Self {
+        self.mergeable = mergeable;
+        self
+    }
+
+    /// Sets the name of the memory shown as `[anon:<name>]` in `/proc/<pid>/maps`, if supported
+    /// by the kernel.
+    ///
+    /// The memory is obtained from an anonymous mapping rather than the heap, and its
+    /// capacity is rounded up to a multiple of the page size. See [`Memory::set_name`](crate::Memory::set_name).
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether to check that the host can back the memory before allocating it, failing
+    /// with [`AllocationError::InsufficientMemory`](crate::AllocationError::InsufficientMemory)
+    /// rather than risking the out-of-memory killer once the memory is touched.
+    ///
+    /// See [`Memory::can_allocate`](crate::Memory::can_allocate).
+    pub fn preflight(mut self, preflight: bool) -> Self {
+        self.preflight = preflight;
+        self
+    }
+
+    /// Gets whether the options require page-granular memory rather than heap memory.
+    pub(crate) fn requires_mapping(&self) -> bool {
+        self.page_aligned
+            || self.dont_fork
+            || self.wipe_on_fork
+            || self.mergeable
+            || self.name.is_some()
+    }
+
+    /// Gets the number of bytes to reserve for a request of `num_bytes`.
+    pub(crate) fn capacity_for(&self, num_bytes: usize, system: &SystemInfo) -> usize {
+        let (Some(max_waste), Some(huge_page_size)) = (
+            self.max_huge_page_waste,
+            system.transparent_huge_page_size(),
+        ) else {
+            return num_bytes;
+        };
+
+        let remainder = num_bytes % huge_page_size;
+        if remainder == 0 {
+            return num_bytes;
+        }
+
+        match num_bytes.checked_add(huge_page_size - remainder) {
+            Some(capacity) if (capacity - num_bytes) as f64 <= capacity as f64 * max_waste => {
+                capacity
+            }
+            _ => num_bytes,
+        }
+    }
+}
+
+impl Debug for AllocationOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AllocationOptions")
+            .field("sequential", &self.sequential)
+            .field("clear", &self.clear)
+            .field("custom_policy", &self.policy.is_some())
+            .field("max_huge_page_waste", &self.max_huge_page_waste)
+            .field("dont_fork", &self.dont_fork)
+            .field("wipe_on_fork", &self.wipe_on_fork)
+            .field("page_aligned", &self.page_aligned)
+            .field("mergeable", &self.mergeable)
+            .field("name", &self.name)
+            .field("preflight", &self.preflight)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::PageAligned;
+    use crate::system_info::{x86_64, ThpMode};
+
+    const TWO_MEGABYTES: usize = 2 * 1024 * 1024;
+
+    #[test]
+    fn defaults() {
+        let options = AllocationOptions::new();
+        assert!(!options.sequential);
+        assert!(!options.clear);
+        assert!(options.policy.is_none());
+        assert!(options.max_huge_page_waste.is_none());
+        assert!(options.name.is_none());
+        assert!(!options.requires_mapping());
+    }
+
+    #[test]
+    fn capacity_is_not_rounded_by_default() {
+        let options = AllocationOptions::new();
+        assert_eq!(options.capacity_for(4_194_305, &x86_64()), 4_194_305);
+    }
+
+    #[test]
+    fn capacity_is_rounded_to_huge_pages() {
+        let options = AllocationOptions::new().round_to_huge_pages(0.5);
+        assert_eq!(
+            options.capacity_for(4_194_305, &x86_64()),
+            3 * TWO_MEGABYTES
+        );
+        assert_eq!(
+            options.capacity_for(TWO_MEGABYTES, &x86_64()),
+            TWO_MEGABYTES
+        );
+    }
+
+    #[test]
+    fn capacity_is_not_rounded_above_waste_ratio() {
+        let options = AllocationOptions::new().round_to_huge_pages(0.25);
+
+        // 1 MiB requested, 1 MiB wasted.
+        assert_eq!(
+            options.capacity_for(TWO_MEGABYTES / 2, &x86_64()),
+            TWO_MEGABYTES / 2
+        );
+
+        // 7 MiB requested, 1 MiB wasted.
+        let seven_megabytes = 7 * TWO_MEGABYTES / 2;
+        assert_eq!(
+            options.capacity_for(seven_megabytes, &x86_64()),
+            4 * TWO_MEGABYTES
+        );
+    }
+
+    #[test]
+    fn capacity_is_not_rounded_without_thp() {
+        let system = SystemInfo {
+            thp_mode: ThpMode::Never,
+            ..x86_64()
+        };
+        let options = AllocationOptions::new().round_to_huge_pages(1.0);
+        assert_eq!(options.capacity_for(4_194_305, &system), 4_194_305);
+    }
+
+    #[test]
+    fn capacity_does_not_overflow() {
+        let options = AllocationOptions::new().round_to_huge_pages(1.0);
+        assert_eq!(options.capacity_for(usize::MAX, &x86_64()), usize::MAX);
+    }
+
+    #[test]
+    fn builder() {
+        let options = AllocationOptions::new()
+            .sequential(true)
+            .clear(true)
+            .policy(PageAligned)
+            .dont_fork(true)
+            .wipe_on_fork(true)
+            .page_aligned(true)
+            .mergeable(true)
+            .name("index");
+        assert!(options.sequential);
+        assert!(options.clear);
+        assert!(options.policy.is_some());
+        assert!(options.dont_fork);
+        assert!(options.wipe_on_fork);
+        assert!(options.page_aligned);
+        assert!(options.mergeable);
+        assert_eq!(options.name.as_deref(), Some("index"));
+        assert!(options.requires_mapping());
+    }
+}
@@ -0,0 +1,244 @@
+//! Provides the [`AlignmentPolicy`] trait deciding the alignment of an allocation,
+//! as well as a set of common policies.
+//!
+//! A policy can either be supplied per allocation through
+//! [`AllocationOptions::policy`](crate::AllocationOptions::policy), or set for the whole
+//! process through [`set_global_policy`]. If neither is done, the [`DefaultPolicy`] is used.
+//!
+//! # Structs
+//! - [`DefaultPolicy`]: Huge page alignment for exact multiples of the huge page size, SIMD alignment otherwise.
+//! - [`HugePageThreshold`]: Huge page alignment for all allocations of at least a given size.
+//! - [`PageAligned`]: Aligns all allocations to the regular page size.
+//! - [`CacheLineAligned`]: Aligns all allocations to the SIMD boundary, never uses huge pages.
+//! - [`DirectIoAligned`]: Aligns all allocations to the 4 KiB blocks required for `O_DIRECT` I/O.
+
+use crate::alignment::AlignmentHint;
+use crate::system_info::SystemInfo;
+use std::sync::{Arc, RwLock};
+
+/// The alignment required for `O_DIRECT` I/O on most file systems and block devices.
+const FOUR_KILOBYTES: usize = 4096;
+
+static GLOBAL_POLICY: RwLock<Option<Arc<dyn AlignmentPolicy>>> = RwLock::new(None);
+
+/// Decides the alignment of an allocation and whether huge pages should be used.
+///
+/// ## Example
+/// ```
+/// use alloc_madvise::{AlignmentHint, AlignmentPolicy, AllocationOptions, Memory, SystemInfo};
+///
+/// /// Aligns everything to 256 bytes.
+/// struct TwoHundredFiftySix;
+///
+/// impl AlignmentPolicy for TwoHundredFiftySix {
+///     fn hint(&self, num_bytes: usize, _system: &SystemInfo) -> AlignmentHint {
+///         AlignmentHint {
+///             alignment: if num_bytes == 0 { 0 } else { 256 },
+///             use_huge_pages: false,
+///         }
+///     }
+/// }
+///
+/// let options = AllocationOptions::new().policy(TwoHundredFiftySix);
+/// let memory = Memory::allocate_with(1000, &options).unwrap();
+/// assert_eq!(memory.alignment(), 256);
+/// assert_eq!(memory.to_ptr_const() as usize % 256, 0);
+/// ```
+pub trait AlignmentPolicy: Send + Sync {
+    /// Gets the alignment for an allocation of the specified number of bytes.
+    ///
+    /// ## Arguments
+    /// * `num_bytes` - The number of bytes to allocate; never zero.
+    /// * `system` - The page configuration of the host.
+    ///
+    /// ## Returns
+    /// An [`AlignmentHint`] outlining the alignment to use. The alignment must be
+    /// a power of two; otherwise, the allocation fails.
+    fn hint(&self, num_bytes: usize, system: &SystemInfo) -> AlignmentHint;
+}
+
+/// The default policy.
+///
+/// If Transparent Huge Pages are available and the number of bytes is a multiple of the
+/// huge page size, the allocation is aligned to the huge page size and huge pages are used.
+/// In any other case, allocations are aligned to the SIMD boundary.
+/// See [`AlignmentHint::with_system_info`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultPolicy;
+
+impl AlignmentPolicy for DefaultPolicy {
+    fn hint(&self, num_bytes: usize, system: &SystemInfo) -> AlignmentHint {
+        AlignmentHint::with_system_info(num_bytes, system)
+    }
+}
+
+/// Aligns allocations of at least `min_bytes` to the huge page size and uses huge pages for them,
+/// regardless of whether their size is a multiple of the huge page size.
+///
+/// Smaller allocations, or all allocations if Transparent Huge Pages are unavailable,
+/// are aligned to the SIMD boundary.
+///
+/// Heap allocations may share their last page with other allocations, so huge pages are only
+/// advised for the whole huge pages they contain; allocations smaller than a huge page do not
+/// use huge pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HugePageThreshold {
+    /// The minimum number of bytes for using huge pages.
+    pub min_bytes: usize,
+}
+
+impl HugePageThreshold {
+    /// Creates a policy using huge pages for allocations of at least `min_bytes`.
+    pub const fn new(min_bytes: usize) -> Self {
+        Self { min_bytes }
+    }
+}
+
+impl AlignmentPolicy for HugePageThreshold {
+    fn hint(&self, num_bytes: usize, system: &SystemInfo) -> AlignmentHint {
+        match system.transparent_huge_page_size() {
+            Some(huge_page_size) if num_bytes >= self.min_bytes => AlignmentHint {
+                alignment: huge_page_size,
+                use_huge_pages: true,
+            },
+            _ => AlignmentHint {
+                alignment: system.simd_alignment(),
+                use_huge_pages: false,
+            },
+        }
+    }
+}
+
+/// Aligns all allocations to the regular page size. Huge pages are not used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PageAligned;
+
+impl AlignmentPolicy for PageAligned {
+    fn hint(&self, _num_bytes: usize, system: &SystemInfo) -> AlignmentHint {
+        AlignmentHint {
+            alignment: system.page_size,
+            use_huge_pages: false,
+        }
+    }
+}
+
+/// Aligns all allocations to the SIMD boundary (the cache line size, but at least 64 bytes).
+/// Huge pages are not used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheLineAligned;
+
+impl AlignmentPolicy for CacheLineAligned {
+    fn hint(&self, _num_bytes: usize, system: &SystemInfo) -> AlignmentHint {
+        AlignmentHint {
+            alignment: system.simd_alignment(),
+            use_huge_pages: false,
+        }
+    }
+}
+
+/// Aligns all allocations to 4 KiB boundaries as required for `O_DIRECT` I/O,
+/// or to the page size if it is larger. Huge pages are not used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DirectIoAligned;
+
+impl AlignmentPolicy for DirectIoAligned {
+    fn hint(&self, _num_bytes: usize, system: &SystemInfo) -> AlignmentHint {
+        AlignmentHint {
+            alignment: system.page_size.max(FOUR_KILOBYTES),
+            use_huge_pages: false,
+        }
+    }
+}
+
+/// Sets the policy used for all allocations that do not specify one.
+///
+/// This affects allocations made through FFI as well.
+pub fn set_global_policy<P: AlignmentPolicy + 'static>(policy: P) {
+    *GLOBAL_POLICY.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(policy));
+}
+
+/// Restores the [`DefaultPolicy`] for all allocations that do not specify one.
+pub fn reset_global_policy() {
+    *GLOBAL_POLICY.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Gets the alignment hint for an allocation, using the specified policy if
+/// present and the global policy otherwise.
+pub(crate) fn resolve(num_bytes: usize, policy: Option<&dyn AlignmentPolicy>) -> AlignmentHint {
+    let system = SystemInfo::get();
+    if let Some(policy) = policy {
+        return policy.hint(num_bytes, system);
+    }
+
+    let global = GLOBAL_POLICY.read().unwrap_or_else(|e| e.into_inner());
+    match global.as_deref() {
+        Some(policy) => policy.hint(num_bytes, system),
+        None => DefaultPolicy.hint(num_bytes, system),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system_info::{x86_64, ThpMode};
+
+    const TWO_MEGABYTES: usize = 2 * 1024 * 1024;
+
+    #[test]
+    fn huge_page_threshold() {
+        let policy = HugePageThreshold::new(TWO_MEGABYTES / 2);
+
+        let hint = policy.hint(TWO_MEGABYTES / 2 + 1, &x86_64());
+        assert_eq!(hint.alignment, TWO_MEGABYTES);
+        assert!(hint.use_huge_pages);
+
+        let hint = policy.hint(TWO_MEGABYTES / 2 - 1, &x86_64());
+        assert_eq!(hint.alignment, 64);
+        assert!(!hint.use_huge_pages);
+    }
+
+    #[test]
+    fn huge_page_threshold_without_thp() {
+        let system = SystemInfo {
+            thp_mode: ThpMode::Never,
+            ..x86_64()
+        };
+        let hint = HugePageThreshold::new(0).hint(TWO_MEGABYTES, &system);
+        assert_eq!(hint.alignment, 64);
+        assert!(!hint.use_huge_pages);
+    }
+
+    #[test]
+    fn page_aligned() {
+        let hint = PageAligned.hint(TWO_MEGABYTES, &x86_64());
+        assert_eq!(hint.alignment, 4096);
+        assert!(!hint.use_huge_pages);
+    }
+
+    #[test]
+    fn cache_line_aligned() {
+        let hint = CacheLineAligned.hint(TWO_MEGABYTES, &x86_64());
+        assert_eq!(hint.alignment, 64);
+        assert!(!hint.use_huge_pages);
+    }
+
+    #[test]
+    fn direct_io_aligned() {
+        let hint = DirectIoAligned.hint(100, &x86_64());
+        assert_eq!(hint.alignment, 4096);
+        assert!(!hint.use_huge_pages);
+
+        let system = SystemInfo {
+            page_size: 64 * 1024,
+            ..x86_64()
+        };
+        let hint = DirectIoAligned.hint(100, &system);
+        assert_eq!(hint.alignment, 64 * 1024);
+    }
+
+    #[test]
+    fn resolve_prefers_explicit_policy() {
+        let hint = resolve(100, Some(&PageAligned));
+        assert_eq!(hint.alignment, SystemInfo::get().page_size);
+    }
+}
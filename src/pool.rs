@@ -0,0 +1,507 @@
+//! Provides [`MemoryPool`], a cache of freed [`Memory`] blocks for reuse.
+//!
+//! Allocating a large block and dropping it again pays for fresh page faults, and for huge
+//! pages possibly for compaction, every time. A pool keeps blocks returned to it and hands
+//! them out again for requests of the same size class, alignment and access pattern, such
+//! that their pages stay resident.
+//!
+//! Requests are rounded up to a size class: a multiple of the Transparent Huge Page size for
+//! requests of at least one huge page, and a multiple of the page size otherwise. Cached blocks
+//! keep counting towards the statistics, and allocation hooks are only invoked when blocks are
+//! actually obtained or released.
+//!
+//! # Structs
+//! - [`MemoryPool`]: A cache of freed blocks.
+//! - [`PoolOptions`]: Options controlling the blocks and how many of them are retained.
+//! - [`PooledMemory`]: A block that returns to its pool when dropped.
+
+use crate::alloc_result::AllocationError;
+use crate::backend::Backend;
+use crate::memory::{Memory, ALLOC_FLAGS_SEQUENTIAL};
+use crate::options::AllocationOptions;
+use crate::policy;
+use crate::system_info::SystemInfo;
+use crate::trace;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Options for a [`MemoryPool`].
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    pub(crate) allocation: AllocationOptions,
+    pub(crate) max_blocks_per_class: usize,
+    pub(crate) max_cached_bytes: usize,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            allocation: AllocationOptions::new(),
+            max_blocks_per_class: 4,
+            max_cached_bytes: usize::MAX,
+        }
+    }
+}
+
+impl PoolOptions {
+    /// Creates options retaining up to four blocks per size class, without a byte limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the options new blocks are allocated with. Whether the access pattern is sequential
+    /// and whether to zero out the memory is set per request, see [`MemoryPool::allocate`].
+    pub fn allocation(mut self, options: AllocationOptions) -> Self {
+        self.allocation = options;
+        self
+    }
+
+    /// Sets the maximum number of blocks retained per size class. Defaults to `4`.
+    pub fn max_blocks_per_class(mut self, max_blocks: usize) -> Self {
+        self.max_blocks_per_class = max_blocks;
+        self
+    }
+
+    /// Sets the maximum number of bytes retained across all size classes, counted by capacity.
+    /// Defaults to no limit.
+    pub fn max_cached_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_cached_bytes = max_bytes;
+        self
+    }
+}
+
+/// A cache of freed [`Memory`] blocks, handing them out again for requests of the same size
+/// class, alignment and access pattern.
+///
+/// Clones share the same cache, so a pool can be handed to multiple threads.
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::{MemoryPool, PoolOptions};
+/// let pool = MemoryPool::new(PoolOptions::new().max_blocks_per_class(2));
+///
+/// let block = pool.allocate(4 * 1024 * 1024, true, false).unwrap();
+/// let address = block.to_ptr_const();
+/// drop(block);
+/// assert_eq!(pool.cached_blocks(), 1);
+///
+/// let block = pool.allocate(4 * 1024 * 1024, true, true).unwrap();
+/// assert_eq!(block.to_ptr_const(), address);
+/// let data: &[u8] = block.as_ref();
+/// assert!(data.iter().all(|&byte| byte == 0));
+///
+/// drop(block);
+/// pool.trim();
+/// assert_eq!(pool.cached_bytes(), 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MemoryPool {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    options: PoolOptions,
+    cache: Mutex<Cache>,
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    blocks: HashMap<SizeClass, Vec<Memory>>,
+    bytes: usize,
+}
+
+// SAFETY: The cached memory is owned by the cache and only accessed while holding its lock.
+unsafe impl Send for Cache {}
+
+/// The properties a cached block has to match to be reused.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+struct SizeClass {
+    capacity: usize,
+    alignment: usize,
+    sequential: bool,
+}
+
+impl SizeClass {
+    fn of(memory: &Memory) -> Self {
+        Self {
+            capacity: memory.capacity,
+            alignment: memory.alignment,
+            sequential: memory.flags & ALLOC_FLAGS_SEQUENTIAL != 0,
+        }
+    }
+}
+
+impl MemoryPool {
+    /// Creates an empty pool.
+    pub fn new(options: PoolOptions) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                options,
+                cache: Mutex::new(Cache::default()),
+            }),
+        }
+    }
+
+    /// Takes a cached block of the size class of `num_bytes`, or allocates a new one.
+    ///
+    /// The block is returned to the pool when dropped. Its length is `num_bytes`,
+    /// its capacity the size of the size class.
+    ///
+    /// ## Arguments
+    /// * `num_bytes` - The number of bytes to allocate.
+    /// * `sequential` - Whether or not the memory access pattern is sequential mostly.
+    /// * `clear` - Whether or not to zero out the memory, including reused blocks.
+    pub fn allocate(
+        &self,
+        num_bytes: usize,
+        sequential: bool,
+        clear: bool,
+    ) -> Result<PooledMemory, AllocationError> {
+        if num_bytes == 0 {
+            return Err(AllocationError::EmptyAllocation);
+        }
+
+        let size = Self::size_class(num_bytes).ok_or(AllocationError::OsError(libc::ENOMEM))?;
+        let class = self.class_for(size, sequential);
+        let cached = {
+            let mut cache = self.shared.lock();
+            let memory = cache.blocks.get_mut(&class).and_then(Vec::pop);
+            if let Some(memory) = &memory {
+                cache.bytes -= memory.capacity;
+            }
+            memory
+        };
+
+        let memory = match cached {
+            Some(mut memory) => {
+                if clear {
+                    // SAFETY: The memory is writable and owned, as protected blocks are not cached.
+                    unsafe { std::ptr::write_bytes(memory.address.cast::<u8>(), 0, num_bytes) };
+                }
+                memory.num_bytes = num_bytes;
+                memory
+            }
+            None => {
+                let options = self
+                    .shared
+                    .options
+                    .allocation
+                    .clone()
+                    .sequential(sequential)
+                    .clear(clear);
+                let mut memory = Memory::allocate_with(size, &options)?;
+                memory.truncate(num_bytes);
+                memory
+            }
+        };
+
+        Ok(PooledMemory {
+            class,
+            flags: memory.flags,
+            memory,
+            pool: Arc::clone(&self.shared),
+        })
+    }
+
+    /// Returns the number of blocks currently cached.
+    pub fn cached_blocks(&self) -> usize {
+        self.shared.lock().blocks.values().map(Vec::len).sum()
+    }
+
+    /// Returns the number of bytes currently cached, counted by capacity.
+    pub fn cached_bytes(&self) -> usize {
+        self.shared.lock().bytes
+    }
+
+    /// Frees all cached blocks, unmapping them or returning them to the heap.
+    pub fn trim(&self) {
+        let blocks = {
+            let mut cache = self.shared.lock();
+            cache.bytes = 0;
+            std::mem::take(&mut cache.blocks)
+        };
+        drop(blocks);
+    }
+
+    /// Releases the physical pages of the cached blocks obtained from anonymous mappings using
+    /// `MADV_DONTNEED`, keeping the blocks cached. Their contents read as zero when reused.
+    /// Other cached blocks are freed.
+    ///
+    /// This returns memory to the host while keeping the address ranges for reuse.
+    pub fn discard(&self) {
+        let mut freed = Vec::new();
+        {
+            let mut cache = self.shared.lock();
+            let mut bytes = 0;
+            for blocks in cache.blocks.values_mut() {
+                let mut index = 0;
+                while index < blocks.len() {
+                    if discard_pages(&blocks[index]) {
+                        bytes += blocks[index].capacity;
+                        index += 1;
+                    } else {
+                        freed.push(blocks.swap_remove(index));
+                    }
+                }
+            }
+            cache.blocks.retain(|_, blocks| !blocks.is_empty());
+            cache.bytes = bytes;
+        }
+        drop(freed);
+    }
+
+    /// Rounds a request up to its size class.
+    fn size_class(num_bytes: usize) -> Option<usize> {
+        let system = SystemInfo::get();
+        let granularity = match system.transparent_huge_page_size() {
+            Some(huge_page_size) if num_bytes >= huge_page_size => huge_page_size,
+            _ => system.page_size,
+        };
+        num_bytes.checked_next_multiple_of(granularity)
+    }
+
+    /// Gets the class of the block [`Memory::allocate_with`] obtains for the size class.
+    fn class_for(&self, size: usize, sequential: bool) -> SizeClass {
+        let options = &self.shared.options.allocation;
+        let capacity = options.capacity_for(size, SystemInfo::get());
+        let mut alignment = policy::resolve(capacity, options.policy.as_deref()).alignment;
+        if options.requires_mapping() {
+            alignment = alignment.max(SystemInfo::get().page_size);
+        }
+        SizeClass {
+            capacity,
+            alignment,
+            sequential,
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Caches memory handed out for the class with the specified flags if it is unchanged
+    /// and the retention limits allow, and frees it otherwise.
+    fn put(&self, mut memory: Memory, class: SizeClass, flags: u32) {
+        // Blocks whose advice or protection was changed, or that were allocated under a different
+        // alignment policy than the class was looked up with, would not match their class.
+        if memory.address.is_null() || memory.flags != flags || SizeClass::of(&memory) != class {
+            return;
+        }
+        // The name is not tracked in the flags of named blocks, so it is restored.
+        if let Some(name) = &self.options.allocation.name {
+            if memory.set_name(name).is_err() {
+                return;
+            }
+        }
+
+        let mut cache = self.lock();
+        let bytes = cache.bytes.saturating_add(memory.capacity);
+        if bytes > self.options.max_cached_bytes {
+            return;
+        }
+        let blocks = cache.blocks.entry(class).or_default();
+        if blocks.len() < self.options.max_blocks_per_class {
+            blocks.push(memory);
+            cache.bytes = bytes;
+        }
+    }
+}
+
+/// Releases the physical pages of an anonymous mapping, returning whether it succeeded.
+fn discard_pages(memory: &Memory) -> bool {
+    memory.backend == Backend::Anonymous
+        // SAFETY: The whole mapping is owned by the memory.
+        && unsafe { trace::madvise(memory.address, memory.capacity, libc::MADV_DONTNEED) } == 0
+}
+
+/// A block obtained from a [`MemoryPool`], which is returned to the pool when dropped.
+///
+/// Dereferences to the [`Memory`]. Blocks whose advice, protection or name was changed, e.g.
+/// using [`Memory::advise`], [`Memory::set_dont_fork`] or [`Memory::protect`], as well as
+/// blocks exceeding the retention limits of the pool are freed instead.
+#[derive(Debug)]
+pub struct PooledMemory {
+    class: SizeClass,
+    flags: u32,
+    memory: Memory,
+    pool: Arc<Shared>,
+}
+
+impl PooledMemory {
+    /// Detaches the memory from the pool, such that it is freed rather than cached when dropped.
+    pub fn into_inner(mut self) -> Memory {
+        std::mem::take(&mut self.memory)
+    }
+}
+
+impl Deref for PooledMemory {
+    type Target = Memory;
+
+    fn deref(&self) -> &Self::Target {
+        &self.memory
+    }
+}
+
+impl DerefMut for PooledMemory {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.memory
+    }
+}
+
+impl Drop for PooledMemory {
+    fn drop(&mut self) {
+        self.pool
+            .put(std::mem::take(&mut self.memory), self.class, self.flags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_reused_by_class() {
+        let pool = MemoryPool::new(PoolOptions::new());
+        let page_size = SystemInfo::get().page_size;
+
+        let block = pool.allocate(1000, false, false).unwrap();
+        assert_eq!(block.len(), 1000);
+        assert_eq!(block.capacity(), page_size);
+        let address = block.to_ptr_const();
+        drop(block);
+        assert_eq!(pool.cached_bytes(), page_size);
+
+        // A different access pattern does not match the cached block.
+        let sequential = pool.allocate(page_size, true, false).unwrap();
+        assert_eq!(pool.cached_blocks(), 1);
+
+        let reused = pool.allocate(page_size, false, false).unwrap();
+        assert_eq!(reused.to_ptr_const(), address);
+        assert_eq!(reused.len(), page_size);
+        assert_eq!(pool.cached_blocks(), 0);
+
+        drop(sequential);
+        drop(reused);
+        assert_eq!(pool.cached_blocks(), 2);
+    }
+
+    #[test]
+    fn reused_blocks_are_cleared() {
+        let pool = MemoryPool::new(PoolOptions::new());
+        let mut block = pool.allocate(4096, false, false).unwrap();
+        let data: &mut [u8] = block.as_mut();
+        data.fill(0xAB);
+        drop(block);
+
+        let block = pool.allocate(4096, false, true).unwrap();
+        let data: &[u8] = block.as_ref();
+        assert!(data.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn retention_limits() {
+        let page_size = SystemInfo::get().page_size;
+        let pool = MemoryPool::new(
+            PoolOptions::new()
+                .max_blocks_per_class(2)
+                .max_cached_bytes(3 * page_size),
+        );
+
+        let blocks: Vec<_> = (0..3)
+            .map(|_| pool.allocate(page_size, false, false).unwrap())
+            .collect();
+        drop(blocks);
+        assert_eq!(pool.cached_blocks(), 2);
+
+        let large = pool.allocate(2 * page_size, false, false).unwrap();
+        drop(large);
+        assert_eq!(pool.cached_bytes(), 2 * page_size);
+
+        let detached = pool.allocate(page_size, false, false).unwrap().into_inner();
+        drop(detached);
+        assert_eq!(pool.cached_blocks(), 1);
+    }
+
+    #[test]
+    fn trim_and_discard() {
+        let options = AllocationOptions::new().page_aligned(true);
+        let pool = MemoryPool::new(PoolOptions::new().allocation(options));
+        let page_size = SystemInfo::get().page_size;
+
+        let mut mapped = pool.allocate(page_size, false, false).unwrap();
+        assert_eq!(mapped.backend(), Backend::Anonymous);
+        let data: &mut [u8] = mapped.as_mut();
+        data.fill(0xAB);
+        drop(mapped);
+
+        pool.discard();
+        assert_eq!(pool.cached_blocks(), 1);
+        let mapped = pool.allocate(page_size, false, false).unwrap();
+        let data: &[u8] = mapped.as_ref();
+        assert!(data.iter().all(|&byte| byte == 0));
+        drop(mapped);
+
+        pool.trim();
+        assert_eq!(pool.cached_blocks(), 0);
+        assert_eq!(pool.cached_bytes(), 0);
+    }
+
+    #[test]
+    fn heap_blocks_are_freed_on_discard() {
+        let pool = MemoryPool::new(PoolOptions::new());
+        drop(pool.allocate(1024, false, false).unwrap());
+        pool.discard();
+        assert_eq!(pool.cached_blocks(), 0);
+        assert_eq!(pool.cached_bytes(), 0);
+    }
+
+    #[test]
+    fn changed_blocks_are_not_cached() {
+        let options = AllocationOptions::new().page_aligned(true);
+        let pool = MemoryPool::new(PoolOptions::new().allocation(options));
+
+        let mut advised = pool.allocate(1024, false, false).unwrap();
+        advised.advise(crate::Advice::Random).unwrap();
+        drop(advised);
+
+        let mut named = pool.allocate(1024, false, false).unwrap();
+        named.set_name("pooled").unwrap();
+        drop(named);
+
+        let mut dont_fork = pool.allocate(1024, false, false).unwrap();
+        dont_fork.set_dont_fork(true).unwrap();
+        drop(dont_fork);
+        assert_eq!(pool.cached_blocks(), 0);
+
+        // Restoring the previous state is fine.
+        let mut advised = pool.allocate(1024, true, false).unwrap();
+        advised.advise(crate::Advice::Normal).unwrap();
+        advised.advise(crate::Advice::Sequential).unwrap();
+        drop(advised);
+        assert_eq!(pool.cached_blocks(), 1);
+    }
+
+    #[test]
+    fn named_blocks_are_renamed() {
+        let options = AllocationOptions::new().name("pool");
+        let pool = MemoryPool::new(PoolOptions::new().allocation(options));
+        let mut block = pool.allocate(1024, false, false).unwrap();
+        block.set_name("other").unwrap();
+        drop(block);
+        assert_eq!(pool.cached_blocks(), 1);
+    }
+
+    #[test]
+    fn protected_blocks_are_not_cached() {
+        let options = AllocationOptions::new().page_aligned(true);
+        let pool = MemoryPool::new(PoolOptions::new().allocation(options));
+        let mut block = pool.allocate(1024, false, false).unwrap();
+        block.protect(0..1024, crate::Protection::ReadOnly).unwrap();
+        drop(block);
+        assert_eq!(pool.cached_blocks(), 0);
+    }
+}
@@ -0,0 +1,308 @@
+//! Provides [`Memory::can_allocate`], which checks whether the host can back an allocation
+//! before it is made.
+//!
+//! Linux overcommits memory by default: a large allocation usually succeeds, and the process
+//! is only killed by the out-of-memory killer once the pages are touched. A preflight check
+//! compares the request against the memory available on the host (`/proc/meminfo`), the
+//! remaining memory of the cgroup v2 hierarchy of the process (`memory.max` and
+//! `memory.current`) and, with strict overcommit accounting (`/proc/sys/vm/overcommit_memory`),
+//! the commit limit. Setting [`AllocationOptions::preflight`](crate::AllocationOptions::preflight)
+//! runs the check as part of an allocation, which then fails with
+//! [`AllocationError::InsufficientMemory`].
+//!
+//! The check is a snapshot; other processes may use up the memory before it is touched.
+//!
+//! # Structs
+//! - [`Preflight`]: The memory available for an allocation, and the verdict.
+//!
+//! # Enums
+//! - [`PreflightVerdict`]: Whether an allocation is expected to fit.
+//! - [`Overcommit`]: The overcommit accounting mode of the host.
+
+use crate::alloc_result::AllocationError;
+use crate::cgroup::{self, CgroupMemory};
+use crate::memory::Memory;
+use crate::system_info::read_usize;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+const MEMINFO: &str = "/proc/meminfo";
+const OVERCOMMIT_MEMORY: &str = "/proc/sys/vm/overcommit_memory";
+
+/// The overcommit accounting mode of the host.
+///
+/// See `/proc/sys/vm/overcommit_memory`.
+#[repr(u32)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Overcommit {
+    /// Obvious overcommits of address space are refused.
+    Heuristic = 0,
+    /// Allocations never fail for lack of memory.
+    Always = 1,
+    /// Allocations fail once the commit limit (`CommitLimit`) is reached.
+    Never = 2,
+}
+
+/// Whether an allocation is expected to fit, or which limit it exceeds.
+#[repr(u32)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum PreflightVerdict {
+    /// The allocation fits within all known limits.
+    Fits = 0,
+    /// The allocation exceeds the remaining commit limit under strict overcommit accounting.
+    ExceedsCommitLimit = 1,
+    /// The allocation exceeds the remaining memory of a cgroup of the process.
+    ExceedsCgroupLimit = 2,
+    /// The allocation exceeds the memory available on the host.
+    ExceedsAvailableMemory = 3,
+}
+
+impl Display for PreflightVerdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PreflightVerdict::Fits => "fits",
+            PreflightVerdict::ExceedsCommitLimit => "exceeds the commit limit",
+            PreflightVerdict::ExceedsCgroupLimit => "exceeds the cgroup memory limit",
+            PreflightVerdict::ExceedsAvailableMemory => "exceeds the available memory",
+        })
+    }
+}
+
+/// The memory available for an allocation, and whether the allocation is expected to fit.
+///
+/// Values that cannot be read, e.g. because the process is not in a cgroup with a memory
+/// limit, are `None` and do not affect the verdict.
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::{Memory, PreflightVerdict};
+/// let preflight = Memory::can_allocate(1024);
+/// assert_eq!(preflight.verdict, PreflightVerdict::Fits);
+///
+/// let preflight = Memory::can_allocate(usize::MAX);
+/// if preflight.mem_available.is_some() {
+///     assert!(!preflight.fits());
+/// }
+/// ```
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Preflight {
+    /// The number of bytes checked.
+    pub num_bytes: usize,
+    /// Whether the allocation is expected to fit.
+    pub verdict: PreflightVerdict,
+    /// The memory available on the host without swapping (`MemAvailable`), in bytes.
+    pub mem_available: Option<usize>,
+    /// The free memory of the default huge TLB pool (`HugePages_Free`), in bytes.
+    pub huge_pages_free: Option<usize>,
+    /// The `memory.max` of the cgroup with the least remaining memory, in bytes.
+    pub cgroup_limit: Option<usize>,
+    /// The `memory.current` of the cgroup with the least remaining memory, in bytes.
+    pub cgroup_usage: Option<usize>,
+    /// The overcommit accounting mode.
+    pub overcommit: Option<Overcommit>,
+    /// The memory that can still be committed (`CommitLimit` minus `Committed_AS`), in bytes.
+    /// Only enforced with [`Overcommit::Never`].
+    pub commit_available: Option<usize>,
+}
+
+impl Preflight {
+    /// Checks whether `num_bytes` can be allocated.
+    fn read(num_bytes: usize) -> Self {
+        let meminfo = fs::read_to_string(MEMINFO).unwrap_or_default();
+        let overcommit = read_usize(Path::new(OVERCOMMIT_MEMORY));
+        Self::from_sources(num_bytes, &meminfo, overcommit, &cgroup::limits())
+    }
+
+    fn from_sources(
+        num_bytes: usize,
+        meminfo: &str,
+        overcommit: Option<usize>,
+        cgroups: &[CgroupMemory],
+    ) -> Self {
+        // Values are listed as `Name:   value kB`, except for page counts.
+        let field = |name: &str| -> Option<usize> {
+            meminfo.lines().find_map(|line| {
+                let value = line.strip_prefix(name)?.strip_prefix(':')?;
+                value.split_whitespace().next()?.parse().ok()
+            })
+        };
+        let kib = |name: &str| field(name).map(|value| value.saturating_mul(1024));
+
+        let huge_pages_free = field("HugePages_Free")
+            .zip(kib("Hugepagesize"))
+            .map(|(pages, page_size)| pages.saturating_mul(page_size));
+        let commit_available = kib("CommitLimit")
+            .zip(kib("Committed_AS"))
+            .map(|(limit, committed)| limit.saturating_sub(committed));
+        let overcommit = overcommit.map(|mode| match mode {
+            0 => Overcommit::Heuristic,
+            1 => Overcommit::Always,
+            _ => Overcommit::Never,
+        });
+        let cgroup = cgroups.iter().min_by_key(|cgroup| cgroup.remaining());
+
+        let mut preflight = Self {
+            num_bytes,
+            verdict: PreflightVerdict::Fits,
+            mem_available: kib("MemAvailable"),
+            huge_pages_free,
+            cgroup_limit: cgroup.map(|cgroup| cgroup.max),
+            cgroup_usage: cgroup.map(|cgroup| cgroup.current),
+            overcommit,
+            commit_available,
+        };
+        preflight.verdict = preflight.judge();
+        preflight
+    }
+
+    /// Compares the request to the limits, starting with the ones failing the allocation itself.
+    fn judge(&self) -> PreflightVerdict {
+        let exceeds = |available: Option<usize>| available.is_some_and(|a| self.num_bytes > a);
+        if self.overcommit == Some(Overcommit::Never) && exceeds(self.commit_available) {
+            PreflightVerdict::ExceedsCommitLimit
+        } else if exceeds(self.cgroup_remaining()) {
+            PreflightVerdict::ExceedsCgroupLimit
+        } else if exceeds(self.mem_available) {
+            PreflightVerdict::ExceedsAvailableMemory
+        } else {
+            PreflightVerdict::Fits
+        }
+    }
+
+    /// Returns whether the allocation is expected to fit.
+    pub fn fits(&self) -> bool {
+        self.verdict == PreflightVerdict::Fits
+    }
+
+    /// Returns whether the allocation fits into the free huge pages of the default huge TLB pool,
+    /// as required for [`MemfdOptions::huge_tlb`](crate::MemfdOptions::huge_tlb).
+    pub fn fits_huge_pages(&self) -> bool {
+        self.huge_pages_free
+            .is_some_and(|free| self.num_bytes <= free)
+    }
+
+    /// Returns the remaining memory of the cgroup with the least remaining memory, in bytes.
+    pub fn cgroup_remaining(&self) -> Option<usize> {
+        Some(self.cgroup_limit?.saturating_sub(self.cgroup_usage?))
+    }
+
+    /// Converts the verdict into a result.
+    ///
+    /// ## Errors
+    /// Fails with [`AllocationError::InsufficientMemory`] unless the allocation fits.
+    pub fn check(&self) -> Result<(), AllocationError> {
+        match self.verdict {
+            PreflightVerdict::Fits => Ok(()),
+            verdict => Err(AllocationError::InsufficientMemory(verdict)),
+        }
+    }
+}
+
+impl Memory {
+    /// Checks whether `num_bytes` can be allocated without exceeding the memory available on
+    /// the host, the memory limit of the cgroup of the process, or the commit limit.
+    ///
+    /// This does not allocate. See [`AllocationOptions::preflight`](crate::AllocationOptions::preflight)
+    /// for checking as part of an allocation.
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::Memory;
+    /// let size = 20 << 30;
+    /// let preflight = Memory::can_allocate(size);
+    /// if !preflight.fits() {
+    ///     eprintln!("cannot allocate {size} bytes: {}", preflight.verdict);
+    /// }
+    /// ```
+    pub fn can_allocate(num_bytes: usize) -> Preflight {
+        Preflight::read(num_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMINFO: &str = "MemTotal:       16384000 kB\n\
+                           MemFree:         1024000 kB\n\
+                           MemAvailable:    8192000 kB\n\
+                           CommitLimit:    10240000 kB\n\
+                           Committed_AS:    6144000 kB\n\
+                           HugePages_Total:      16\n\
+                           HugePages_Free:        8\n\
+                           Hugepagesize:       2048 kB\n";
+
+    const KIB: usize = 1024;
+
+    #[test]
+    fn meminfo_is_parsed() {
+        let preflight = Preflight::from_sources(KIB, MEMINFO, Some(0), &[]);
+        assert_eq!(preflight.mem_available, Some(8_192_000 * KIB));
+        assert_eq!(preflight.huge_pages_free, Some(16 << 20));
+        assert_eq!(preflight.commit_available, Some(4_096_000 * KIB));
+        assert_eq!(preflight.overcommit, Some(Overcommit::Heuristic));
+        assert_eq!(preflight.cgroup_remaining(), None);
+        assert!(preflight.fits());
+        assert!(preflight.fits_huge_pages());
+    }
+
+    #[test]
+    fn verdicts() {
+        let check = |num_bytes, overcommit, cgroups: &[CgroupMemory]| {
+            Preflight::from_sources(num_bytes, MEMINFO, Some(overcommit), cgroups).verdict
+        };
+
+        // The commit limit only applies with strict accounting.
+        let committable = 4_096_000 * KIB;
+        assert_eq!(check(committable + 1, 0, &[]), PreflightVerdict::Fits);
+        assert_eq!(
+            check(committable + 1, 2, &[]),
+            PreflightVerdict::ExceedsCommitLimit
+        );
+
+        let cgroups = [
+            CgroupMemory {
+                max: 4 << 30,
+                current: 1 << 30,
+            },
+            CgroupMemory {
+                max: 2 << 30,
+                current: 1 << 30,
+            },
+        ];
+        assert_eq!(check(1 << 30, 1, &cgroups), PreflightVerdict::Fits);
+        assert_eq!(
+            check((1 << 30) + 1, 1, &cgroups),
+            PreflightVerdict::ExceedsCgroupLimit
+        );
+
+        assert_eq!(
+            check(8_192_000 * KIB + 1, 1, &[]),
+            PreflightVerdict::ExceedsAvailableMemory
+        );
+    }
+
+    #[test]
+    fn missing_values_are_ignored() {
+        let preflight = Preflight::from_sources(usize::MAX, "", None, &[]);
+        assert_eq!(preflight.mem_available, None);
+        assert_eq!(preflight.overcommit, None);
+        assert!(preflight.fits());
+        assert!(!preflight.fits_huge_pages());
+    }
+
+    #[test]
+    fn preflight_rejects_excessive_allocations() {
+        let options = crate::AllocationOptions::new().preflight(true);
+        let memory = Memory::allocate_with(1024, &options).unwrap();
+        assert_eq!(memory.len(), 1024);
+
+        if Memory::can_allocate(usize::MAX / 2).mem_available.is_some() {
+            assert!(matches!(
+                Memory::allocate_with(usize::MAX / 2, &options),
+                Err(AllocationError::InsufficientMemory(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Provides memory protection for [`Memory`] using `mprotect(2)`, and [`FrozenMemory`].
+//!
+//! Protection applies to whole pages. Since heap memory shares its pages with other
+//! allocations, it can only be applied to page-granular memory, which is obtained by
+//! setting [`AllocationOptions::page_aligned`](crate::AllocationOptions::page_aligned),
+//! or by mapping files and shared memory.
+//!
+//! # Enums
+//! - [`Protection`]: The access permitted to protected memory.
+//!
+//! # Structs
+//! - [`FrozenMemory`]: Read-only memory that can be shared across threads.
+
+use crate::alloc_result::AllocationError;
+use crate::backend::Backend;
+use crate::memory::{Memory, ALLOC_FLAGS_PROTECTED, ALLOC_FLAGS_READ_ONLY};
+use crate::system_info::SystemInfo;
+use std::ops::{Deref, Range};
+
+/// The access permitted to protected memory.
+#[repr(u32)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Protection {
+    /// The memory can only be read (`PROT_READ`).
+    ReadOnly = 0,
+    /// The memory can be read and written (`PROT_READ | PROT_WRITE`).
+    ReadWrite = 1,
+}
+
+impl Protection {
+    fn to_prot(self) -> libc::c_int {
+        match self {
+            Protection::ReadOnly => libc::PROT_READ,
+            Protection::ReadWrite => libc::PROT_READ | libc::PROT_WRITE,
+        }
+    }
+}
+
+impl Memory {
+    /// Changes the protection of a byte range of the memory.
+    ///
+    /// The start of the range is required to be a multiple of the page size; its end
+    /// is rounded up to the next page. Making any range read-only marks the memory as
+    /// [read-only](Memory::is_read_only), such that obtaining mutable slices panics
+    /// rather than faulting. The mark is removed when all of the memory is made read-write.
+    ///
+    /// ## Errors
+    /// Fails with `EINVAL` for heap memory, whose pages are shared with other allocations,
+    /// or if the start of the range is not page-aligned. Fails with `EACCES` when making
+    /// a read-only file mapping read-write.
+    ///
+    /// ## Panics
+    /// Panics if the range exceeds the length of the memory.
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::{AllocationOptions, Memory, Protection};
+    /// let options = AllocationOptions::new().page_aligned(true);
+    /// let mut memory = Memory::allocate_with(8192, &options).unwrap();
+    ///
+    /// memory.protect(0..4096, Protection::ReadOnly).unwrap();
+    /// assert!(memory.is_read_only());
+    ///
+    /// memory.protect(0..8192, Protection::ReadWrite).unwrap();
+    /// assert!(!memory.is_read_only());
+    /// ```
+    pub fn protect(
+        &mut self,
+        range: Range<usize>,
+        protection: Protection,
+    ) -> Result<(), AllocationError> {
+        assert!(
+            range.start <= range.end && range.end <= self.num_bytes,
+            "Range {range:?} exceeds length {}",
+            self.num_bytes
+        );
+
+        if self.backend == Backend::Heap {
+            return Err(AllocationError::OsError(libc::EINVAL));
+        }
+
+        let page_size = SystemInfo::get().page_size;
+        if range.start % page_size != 0 {
+            return Err(AllocationError::OsError(libc::EINVAL));
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        // The end cannot overflow, since mappings span whole pages.
+        let end = range.end.next_multiple_of(page_size).min(self.capacity);
+
+        // SAFETY: The range lies within the mapping and starts at a page boundary.
+        let result = unsafe {
+            libc::mprotect(
+                self.address.cast::<u8>().add(range.start).cast(),
+                end - range.start,
+                protection.to_prot(),
+            )
+        };
+        if result != 0 {
+            return Err(AllocationError::last_os_error());
+        }
+
+        self.flags |= ALLOC_FLAGS_PROTECTED;
+        match protection {
+            Protection::ReadOnly => self.flags |= ALLOC_FLAGS_READ_ONLY,
+            Protection::ReadWrite if range.start == 0 && range.end == self.num_bytes => {
+                self.flags &= !ALLOC_FLAGS_READ_ONLY;
+            }
+            Protection::ReadWrite => {}
+        }
+        Ok(())
+    }
+
+    /// Makes all of the memory read-only, e.g. after building a lookup table.
+    ///
+    /// The memory is freed if its protection cannot be changed.
+    /// See [`Memory::protect`] for the requirements.
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::{AllocationOptions, FrozenMemory, Memory};
+    /// let options = AllocationOptions::new().page_aligned(true);
+    /// let mut memory = Memory::allocate_with(1024, &options).unwrap();
+    /// let table: &mut [u32] = memory.as_mut();
+    /// table[0] = 42;
+    ///
+    /// let frozen = std::sync::Arc::new(memory.freeze().unwrap());
+    /// let reader = std::sync::Arc::clone(&frozen);
+    /// std::thread::spawn(move || {
+    ///     let frozen: &FrozenMemory = &reader;
+    ///     let table: &[u32] = frozen.as_ref();
+    ///     assert_eq!(table[0], 42);
+    /// })
+    /// .join()
+    /// .unwrap();
+    /// ```
+    pub fn freeze(mut self) -> Result<FrozenMemory, AllocationError> {
+        let len = self.num_bytes;
+        self.protect(0..len, Protection::ReadOnly)?;
+        Ok(FrozenMemory { memory: self })
+    }
+
+    /// Restores read-write access to protected memory before it is released.
+    ///
+    /// ## Safety
+    /// The memory is required to be page-granular and protected using [`Memory::protect`].
+    pub(crate) unsafe fn unprotect(&mut self) {
+        libc::mprotect(
+            self.address,
+            self.capacity,
+            libc::PROT_READ | libc::PROT_WRITE,
+        );
+        self.flags &= !(ALLOC_FLAGS_PROTECTED | ALLOC_FLAGS_READ_ONLY);
+    }
+}
+
+/// Memory that was made read-only using [`Memory::freeze`].
+///
+/// Dereferences to the [`Memory`], providing the same immutable slice views. Since the
+/// contents can no longer change, it can be shared across threads.
+#[derive(Debug)]
+pub struct FrozenMemory {
+    memory: Memory,
+}
+
+// SAFETY: The memory is owned by the instance and was made read-only.
+unsafe impl Send for FrozenMemory {}
+
+// SAFETY: The memory cannot be written to, so shared references can be used concurrently.
+unsafe impl Sync for FrozenMemory {}
+
+impl FrozenMemory {
+    /// Makes the memory read-write again. The memory is freed if its protection cannot be changed.
+    ///
+    /// ## Safety
+    /// Code that obtained pointers into the memory while it was frozen, e.g. through
+    /// [`Memory::to_ptr_const`], is required to no longer rely on the contents being immutable.
+    pub unsafe fn unfreeze(self) -> Result<Memory, AllocationError> {
+        let mut memory = self.memory;
+        let len = memory.num_bytes;
+        memory.protect(0..len, Protection::ReadWrite)?;
+        Ok(memory)
+    }
+}
+
+impl Deref for FrozenMemory {
+    type Target = Memory;
+
+    fn deref(&self) -> &Self::Target {
+        &self.memory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::AllocationOptions;
+    use crate::test_support::maps_line;
+
+    /// Gets the permissions of the mapping containing `address` from `/proc/self/maps`.
+    fn permissions(address: *const u8) -> String {
+        let line = maps_line(address.cast());
+        line.split(' ').nth(1).unwrap().to_string()
+    }
+
+    fn allocate_pages(num_pages: usize) -> Memory {
+        let page_size = SystemInfo::get().page_size;
+        let options = AllocationOptions::new().page_aligned(true);
+        Memory::allocate_with(num_pages * page_size, &options).unwrap()
+    }
+
+    #[test]
+    fn protect_partial_range() {
+        let page_size = SystemInfo::get().page_size;
+        let mut memory = allocate_pages(2);
+        let ptr = memory.to_ptr_const().cast::<u8>();
+
+        memory
+            .protect(page_size..page_size + 1, Protection::ReadOnly)
+            .unwrap();
+        assert!(memory.is_read_only());
+        assert_eq!(permissions(ptr), "rw-p");
+        assert_eq!(permissions(unsafe { ptr.add(page_size) }), "r--p");
+
+        memory
+            .protect(page_size..2 * page_size, Protection::ReadWrite)
+            .unwrap();
+        assert!(memory.is_read_only(), "only a part was made read-write");
+
+        memory
+            .protect(0..2 * page_size, Protection::ReadWrite)
+            .unwrap();
+        assert!(!memory.is_read_only());
+        assert_eq!(permissions(unsafe { ptr.add(page_size) }), "rw-p");
+    }
+
+    #[test]
+    fn protect_requires_page_aligned_start() {
+        let mut memory = allocate_pages(1);
+        assert_eq!(
+            memory.protect(1..2, Protection::ReadOnly),
+            Err(AllocationError::OsError(libc::EINVAL))
+        );
+        assert!(!memory.is_read_only());
+    }
+
+    #[test]
+    fn protect_heap_memory_fails() {
+        let mut memory = Memory::allocate(8192, false, false).unwrap();
+        assert_eq!(
+            memory.protect(0..8192, Protection::ReadOnly),
+            Err(AllocationError::OsError(libc::EINVAL))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds length")]
+    fn protect_out_of_bounds_panics() {
+        let mut memory = allocate_pages(1);
+        let _ = memory.protect(0..usize::MAX, Protection::ReadOnly);
+    }
+
+    #[test]
+    fn freeze_and_unfreeze() {
+        let mut memory = allocate_pages(1);
+        let data: &mut [u8] = memory.as_mut();
+        data[0] = 42;
+
+        let frozen = memory.freeze().unwrap();
+        assert!(frozen.is_read_only());
+        assert_eq!(permissions(frozen.to_ptr_const().cast()), "r--p");
+        let view: &[u8] = frozen.as_ref();
+        assert_eq!(view[0], 42);
+
+        let mut memory = unsafe { frozen.unfreeze() }.unwrap();
+        assert!(!memory.is_read_only());
+        let data: &mut [u8] = memory.as_mut();
+        data[0] = 43;
+    }
+
+    #[test]
+    fn free_restores_access_of_secure_memory() {
+        let memory = Memory::allocate_secure(100, &crate::secure::SecureOptions::new()).unwrap();
+        let frozen = memory.freeze().unwrap();
+        drop(frozen);
+    }
+}
@@ -0,0 +1,295 @@
+//! Provides introspection of how [`Memory`] is backed by physical pages.
+//!
+//! [`Memory::residency`] reports which pages are resident in RAM using `mincore(2)`, and
+//! [`Memory::huge_page_coverage`] reports how much of the memory is backed by Transparent
+//! Huge Pages according to `/proc/self/smaps`. Both allow verifying that advices such as
+//! `MADV_HUGEPAGE` took effect.
+//!
+//! # Structs
+//! - [`Residency`]: The number of resident pages of a block.
+//! - [`HugePageCoverage`]: The page statistics of the mappings containing a block.
+
+use crate::alloc_result::AllocationError;
+use crate::memory::Memory;
+use crate::system_info::SystemInfo;
+use std::fs;
+
+const SMAPS: &str = "/proc/self/smaps";
+
+/// The number of pages of a block that are resident in RAM.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Residency {
+    /// The number of resident pages.
+    pub resident_pages: usize,
+    /// The number of pages spanned by the block.
+    pub total_pages: usize,
+    /// The size of a page in bytes.
+    pub page_size: usize,
+}
+
+impl Residency {
+    /// Returns the number of resident bytes.
+    pub fn resident_bytes(&self) -> usize {
+        self.resident_pages * self.page_size
+    }
+
+    /// Returns the fraction of resident pages, between `0.0` and `1.0`.
+    pub fn ratio(&self) -> f64 {
+        if self.total_pages == 0 {
+            return 0.0;
+        }
+        self.resident_pages as f64 / self.total_pages as f64
+    }
+}
+
+/// The page statistics of the mappings (VMAs) containing a block, in bytes.
+///
+/// Heap memory usually shares its mapping with other allocations, in which case the
+/// statistics describe all of them. Memory allocated with
+/// [`AllocationOptions::page_aligned`](crate::AllocationOptions::page_aligned) has
+/// mappings of its own.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct HugePageCoverage {
+    /// The total size of the mappings.
+    pub size: usize,
+    /// The number of bytes resident in RAM (`Rss`).
+    pub rss: usize,
+    /// The number of bytes backed by anonymous Transparent Huge Pages (`AnonHugePages`).
+    pub anon_huge_pages: usize,
+    /// The number of bytes of shared memory mapped by huge pages (`ShmemPmdMapped`).
+    pub shmem_pmd_mapped: usize,
+    /// The number of bytes swapped out (`Swap`).
+    pub swap: usize,
+}
+
+impl HugePageCoverage {
+    /// Returns the number of bytes backed by Transparent Huge Pages.
+    pub fn huge_page_bytes(&self) -> usize {
+        self.anon_huge_pages + self.shmem_pmd_mapped
+    }
+
+    /// Returns the fraction of resident memory backed by Transparent Huge Pages,
+    /// between `0.0` and `1.0`.
+    pub fn ratio(&self) -> f64 {
+        if self.rss == 0 {
+            return 0.0;
+        }
+        self.huge_page_bytes() as f64 / self.rss as f64
+    }
+}
+
+impl Memory {
+    /// Determines which pages of the memory are resident in RAM using `mincore(2)`.
+    ///
+    /// Pages that were never written to are usually not resident.
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::{AllocationOptions, Memory};
+    /// let options = AllocationOptions::new().page_aligned(true);
+    /// let mut memory = Memory::allocate_with(4 * 4096, &options).unwrap();
+    /// let data: &mut [u8] = memory.as_mut();
+    /// data[0] = 1;
+    ///
+    /// let residency = memory.residency().unwrap();
+    /// assert!(residency.resident_pages >= 1);
+    /// ```
+    pub fn residency(&self) -> Result<Residency, AllocationError> {
+        if self.address.is_null() {
+            return Err(AllocationError::EmptyAllocation);
+        }
+
+        // mincore(2) requires a page-aligned address.
+        let page_size = SystemInfo::get().page_size;
+        let start = self.address as usize & !(page_size - 1);
+        let end = self.address as usize + self.capacity;
+        let total_pages = (end - start).div_ceil(page_size);
+
+        let mut pages = vec![0u8; total_pages];
+        // SAFETY: The range lies within mapped memory, and `pages` holds one byte per page.
+        let result = unsafe {
+            libc::mincore(
+                start as *mut libc::c_void,
+                end - start,
+                pages.as_mut_ptr().cast(),
+            )
+        };
+        if result != 0 {
+            return Err(AllocationError::last_os_error());
+        }
+
+        Ok(Residency {
+            resident_pages: pages.iter().filter(|&&page| page & 1 == 1).count(),
+            total_pages,
+            page_size,
+        })
+    }
+
+    /// Determines how much of the memory is backed by Transparent Huge Pages by summing
+    /// the statistics of the mappings containing it from `/proc/self/smaps`.
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::{AllocationOptions, Memory};
+    /// let options = AllocationOptions::new().page_aligned(true);
+    /// let memory = Memory::allocate_with(8 * 1024 * 1024, &options).unwrap();
+    ///
+    /// let coverage = memory.huge_page_coverage().unwrap();
+    /// assert!(coverage.size >= 8 * 1024 * 1024);
+    /// assert!(coverage.huge_page_bytes() <= coverage.rss);
+    /// ```
+    pub fn huge_page_coverage(&self) -> Result<HugePageCoverage, AllocationError> {
+        if self.address.is_null() {
+            return Err(AllocationError::EmptyAllocation);
+        }
+
+        let smaps = fs::read_to_string(SMAPS)?;
+        let start = self.address as usize;
+        Ok(parse_smaps(&smaps, start, start + self.capacity))
+    }
+}
+
+/// Sums the statistics of all mappings overlapping `start..end`.
+fn parse_smaps(smaps: &str, start: usize, end: usize) -> HugePageCoverage {
+    let mut coverage = HugePageCoverage::default();
+    let mut overlapping = false;
+
+    for line in smaps.lines() {
+        if let Some(range) = mapping_range(line) {
+            overlapping = range.0 < end && start < range.1;
+            continue;
+        }
+        if !overlapping {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let field = match key {
+            "Size" => &mut coverage.size,
+            "Rss" => &mut coverage.rss,
+            "AnonHugePages" => &mut coverage.anon_huge_pages,
+            "ShmemPmdMapped" => &mut coverage.shmem_pmd_mapped,
+            "Swap" => &mut coverage.swap,
+            _ => continue,
+        };
+        if let Some(kilobytes) = value
+            .trim()
+            .strip_suffix("kB")
+            .and_then(|value| value.trim().parse::<usize>().ok())
+        {
+            *field += kilobytes * 1024;
+        }
+    }
+
+    coverage
+}
+
+/// Parses the address range of a mapping header such as `7f00-7f10 rw-p 00000000 00:00 0`.
+fn mapping_range(line: &str) -> Option<(usize, usize)> {
+    let (range, _) = line.split_once(' ')?;
+    let (start, end) = range.split_once('-')?;
+    Some((
+        usize::from_str_radix(start, 16).ok()?,
+        usize::from_str_radix(end, 16).ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::AllocationOptions;
+
+    const SMAPS_SAMPLE: &str = "\
+7f0000000000-7f0000400000 rw-p 00000000 00:00 0
+Size:               4096 kB
+Rss:                4096 kB
+AnonHugePages:      4096 kB
+ShmemPmdMapped:        0 kB
+Swap:                  0 kB
+VmFlags: rd wr mr mw me ac hg
+7f0000400000-7f0000500000 rw-p 00000000 00:00 0
+Size:               1024 kB
+Rss:                 512 kB
+AnonHugePages:         0 kB
+Swap:                 64 kB
+VmFlags: rd wr mr mw me ac
+7f0000500000-7f0000600000 r--p 00000000 fe:00 1234    /usr/lib/libc.so.6
+Size:               1024 kB
+Rss:                1024 kB
+VmFlags: rd mr mw me
+";
+
+    #[test]
+    fn parse_overlapping_mappings() {
+        let coverage = parse_smaps(SMAPS_SAMPLE, 0x7f0000200000, 0x7f0000480000);
+        assert_eq!(
+            coverage,
+            HugePageCoverage {
+                size: 5 * 1024 * 1024,
+                rss: 4608 * 1024,
+                anon_huge_pages: 4 * 1024 * 1024,
+                shmem_pmd_mapped: 0,
+                swap: 64 * 1024,
+            }
+        );
+        assert!(coverage.ratio() > 0.88 && coverage.ratio() < 0.9);
+    }
+
+    #[test]
+    fn parse_non_overlapping_mappings() {
+        let coverage = parse_smaps(SMAPS_SAMPLE, 0x7f0000600000, 0x7f0000700000);
+        assert_eq!(coverage, HugePageCoverage::default());
+        assert_eq!(coverage.ratio(), 0.0);
+    }
+
+    #[test]
+    fn residency_counts_touched_pages() {
+        let page_size = SystemInfo::get().page_size;
+        let options = AllocationOptions::new().page_aligned(true);
+        let mut memory = Memory::allocate_with(4 * page_size, &options).unwrap();
+
+        let residency = memory.residency().unwrap();
+        assert_eq!(residency.total_pages, 4);
+        assert_eq!(residency.resident_pages, 0);
+
+        let data: &mut [u8] = memory.as_mut();
+        data[0] = 1;
+        data[2 * page_size] = 1;
+
+        let residency = memory.residency().unwrap();
+        assert_eq!(residency.resident_pages, 2);
+        assert_eq!(residency.resident_bytes(), 2 * page_size);
+        assert_eq!(residency.ratio(), 0.5);
+    }
+
+    #[test]
+    fn huge_page_coverage_of_huge_page_memory() {
+        let Some(huge_page_size) = SystemInfo::get().transparent_huge_page_size() else {
+            return;
+        };
+
+        let options = AllocationOptions::new()
+            .page_aligned(true)
+            .round_to_huge_pages(1.0);
+        let mut memory = Memory::allocate_with(2 * huge_page_size, &options).unwrap();
+        let data: &mut [u8] = memory.as_mut();
+        data.fill(1);
+
+        let coverage = memory.huge_page_coverage().unwrap();
+        assert!(coverage.size >= memory.capacity());
+        assert!(coverage.rss + coverage.swap >= memory.capacity());
+        assert!(coverage.huge_page_bytes() <= coverage.rss);
+    }
+
+    #[test]
+    fn empty_memory_fails() {
+        let memory = Memory::default();
+        assert_eq!(memory.residency(), Err(AllocationError::EmptyAllocation));
+        assert_eq!(
+            memory.huge_page_coverage(),
+            Err(AllocationError::EmptyAllocation)
+        );
+    }
+}
@@ -0,0 +1,357 @@
+//! Provides [`RingBuffer`], a lock-free single-producer single-consumer queue
+//! built on [`MirroredMemory`].
+//!
+//! Since the storage is mapped twice back-to-back, the free and the occupied parts of
+//! the buffer are always contiguous, even if they wrap around its end. Elements can thus
+//! be written and read as plain slices without ever splitting them.
+//!
+//! # Structs
+//! - [`RingBuffer`]: The queue, split into its two halves using [`RingBuffer::split`].
+//! - [`Producer`]: The writing half.
+//! - [`Consumer`]: The reading half.
+
+use crate::alloc_result::AllocationError;
+use crate::memfd::MemfdOptions;
+use crate::mirrored::MirroredMemory;
+use std::marker::PhantomData;
+use std::mem::{size_of, MaybeUninit};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A lock-free single-producer single-consumer queue of `Copy` elements.
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::RingBuffer;
+/// let (mut producer, mut consumer) = RingBuffer::<f32>::new(1024).unwrap().split();
+///
+/// assert_eq!(producer.push_slice(&[1.0, 2.0, 3.0]), 3);
+/// assert_eq!(consumer.read_slice(), &[1.0, 2.0, 3.0]);
+/// consumer.consume(2);
+/// assert_eq!(consumer.read_slice(), &[3.0]);
+/// ```
+#[derive(Debug)]
+pub struct RingBuffer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The writing half of a [`RingBuffer`].
+#[derive(Debug)]
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The reading half of a [`RingBuffer`].
+#[derive(Debug)]
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    memory: MirroredMemory,
+    capacity: usize,
+    /// The read position, in `0..2 * capacity`.
+    head: AtomicUsize,
+    /// The write position, in `0..2 * capacity`.
+    tail: AtomicUsize,
+    _elements: PhantomData<T>,
+}
+
+// SAFETY: Elements are only moved between threads, never shared.
+unsafe impl<T: Send> Send for Shared<T> {}
+
+// SAFETY: The producer and the consumer only access disjoint parts of the memory.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T: Copy> RingBuffer<T> {
+    /// Creates a ring buffer holding at least `capacity` elements.
+    ///
+    /// The capacity is rounded up such that the storage is a multiple of the page size.
+    ///
+    /// ## Panics
+    /// Panics if `T` is zero-sized.
+    pub fn new(capacity: usize) -> Result<Self, AllocationError> {
+        Self::with_options(capacity, &MemfdOptions::new())
+    }
+
+    /// Creates a ring buffer holding at least `capacity` elements, using the specified
+    /// options for the backing `memfd`, e.g. in order to use huge pages.
+    ///
+    /// ## Panics
+    /// Panics if `T` is zero-sized.
+    pub fn with_options(capacity: usize, options: &MemfdOptions) -> Result<Self, AllocationError> {
+        assert_ne!(size_of::<T>(), 0, "Zero-sized elements are not supported");
+
+        let num_bytes = capacity
+            .checked_mul(size_of::<T>())
+            .ok_or(AllocationError::OsError(libc::ENOMEM))?;
+        let memory = MirroredMemory::allocate_granular(num_bytes, size_of::<T>(), options)?;
+        debug_assert_eq!(
+            memory.to_ptr_const() as usize % std::mem::align_of::<T>(),
+            0
+        );
+
+        Ok(Self {
+            shared: Arc::new(Shared {
+                capacity: memory.len() / size_of::<T>(),
+                memory,
+                head: AtomicUsize::new(0),
+                tail: AtomicUsize::new(0),
+                _elements: PhantomData,
+            }),
+        })
+    }
+
+    /// Returns the number of elements the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// Splits the buffer into its writing and reading halves, which can be moved to different threads.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        let producer = Producer {
+            shared: Arc::clone(&self.shared),
+        };
+        let consumer = Consumer {
+            shared: self.shared,
+        };
+        (producer, consumer)
+    }
+}
+
+impl<T: Copy> Producer<T> {
+    /// Returns the number of elements the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// Returns the number of elements that can currently be written.
+    pub fn free(&self) -> usize {
+        let head = self.shared.head.load(Ordering::Acquire);
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        self.shared.capacity - self.shared.distance(head, tail)
+    }
+
+    /// Returns the free part of the buffer as one contiguous slice.
+    ///
+    /// Elements written to it become visible to the consumer after calling [`Producer::commit`].
+    pub fn write_slice(&mut self) -> &mut [MaybeUninit<T>] {
+        let free = self.free();
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        // SAFETY: The free part is not accessed by the consumer, and lies within the
+        // mirrored region since `free <= capacity`.
+        unsafe { std::slice::from_raw_parts_mut(self.shared.element(tail).cast(), free) }
+    }
+
+    /// Publishes the first `count` elements of the [write slice](Producer::write_slice) to the consumer.
+    ///
+    /// ## Panics
+    /// Panics if `count` exceeds the number of free elements.
+    ///
+    /// ## Safety
+    /// The first `count` elements of the write slice are required to be initialized.
+    pub unsafe fn commit(&mut self, count: usize) {
+        assert!(count <= self.free(), "Committed more elements than free");
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        self.shared
+            .tail
+            .store(self.shared.advance(tail, count), Ordering::Release);
+    }
+
+    /// Copies as many elements as fit into the buffer and publishes them to the consumer.
+    ///
+    /// ## Returns
+    /// The number of elements written.
+    pub fn push_slice(&mut self, elements: &[T]) -> usize {
+        let target = self.write_slice();
+        let count = target.len().min(elements.len());
+        for (target, element) in target.iter_mut().zip(&elements[..count]) {
+            target.write(*element);
+        }
+        // SAFETY: The elements were initialized above.
+        unsafe { self.commit(count) };
+        count
+    }
+}
+
+impl<T: Copy> Consumer<T> {
+    /// Returns the number of elements the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// Returns the number of elements that can currently be read.
+    pub fn len(&self) -> usize {
+        let head = self.shared.head.load(Ordering::Relaxed);
+        let tail = self.shared.tail.load(Ordering::Acquire);
+        self.shared.distance(head, tail)
+    }
+
+    /// Returns whether no elements can currently be read.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the readable part of the buffer as one contiguous slice.
+    ///
+    /// The elements remain in the buffer until released using [`Consumer::consume`].
+    pub fn read_slice(&self) -> &[T] {
+        let available = self.len();
+        let head = self.shared.head.load(Ordering::Relaxed);
+        // SAFETY: The readable part was initialized by the producer, is not accessed by it
+        // until consumed, and lies within the mirrored region since `available <= capacity`.
+        unsafe { std::slice::from_raw_parts(self.shared.element(head), available) }
+    }
+
+    /// Releases the first `count` elements of the [read slice](Consumer::read_slice) to the producer.
+    ///
+    /// ## Panics
+    /// Panics if `count` exceeds the number of readable elements.
+    pub fn consume(&mut self, count: usize) {
+        assert!(count <= self.len(), "Consumed more elements than available");
+        let head = self.shared.head.load(Ordering::Relaxed);
+        self.shared
+            .head
+            .store(self.shared.advance(head, count), Ordering::Release);
+    }
+
+    /// Copies as many elements as available into `target` and releases them to the producer.
+    ///
+    /// ## Returns
+    /// The number of elements read.
+    pub fn pop_slice(&mut self, target: &mut [T]) -> usize {
+        let source = self.read_slice();
+        let count = source.len().min(target.len());
+        target[..count].copy_from_slice(&source[..count]);
+        self.consume(count);
+        count
+    }
+}
+
+impl<T> Shared<T> {
+    /// Gets the number of elements between two positions.
+    fn distance(&self, head: usize, tail: usize) -> usize {
+        (tail + 2 * self.capacity - head) % (2 * self.capacity)
+    }
+
+    /// Advances a position by `count` elements.
+    fn advance(&self, position: usize, count: usize) -> usize {
+        (position + count) % (2 * self.capacity)
+    }
+
+    /// Gets a pointer to the element at a position.
+    fn element(&self, position: usize) -> *mut T {
+        let index = position % self.capacity;
+        // SAFETY: The index lies within the first half of the mirrored region.
+        unsafe { self.memory.to_ptr_mut().cast::<T>().add(index) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system_info::SystemInfo;
+
+    #[test]
+    fn capacity_fills_pages() {
+        let buffer = RingBuffer::<u32>::new(10).unwrap();
+        assert_eq!(buffer.capacity(), SystemInfo::get().page_size / 4);
+
+        let buffer = RingBuffer::<[u8; 12]>::new(1).unwrap();
+        assert_eq!(buffer.capacity() * 12 % SystemInfo::get().page_size, 0);
+    }
+
+    #[test]
+    fn huge_tlb_buffer() {
+        // The hugetlbfs pool is empty or unsupported on this host.
+        if !crate::Memory::can_allocate(1).fits_huge_pages() {
+            return;
+        }
+
+        let options = MemfdOptions::new().huge_tlb(true);
+        let (mut producer, consumer) = RingBuffer::<u64>::with_options(1, &options)
+            .unwrap()
+            .split();
+        assert!(producer.capacity() * size_of::<u64>() > SystemInfo::get().page_size);
+        assert_eq!(producer.push_slice(&[1, 2, 3]), 3);
+        assert_eq!(consumer.len(), 3);
+    }
+
+    #[test]
+    fn slices_are_contiguous_across_wrap() {
+        let (mut producer, mut consumer) = RingBuffer::<u64>::new(1).unwrap().split();
+        let capacity = producer.capacity();
+
+        // Move the positions close to the end of the storage.
+        let filler = vec![0u64; capacity - 3];
+        assert_eq!(producer.push_slice(&filler), capacity - 3);
+        consumer.consume(capacity - 3);
+
+        let elements: Vec<u64> = (0..10).collect();
+        assert_eq!(producer.push_slice(&elements), 10);
+        assert_eq!(consumer.read_slice(), elements.as_slice());
+
+        let mut target = [0u64; 4];
+        assert_eq!(consumer.pop_slice(&mut target), 4);
+        assert_eq!(target, [0, 1, 2, 3]);
+        assert_eq!(consumer.read_slice(), &elements[4..]);
+    }
+
+    #[test]
+    fn full_buffer_rejects_writes() {
+        let (mut producer, mut consumer) = RingBuffer::<u8>::new(1).unwrap().split();
+        let capacity = producer.capacity();
+
+        let elements = vec![7u8; capacity + 10];
+        assert_eq!(producer.push_slice(&elements), capacity);
+        assert_eq!(producer.free(), 0);
+        assert!(producer.write_slice().is_empty());
+        assert_eq!(producer.push_slice(&elements), 0);
+
+        assert_eq!(consumer.len(), capacity);
+        consumer.consume(1);
+        assert_eq!(producer.push_slice(&[8]), 1);
+        assert_eq!(*consumer.read_slice().last().unwrap(), 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "Consumed more elements than available")]
+    fn consume_beyond_available_panics() {
+        let (_producer, mut consumer) = RingBuffer::<u8>::new(1).unwrap().split();
+        consumer.consume(1);
+    }
+
+    #[test]
+    fn threads_transfer_in_order() {
+        const COUNT: u64 = 100_000;
+        let (mut producer, mut consumer) = RingBuffer::<u64>::new(1000).unwrap().split();
+
+        let writer = std::thread::spawn(move || {
+            let mut next = 0;
+            while next < COUNT {
+                let slice = producer.write_slice();
+                let count = slice.len().min((COUNT - next) as usize);
+                for element in &mut slice[..count] {
+                    element.write(next);
+                    next += 1;
+                }
+                unsafe { producer.commit(count) };
+            }
+        });
+
+        let mut expected = 0;
+        while expected < COUNT {
+            let slice = consumer.read_slice();
+            for &element in slice {
+                assert_eq!(element, expected);
+                expected += 1;
+            }
+            let count = slice.len();
+            consumer.consume(count);
+        }
+
+        writer.join().unwrap();
+        assert!(consumer.is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+//! Provides secure [`Memory`] for key material and other sensitive data.
+//!
+//! Secure memory is locked into RAM (`mlock(2)`) so that it is never written to swap,
+//! excluded from core dumps (`MADV_DONTDUMP`) and wiped in child processes created
+//! using `fork(2)` (`MADV_WIPEONFORK`). When freed, its contents are overwritten with
+//! zeros using volatile writes that the compiler cannot elide.
+//!
+//! If requested and supported by the kernel, the memory is obtained from `memfd_secret(2)`,
+//! which additionally removes the pages from the kernel's direct map.
+//!
+//! # Structs
+//! - [`SecureOptions`]: Options for allocating secure memory.
+
+use crate::alloc_result::AllocationError;
+use crate::backend::Backend;
+use crate::memory::{Memory, ALLOC_FLAGS_DONT_FORK, ALLOC_FLAGS_SECURE, ALLOC_FLAGS_WIPE_ON_FORK};
+use crate::mmap::{self, map_aligned};
+use crate::policy;
+use crate::stats;
+use crate::system_info::SystemInfo;
+use crate::trace;
+use std::ffi::c_void;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr::NonNull;
+
+/// Options for allocating secure memory using [`Memory::allocate_secure`].
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::{Memory, SecureOptions};
+/// let mut key = Memory::allocate_secure(32, &SecureOptions::new()).unwrap();
+/// assert!(key.is_secure());
+///
+/// let data: &mut [u8] = key.as_mut();
+/// data.copy_from_slice(&[0x42; 32]);
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SecureOptions {
+    pub(crate) memfd_secret: bool,
+}
+
+impl SecureOptions {
+    /// Creates options for secure memory obtained from an anonymous mapping.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether the memory is obtained from `memfd_secret(2)` if supported by the kernel.
+    /// Defaults to `false`.
+    ///
+    /// Secret memory is inaccessible even to the kernel, but counts towards `RLIMIT_MEMLOCK`
+    /// and prevents hibernation while allocated. If the kernel does not support it, e.g.
+    /// because it was booted without `secretmem.enable`, an anonymous mapping is used instead.
+    pub fn memfd_secret(mut self, memfd_secret: bool) -> Self {
+        self.memfd_secret = memfd_secret;
+        self
+    }
+}
+
+impl Memory {
+    /// Allocates zeroed-out secure memory of the specified number of bytes.
+    ///
+    /// The capacity is rounded up to a multiple of the page size, and the memory is aligned
+    /// as decided by the global alignment policy, but at least to the page size.
+    ///
+    /// The memory is locked into RAM, excluded from core dumps and wiped in child processes
+    /// created using `fork`. Its contents are overwritten with zeros when it is freed.
+    ///
+    /// ## Errors
+    /// Fails with [`AllocationError::OsError`] if any of the protections cannot be applied,
+    /// e.g. with `ENOMEM` or `EPERM` if locking the memory exceeds `RLIMIT_MEMLOCK`.
+    ///
+    /// ## Arguments
+    /// * `num_bytes` - The number of bytes to allocate.
+    /// * `options` - The options for allocating the memory.
+    pub fn allocate_secure(
+        num_bytes: usize,
+        options: &SecureOptions,
+    ) -> Result<Self, AllocationError> {
+        let operation = trace::Operation::allocate("allocate_secure", num_bytes);
+        let result = Self::allocate_secure_unrecorded(num_bytes, options);
+        operation.allocated(&result);
+        stats::record_result(result)
+    }
+
+    fn allocate_secure_unrecorded(
+        num_bytes: usize,
+        options: &SecureOptions,
+    ) -> Result<Self, AllocationError> {
+        if num_bytes == 0 {
+            return Err(AllocationError::EmptyAllocation);
+        }
+
+        let capacity = num_bytes
+            .checked_next_multiple_of(SystemInfo::get().page_size)
+            .ok_or(AllocationError::OsError(libc::ENOMEM))?;
+        let alignment = mmap::mapping_alignment(
+            capacity,
+            policy::resolve(capacity, None).alignment,
+            SystemInfo::get().page_size,
+        )?;
+
+        if options.memfd_secret {
+            match open_secret() {
+                Ok(fd) => return Self::map_secret(&fd, num_bytes, capacity, alignment),
+                Err(AllocationError::OsError(libc::ENOSYS)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        let ptr = map_aligned(
+            capacity,
+            alignment,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+        )?;
+
+        // SAFETY: The mapping was created above.
+        let protected = unsafe {
+            lock(ptr, capacity)
+                .and_then(|_| advise(ptr, capacity, libc::MADV_DONTDUMP))
+                .and_then(|_| advise(ptr, capacity, libc::MADV_WIPEONFORK))
+        };
+        if let Err(error) = protected {
+            // SAFETY: The mapping was created above and is not referenced anymore.
+            unsafe { mmap::unmap(ptr, capacity) };
+            return Err(error);
+        }
+
+        Self::allocated(
+            ALLOC_FLAGS_SECURE | ALLOC_FLAGS_WIPE_ON_FORK,
+            num_bytes,
+            capacity,
+            alignment,
+            Backend::Anonymous,
+            ptr.as_ptr(),
+        )
+    }
+
+    /// Maps secret memory. The descriptor is not retained, since the mapping keeps the memory alive.
+    fn map_secret(
+        fd: &OwnedFd,
+        num_bytes: usize,
+        capacity: usize,
+        alignment: usize,
+    ) -> Result<Self, AllocationError> {
+        let size =
+            libc::off_t::try_from(capacity).map_err(|_| AllocationError::OsError(libc::EFBIG))?;
+        // SAFETY: `fd` is a valid descriptor.
+        if unsafe { libc::ftruncate(fd.as_raw_fd(), size) } != 0 {
+            return Err(AllocationError::last_os_error());
+        }
+
+        let ptr = map_aligned(
+            capacity,
+            alignment,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            fd.as_raw_fd(),
+        )?;
+
+        // Secret memory is implicitly locked and excluded from core dumps. Since it is
+        // a shared mapping, it cannot be wiped on fork; it is not inherited instead.
+        // SAFETY: The mapping was created above.
+        if let Err(error) = unsafe { advise(ptr, capacity, libc::MADV_DONTFORK) } {
+            // SAFETY: The mapping was created above and is not referenced anymore.
+            unsafe { mmap::unmap(ptr, capacity) };
+            return Err(error);
+        }
+
+        Self::allocated(
+            ALLOC_FLAGS_SECURE | ALLOC_FLAGS_DONT_FORK,
+            num_bytes,
+            capacity,
+            alignment,
+            Backend::Secret,
+            ptr.as_ptr(),
+        )
+    }
+}
+
+/// Overwrites memory with zeros using volatile writes, which the compiler cannot elide.
+///
+/// ## Safety
+/// `ptr` is required to be valid for writing `num_bytes` bytes.
+pub(crate) unsafe fn zeroize(ptr: *mut c_void, num_bytes: usize) {
+    let bytes = ptr.cast::<u8>();
+    for offset in 0..num_bytes {
+        bytes.add(offset).write_volatile(0);
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Creates a `memfd_secret(2)` descriptor. Fails with `ENOSYS` if unsupported.
+fn open_secret() -> Result<OwnedFd, AllocationError> {
+    // SAFETY: The system call takes a flags argument and returns a new descriptor.
+    let fd = unsafe { libc::syscall(libc::SYS_memfd_secret, libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(AllocationError::last_os_error());
+    }
+    // SAFETY: The descriptor was just created and is owned by nobody else.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) })
+}
+
+unsafe fn lock(ptr: NonNull<c_void>, num_bytes: usize) -> Result<(), AllocationError> {
+    if libc::mlock(ptr.as_ptr(), num_bytes) != 0 {
+        return Err(AllocationError::last_os_error());
+    }
+    Ok(())
+}
+
+unsafe fn advise(
+    ptr: NonNull<c_void>,
+    num_bytes: usize,
+    advice: libc::c_int,
+) -> Result<(), AllocationError> {
+    if trace::madvise(ptr.as_ptr(), num_bytes, advice) != 0 {
+        return Err(AllocationError::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gets the `VmFlags` of the mapping starting at `address` from `/proc/self/smaps`.
+    fn vm_flags(address: *const c_void) -> Vec<String> {
+        let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+        let start = format!("{:x}-", address as usize);
+        let flags = smaps
+            .lines()
+            .skip_while(|line| !line.starts_with(&start))
+            .find_map(|line| line.strip_prefix("VmFlags:"))
+            .expect("mapping not found");
+        flags.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn secure_memory_is_protected() {
+        let mut memory = Memory::allocate_secure(100, &SecureOptions::new()).unwrap();
+        assert!(memory.is_secure());
+        assert!(memory.is_wipe_on_fork());
+        assert_eq!(memory.backend(), Backend::Anonymous);
+        assert_eq!(memory.len(), 100);
+        assert_eq!(memory.capacity(), SystemInfo::get().page_size);
+        assert_eq!(memory.to_ptr_const() as usize % memory.alignment(), 0);
+        assert!(memory.fd().is_none());
+
+        let data: &mut [u8] = memory.as_mut();
+        assert!(data.iter().all(|&value| value == 0));
+        data[99] = 42;
+
+        let flags = vm_flags(memory.to_ptr_const());
+        for flag in ["lo", "dd", "wf"] {
+            assert!(flags.iter().any(|f| f == flag), "{flag} missing: {flags:?}");
+        }
+    }
+
+    #[test]
+    fn secret_memory_is_not_inherited() {
+        let options = SecureOptions::new().memfd_secret(true);
+        let mut memory = Memory::allocate_secure(100, &options).unwrap();
+        assert!(memory.is_secure());
+        let data: &mut [u8] = memory.as_mut();
+        data[0] = 42;
+
+        let flags = vm_flags(memory.to_ptr_const());
+        match memory.backend() {
+            Backend::Secret => assert!(flags.iter().any(|f| f == "dc"), "{flags:?}"),
+            backend => assert_eq!(backend, Backend::Anonymous),
+        }
+    }
+
+    #[test]
+    fn zeroize_clears_memory() {
+        let mut buffer = [0xAAu8; 100];
+        unsafe { zeroize(buffer.as_mut_ptr().cast(), 50) };
+        assert!(buffer[..50].iter().all(|&value| value == 0));
+        assert!(buffer[50..].iter().all(|&value| value == 0xAA));
+    }
+
+    #[test]
+    fn empty_allocation_fails() {
+        let err = Memory::allocate_secure(0, &SecureOptions::new()).expect_err("empty");
+        assert_eq!(err, AllocationError::EmptyAllocation);
+    }
+}
@@ -0,0 +1,347 @@
+//! Provides [`SharedMemory`], a named POSIX shared memory segment created using `shm_open(3)`.
+//!
+//! Unlike the anonymous memory of [`Memory::allocate_shared`], a named segment can be
+//! attached to by any process knowing its name, e.g. through the FFI functions
+//! `alloc_madvise_shm_create` and `alloc_madvise_shm_open`. The segment persists until it is
+//! explicitly unlinked using [`SharedMemory::unlink`] (or on drop, see
+//! [`ShmOptions::unlink_on_drop`]), even after all processes have detached.
+//!
+//! # Structs
+//! - [`SharedMemory`]: A mapped named segment, dereferencing to [`Memory`].
+//! - [`ShmOptions`]: Options for creating a named segment.
+
+use crate::alloc_result::AllocationError;
+use crate::backend::Backend;
+use crate::memory::Memory;
+use crate::mmap::{Advice, MapMode};
+use std::ffi::CString;
+use std::ops::{Deref, DerefMut};
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+
+/// Options for creating a named shared memory segment using [`SharedMemory::create`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShmOptions {
+    pub(crate) permissions: u32,
+    pub(crate) exclusive: bool,
+    pub(crate) unlink_on_drop: bool,
+    pub(crate) advice: Advice,
+}
+
+impl Default for ShmOptions {
+    fn default() -> Self {
+        Self {
+            permissions: 0o600,
+            exclusive: true,
+            unlink_on_drop: false,
+            advice: Advice::Normal,
+        }
+    }
+}
+
+impl ShmOptions {
+    /// Creates options for an exclusively created segment only accessible by the current user.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the permission bits of a newly created segment, e.g. `0o660`. Defaults to `0o600`.
+    pub fn permissions(mut self, permissions: u32) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    /// Sets whether creating the segment fails if it already exists (`O_EXCL`).
+    /// Defaults to `true`.
+    ///
+    /// If not set, an existing segment is opened and resized to the requested number of bytes.
+    pub fn exclusive(mut self, exclusive: bool) -> Self {
+        self.exclusive = exclusive;
+        self
+    }
+
+    /// Sets whether the segment is unlinked when the [`SharedMemory`] is dropped.
+    /// Defaults to `false`.
+    pub fn unlink_on_drop(mut self, unlink_on_drop: bool) -> Self {
+        self.unlink_on_drop = unlink_on_drop;
+        self
+    }
+
+    /// Sets the expected access pattern.
+    pub fn advice(mut self, advice: Advice) -> Self {
+        self.advice = advice;
+        self
+    }
+}
+
+/// A named POSIX shared memory segment mapped into memory.
+///
+/// Dereferences to the mapped [`Memory`], providing the same slice views.
+/// The segment is unmapped when dropped, but only removed when unlinked.
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::{SharedMemory, ShmOptions};
+/// let name = format!("/alloc-madvise-doc-{}", std::process::id());
+/// let options = ShmOptions::new().unlink_on_drop(true);
+/// let mut created = unsafe { SharedMemory::create(&name, 4096, &options) }.unwrap();
+/// let data: &mut [u32] = created.as_mut();
+/// data[0] = 42;
+///
+/// let attached = unsafe { SharedMemory::open(&name) }.unwrap();
+/// let view: &[u32] = attached.as_ref();
+/// assert_eq!(view[0], 42);
+/// ```
+#[derive(Debug)]
+pub struct SharedMemory {
+    memory: Memory,
+    name: CString,
+    unlink_on_drop: bool,
+}
+
+impl SharedMemory {
+    /// Creates a named segment of the specified number of bytes and maps it read-write.
+    ///
+    /// The contents of a newly created segment are zero. The name is required to consist
+    /// of a leading slash followed by up to 255 characters other than slashes; the leading
+    /// slash is added if missing.
+    ///
+    /// ## Safety
+    /// Other processes are required not to truncate the segment while it is mapped.
+    /// The memory may be modified by other processes at any time.
+    pub unsafe fn create(
+        name: &str,
+        num_bytes: usize,
+        options: &ShmOptions,
+    ) -> Result<Self, AllocationError> {
+        if num_bytes == 0 {
+            return Err(AllocationError::EmptyAllocation);
+        }
+
+        let name = segment_name(name)?;
+        let mut flags = libc::O_RDWR | libc::O_CREAT | libc::O_CLOEXEC;
+        if options.exclusive {
+            flags |= libc::O_EXCL;
+        }
+
+        let fd = shm_open(&name, flags, options.permissions)?;
+        let size =
+            libc::off_t::try_from(num_bytes).map_err(|_| AllocationError::OsError(libc::EFBIG))?;
+        if libc::ftruncate(fd.as_raw_fd(), size) != 0 {
+            let error = AllocationError::last_os_error();
+            if options.exclusive {
+                libc::shm_unlink(name.as_ptr());
+            }
+            return Err(error);
+        }
+
+        match Memory::map_fd(
+            fd.as_raw_fd(),
+            num_bytes,
+            MapMode::ReadWrite,
+            options.advice,
+            Backend::File,
+        ) {
+            Ok(mut memory) => {
+                memory.fd = fd.into_raw_fd();
+                Ok(Self {
+                    memory,
+                    name,
+                    unlink_on_drop: options.unlink_on_drop,
+                })
+            }
+            Err(error) => {
+                if options.exclusive {
+                    libc::shm_unlink(name.as_ptr());
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// Opens an existing named segment and maps all of it read-write.
+    ///
+    /// ## Safety
+    /// See [`SharedMemory::create`].
+    pub unsafe fn open(name: &str) -> Result<Self, AllocationError> {
+        Self::open_with(name, MapMode::ReadWrite)
+    }
+
+    /// Opens an existing named segment and maps all of it using the specified mode.
+    ///
+    /// ## Safety
+    /// See [`SharedMemory::create`].
+    pub unsafe fn open_with(name: &str, mode: MapMode) -> Result<Self, AllocationError> {
+        let name = segment_name(name)?;
+        let flags = match mode {
+            MapMode::ReadWrite => libc::O_RDWR,
+            MapMode::ReadOnly | MapMode::CopyOnWrite => libc::O_RDONLY,
+        };
+        let fd = shm_open(&name, flags | libc::O_CLOEXEC, 0)?;
+
+        let mut stat: libc::stat = std::mem::zeroed();
+        if libc::fstat(fd.as_raw_fd(), &mut stat) != 0 {
+            return Err(AllocationError::last_os_error());
+        }
+        let num_bytes =
+            usize::try_from(stat.st_size).map_err(|_| AllocationError::OsError(libc::EFBIG))?;
+
+        let mut memory = Memory::map_fd(
+            fd.as_raw_fd(),
+            num_bytes,
+            mode,
+            Advice::Normal,
+            Backend::File,
+        )?;
+        memory.fd = fd.into_raw_fd();
+        Ok(Self {
+            memory,
+            name,
+            unlink_on_drop: false,
+        })
+    }
+
+    /// Removes the name of a segment. Processes having it mapped can continue to use it,
+    /// but it can no longer be opened. The memory is released once all processes detached.
+    pub fn unlink(name: &str) -> Result<(), AllocationError> {
+        let name = segment_name(name)?;
+        // SAFETY: `name` is a valid C string.
+        if unsafe { libc::shm_unlink(name.as_ptr()) } != 0 {
+            return Err(AllocationError::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the segment, including the leading slash.
+    pub fn name(&self) -> &str {
+        self.name.to_str().expect("name was created from a string")
+    }
+}
+
+impl Deref for SharedMemory {
+    type Target = Memory;
+
+    fn deref(&self) -> &Self::Target {
+        &self.memory
+    }
+}
+
+impl DerefMut for SharedMemory {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.memory
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        if self.unlink_on_drop {
+            // SAFETY: `name` is a valid C string.
+            unsafe { libc::shm_unlink(self.name.as_ptr()) };
+        }
+    }
+}
+
+/// Gets the name of a segment, adding the leading slash if missing.
+fn segment_name(name: &str) -> Result<CString, AllocationError> {
+    let name = if name.starts_with('/') {
+        name.to_string()
+    } else {
+        format!("/{name}")
+    };
+    if name.len() < 2 || name[1..].contains('/') {
+        return Err(AllocationError::OsError(libc::EINVAL));
+    }
+    CString::new(name).map_err(|_| AllocationError::OsError(libc::EINVAL))
+}
+
+fn shm_open(name: &CString, flags: libc::c_int, mode: u32) -> Result<OwnedFd, AllocationError> {
+    // SAFETY: `name` is a valid C string.
+    let fd = unsafe { libc::shm_open(name.as_ptr(), flags, mode as libc::mode_t) };
+    if fd < 0 {
+        return Err(AllocationError::last_os_error());
+    }
+    // SAFETY: The descriptor was just opened and is owned by nobody else.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unique_name(test: &str) -> String {
+        format!("alloc-madvise-{}-{test}", std::process::id())
+    }
+
+    #[test]
+    fn create_and_open() {
+        let name = unique_name("create_and_open");
+        let options = ShmOptions::new().unlink_on_drop(true);
+        let mut created = unsafe { SharedMemory::create(&name, 10_000, &options) }.unwrap();
+        assert_eq!(created.name(), format!("/{name}"));
+        assert_eq!(created.len(), 10_000);
+        assert_eq!(created.to_ptr_const() as usize % created.alignment(), 0);
+
+        let data: &mut [u8] = created.as_mut();
+        assert!(data.iter().all(|&value| value == 0));
+        data[9_999] = 42;
+
+        let mut attached = unsafe { SharedMemory::open(&name) }.unwrap();
+        assert_eq!(attached.len(), 10_000);
+        let view: &mut [u8] = attached.as_mut();
+        assert_eq!(view[9_999], 42);
+        view[0] = 7;
+
+        let data: &[u8] = created.as_ref();
+        assert_eq!(data[0], 7);
+    }
+
+    #[test]
+    fn open_read_only() {
+        let name = unique_name("open_read_only");
+        let options = ShmOptions::new().unlink_on_drop(true);
+        let _created = unsafe { SharedMemory::create(&name, 64, &options) }.unwrap();
+
+        let attached = unsafe { SharedMemory::open_with(&name, MapMode::ReadOnly) }.unwrap();
+        assert!(attached.is_read_only());
+    }
+
+    #[test]
+    fn create_is_exclusive() {
+        let name = unique_name("create_is_exclusive");
+        let options = ShmOptions::new().unlink_on_drop(true);
+        let _created = unsafe { SharedMemory::create(&name, 64, &options) }.unwrap();
+
+        let err = unsafe { SharedMemory::create(&name, 64, &ShmOptions::new()) }
+            .expect_err("the segment exists");
+        assert_eq!(err, AllocationError::OsError(libc::EEXIST));
+
+        let options = ShmOptions::new().exclusive(false);
+        let reopened = unsafe { SharedMemory::create(&name, 128, &options) }.unwrap();
+        assert_eq!(reopened.len(), 128);
+    }
+
+    #[test]
+    fn unlink_removes_name() {
+        let name = unique_name("unlink_removes_name");
+        let mut created = unsafe { SharedMemory::create(&name, 64, &ShmOptions::new()) }.unwrap();
+        SharedMemory::unlink(&name).unwrap();
+
+        let err = unsafe { SharedMemory::open(&name) }.expect_err("the segment was unlinked");
+        assert_eq!(err, AllocationError::OsError(libc::ENOENT));
+
+        // The mapping remains usable.
+        let data: &mut [u8] = created.as_mut();
+        data[63] = 1;
+
+        let err = SharedMemory::unlink(&name).expect_err("the segment was unlinked");
+        assert_eq!(err, AllocationError::OsError(libc::ENOENT));
+    }
+
+    #[test]
+    fn invalid_names_fail() {
+        for name in ["", "/", "/a/b", "nul\0byte"] {
+            let err = unsafe { SharedMemory::create(name, 64, &ShmOptions::new()) }
+                .expect_err("name is invalid");
+            assert_eq!(err, AllocationError::OsError(libc::EINVAL));
+        }
+    }
+}
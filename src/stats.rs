@@ -0,0 +1,413 @@
+//! Provides global statistics of the memory held through this crate.
+//!
+//! The statistics are only collected if the crate is built with the `stats` feature;
+//! otherwise recording compiles to nothing. Counters are updated when [`Memory`] is
+//! allocated, mapped or freed, and are split by page size, access pattern and backend.
+//! Bytes are counted by capacity, i.e. including the rounding applied when allocating.
+//! Failures are counted for [`Memory::allocate`], [`Memory::allocate_with`],
+//! [`Memory::allocate_shared`], [`Memory::allocate_secure`] and [`Memory::allocate_in`].
+//!
+//! # Functions
+//! - [`snapshot`]: Reads the current statistics.
+//!
+//! # Structs
+//! - [`AllocationStats`]: A snapshot of all statistics.
+//! - [`Usage`]: The counters of a single category.
+//! - [`Failures`]: The number of failed allocations by error kind.
+
+#![cfg_attr(not(feature = "stats"), allow(dead_code))]
+
+use crate::alloc_result::AllocationError;
+use crate::backend::Backend;
+use crate::memory::{Memory, ALLOC_FLAGS_HUGE_PAGES, ALLOC_FLAGS_SEQUENTIAL};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The counters of a single category of memory.
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Usage {
+    /// The number of bytes currently held.
+    pub current_bytes: u64,
+    /// The maximum number of bytes held at any time.
+    pub peak_bytes: u64,
+    /// The number of blocks currently held.
+    pub live_blocks: u64,
+    /// The total number of blocks obtained.
+    pub allocations: u64,
+}
+
+/// The number of failed allocations by [`AllocationError`] kind.
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Failures {
+    /// The number of [`AllocationError::EmptyAllocation`] errors.
+    pub empty: u64,
+    /// The number of [`AllocationError::InvalidAlignment`] errors.
+    pub invalid_alignment: u64,
+    /// The number of [`AllocationError::OsError`] errors.
+    pub os_error: u64,
+    /// The number of [`AllocationError::Rejected`] errors.
+    pub rejected: u64,
+    /// The number of [`AllocationError::BudgetExceeded`] errors.
+    pub budget_exceeded: u64,
+    /// The number of [`AllocationError::InsufficientMemory`] errors.
+    pub insufficient_memory: u64,
+}
+
+/// A snapshot of the statistics of all memory held through this crate.
+///
+/// ## Example
+/// ```
+/// # #[cfg(feature = "stats")]
+/// # {
+/// # use alloc_madvise::{stats, Memory};
+/// let memory = Memory::allocate(1024, true, false).unwrap();
+///
+/// let stats = stats::snapshot();
+/// assert!(stats.total.current_bytes >= 1024);
+/// assert!(stats.sequential.live_blocks >= 1);
+/// # }
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct AllocationStats {
+    /// All memory.
+    pub total: Usage,
+    /// Memory advised to use Transparent Huge Pages.
+    pub huge_pages: Usage,
+    /// Memory using regular pages.
+    pub regular_pages: Usage,
+    /// Memory advised for sequential access.
+    pub sequential: Usage,
+    /// Memory advised for normal access.
+    pub normal: Usage,
+    /// Memory allocated from the heap, see [`Backend::Heap`].
+    pub heap: Usage,
+    /// File mappings, see [`Backend::File`].
+    pub file: Usage,
+    /// Shared memory, see [`Backend::Memfd`].
+    pub memfd: Usage,
+    /// Anonymous mappings, see [`Backend::Anonymous`].
+    pub anonymous: Usage,
+    /// Secret memory, see [`Backend::Secret`].
+    pub secret: Usage,
+    /// The failed allocations.
+    pub failures: Failures,
+}
+
+impl AllocationStats {
+    /// Returns the counters of the specified backend.
+    pub fn backend(&self, backend: Backend) -> &Usage {
+        match backend {
+            Backend::Heap => &self.heap,
+            Backend::File => &self.file,
+            Backend::Memfd => &self.memfd,
+            Backend::Anonymous => &self.anonymous,
+            Backend::Secret => &self.secret,
+        }
+    }
+}
+
+/// Reads the current statistics.
+///
+/// The counters are read individually, so a snapshot taken while other threads allocate
+/// may be slightly inconsistent.
+pub fn snapshot() -> AllocationStats {
+    STATS.snapshot()
+}
+
+static STATS: Stats = Stats::new();
+
+struct Counters {
+    current_bytes: AtomicU64,
+    peak_bytes: AtomicU64,
+    live_blocks: AtomicU64,
+    allocations: AtomicU64,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            current_bytes: AtomicU64::new(0),
+            peak_bytes: AtomicU64::new(0),
+            live_blocks: AtomicU64::new(0),
+            allocations: AtomicU64::new(0),
+        }
+    }
+
+    fn add_bytes(&self, num_bytes: u64) {
+        let current = self.current_bytes.fetch_add(num_bytes, Ordering::Relaxed) + num_bytes;
+        self.peak_bytes.fetch_max(current, Ordering::Relaxed);
+    }
+
+    fn sub_bytes(&self, num_bytes: u64) {
+        self.current_bytes.fetch_sub(num_bytes, Ordering::Relaxed);
+    }
+
+    fn allocated(&self, num_bytes: u64) {
+        self.add_bytes(num_bytes);
+        self.live_blocks.fetch_add(1, Ordering::Relaxed);
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn released(&self, num_bytes: u64) {
+        self.sub_bytes(num_bytes);
+        self.live_blocks.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn read(&self) -> Usage {
+        Usage {
+            current_bytes: self.current_bytes.load(Ordering::Relaxed),
+            peak_bytes: self.peak_bytes.load(Ordering::Relaxed),
+            live_blocks: self.live_blocks.load(Ordering::Relaxed),
+            allocations: self.allocations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct Stats {
+    total: Counters,
+    huge_pages: Counters,
+    regular_pages: Counters,
+    sequential: Counters,
+    normal: Counters,
+    backends: [Counters; 5],
+    failures: [AtomicU64; 6],
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Self {
+            total: Counters::new(),
+            huge_pages: Counters::new(),
+            regular_pages: Counters::new(),
+            sequential: Counters::new(),
+            normal: Counters::new(),
+            backends: [
+                Counters::new(),
+                Counters::new(),
+                Counters::new(),
+                Counters::new(),
+                Counters::new(),
+            ],
+            failures: [
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+            ],
+        }
+    }
+
+    /// Gets the page size and access pattern categories of memory with the specified flags.
+    fn categories(&self, flags: u32) -> [&Counters; 2] {
+        let pages = if flags & ALLOC_FLAGS_HUGE_PAGES != 0 {
+            &self.huge_pages
+        } else {
+            &self.regular_pages
+        };
+        let access = if flags & ALLOC_FLAGS_SEQUENTIAL != 0 {
+            &self.sequential
+        } else {
+            &self.normal
+        };
+        [pages, access]
+    }
+
+    fn allocated(&self, flags: u32, backend: Backend, num_bytes: u64) {
+        self.total.allocated(num_bytes);
+        self.backends[backend as usize].allocated(num_bytes);
+        for counters in self.categories(flags) {
+            counters.allocated(num_bytes);
+        }
+    }
+
+    fn released(&self, flags: u32, backend: Backend, num_bytes: u64) {
+        self.total.released(num_bytes);
+        self.backends[backend as usize].released(num_bytes);
+        for counters in self.categories(flags) {
+            counters.released(num_bytes);
+        }
+    }
+
+    fn reclassified(&self, old_flags: u32, new_flags: u32, num_bytes: u64) {
+        let old = self.categories(old_flags);
+        let new = self.categories(new_flags);
+        for (old, new) in old.into_iter().zip(new) {
+            if !std::ptr::eq(old, new) {
+                old.released(num_bytes);
+                new.add_bytes(num_bytes);
+                new.live_blocks.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn failed(&self, error: &AllocationError) {
+        let kind = match error {
+            AllocationError::EmptyAllocation => 0,
+            AllocationError::InvalidAlignment(_) => 1,
+            AllocationError::OsError(_) => 2,
+            AllocationError::Rejected => 3,
+            AllocationError::BudgetExceeded => 4,
+            AllocationError::InsufficientMemory(_) => 5,
+        };
+        self.failures[kind].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> AllocationStats {
+        AllocationStats {
+            total: self.total.read(),
+            huge_pages: self.huge_pages.read(),
+            regular_pages: self.regular_pages.read(),
+            sequential: self.sequential.read(),
+            normal: self.normal.read(),
+            heap: self.backends[Backend::Heap as usize].read(),
+            file: self.backends[Backend::File as usize].read(),
+            memfd: self.backends[Backend::Memfd as usize].read(),
+            anonymous: self.backends[Backend::Anonymous as usize].read(),
+            secret: self.backends[Backend::Secret as usize].read(),
+            failures: Failures {
+                empty: self.failures[0].load(Ordering::Relaxed),
+                invalid_alignment: self.failures[1].load(Ordering::Relaxed),
+                os_error: self.failures[2].load(Ordering::Relaxed),
+                rejected: self.failures[3].load(Ordering::Relaxed),
+                budget_exceeded: self.failures[4].load(Ordering::Relaxed),
+                insufficient_memory: self.failures[5].load(Ordering::Relaxed),
+            },
+        }
+    }
+}
+
+/// Records memory that was obtained.
+#[inline(always)]
+pub(crate) fn record_allocation(memory: &Memory) {
+    #[cfg(feature = "stats")]
+    STATS.allocated(memory.flags, memory.backend, memory.capacity as u64);
+    #[cfg(not(feature = "stats"))]
+    let _ = memory;
+}
+
+/// Records memory that is about to be released.
+#[inline(always)]
+pub(crate) fn record_release(memory: &Memory) {
+    #[cfg(feature = "stats")]
+    STATS.released(memory.flags, memory.backend, memory.capacity as u64);
+    #[cfg(not(feature = "stats"))]
+    let _ = memory;
+}
+
+/// Records a change of the flags of memory, moving it between categories.
+#[inline(always)]
+pub(crate) fn record_flags_change(memory: &Memory, old_flags: u32) {
+    #[cfg(feature = "stats")]
+    STATS.reclassified(old_flags, memory.flags, memory.capacity as u64);
+    #[cfg(not(feature = "stats"))]
+    let _ = (memory, old_flags);
+}
+
+/// Records the outcome of an allocation, passing it through.
+#[inline(always)]
+pub(crate) fn record_result(
+    result: Result<Memory, AllocationError>,
+) -> Result<Memory, AllocationError> {
+    #[cfg(feature = "stats")]
+    if let Err(error) = &result {
+        STATS.failed(error);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preflight::PreflightVerdict;
+
+    const HUGE_SEQUENTIAL: u32 = ALLOC_FLAGS_HUGE_PAGES | ALLOC_FLAGS_SEQUENTIAL;
+
+    #[test]
+    fn allocations_are_split_by_category() {
+        let stats = Stats::new();
+        stats.allocated(HUGE_SEQUENTIAL, Backend::Heap, 4096);
+        stats.allocated(0, Backend::Memfd, 1024);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.total.current_bytes, 5120);
+        assert_eq!(snapshot.total.live_blocks, 2);
+        assert_eq!(snapshot.huge_pages.current_bytes, 4096);
+        assert_eq!(snapshot.regular_pages.current_bytes, 1024);
+        assert_eq!(snapshot.sequential.current_bytes, 4096);
+        assert_eq!(snapshot.normal.current_bytes, 1024);
+        assert_eq!(snapshot.backend(Backend::Heap).current_bytes, 4096);
+        assert_eq!(snapshot.backend(Backend::Memfd).current_bytes, 1024);
+        assert_eq!(snapshot.backend(Backend::File), &Usage::default());
+    }
+
+    #[test]
+    fn release_keeps_peak() {
+        let stats = Stats::new();
+        stats.allocated(0, Backend::Heap, 4096);
+        stats.allocated(0, Backend::Heap, 1024);
+        stats.released(0, Backend::Heap, 4096);
+        stats.allocated(0, Backend::Heap, 2048);
+
+        let total = stats.snapshot().total;
+        assert_eq!(total.current_bytes, 3072);
+        assert_eq!(total.peak_bytes, 5120);
+        assert_eq!(total.live_blocks, 2);
+        assert_eq!(total.allocations, 3);
+    }
+
+    #[test]
+    fn reclassification_moves_bytes() {
+        let stats = Stats::new();
+        stats.allocated(0, Backend::Heap, 4096);
+        stats.reclassified(0, ALLOC_FLAGS_SEQUENTIAL, 4096);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.normal.current_bytes, 0);
+        assert_eq!(snapshot.normal.live_blocks, 0);
+        assert_eq!(snapshot.sequential.current_bytes, 4096);
+        assert_eq!(snapshot.sequential.live_blocks, 1);
+        assert_eq!(snapshot.sequential.allocations, 0);
+        assert_eq!(snapshot.total.current_bytes, 4096);
+
+        stats.released(ALLOC_FLAGS_SEQUENTIAL, Backend::Heap, 4096);
+        assert_eq!(stats.snapshot().sequential.current_bytes, 0);
+    }
+
+    #[test]
+    fn failures_are_counted_by_kind() {
+        let stats = Stats::new();
+        stats.failed(&AllocationError::EmptyAllocation);
+        stats.failed(&AllocationError::OsError(libc::ENOMEM));
+        stats.failed(&AllocationError::OsError(libc::EINVAL));
+        stats.failed(&AllocationError::Rejected);
+        stats.failed(&AllocationError::BudgetExceeded);
+        stats.failed(&AllocationError::InsufficientMemory(
+            PreflightVerdict::ExceedsCgroupLimit,
+        ));
+
+        let failures = stats.snapshot().failures;
+        assert_eq!(failures.empty, 1);
+        assert_eq!(failures.invalid_alignment, 0);
+        assert_eq!(failures.os_error, 2);
+        assert_eq!(failures.rejected, 1);
+        assert_eq!(failures.budget_exceeded, 1);
+        assert_eq!(failures.insufficient_memory, 1);
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn memory_is_recorded() {
+        let before = snapshot();
+        let memory = Memory::allocate(1 << 20, true, false).unwrap();
+        let during = snapshot();
+        assert!(during.total.allocations > before.total.allocations);
+        assert!(during.heap.peak_bytes >= 1 << 20);
+        drop(memory);
+
+        let _ = Memory::allocate(0, false, false);
+        assert!(snapshot().failures.empty > before.failures.empty);
+    }
+}
@@ -0,0 +1,314 @@
+//! Provides the [`SystemInfo`] struct describing the memory page configuration of the host.
+//!
+//! The information is read from `sysconf(3)` and the `sysfs` entries below
+//! `/sys/kernel/mm` and `/sys/devices/system/cpu`. Since it is consulted for every
+//! allocation, [`SystemInfo::get`] reads it only once and caches it for the lifetime
+//! of the process.
+
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+const THP_ENABLED: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+const THP_DEFRAG: &str = "/sys/kernel/mm/transparent_hugepage/defrag";
+const THP_PMD_SIZE: &str = "/sys/kernel/mm/transparent_hugepage/hpage_pmd_size";
+const HUGETLB_PAGES: &str = "/sys/kernel/mm/hugepages";
+const CACHE_LINE_SIZE: &str = "/sys/devices/system/cpu/cpu0/cache/index0/coherency_line_size";
+
+/// The page size assumed if it cannot be determined.
+const DEFAULT_PAGE_SIZE: usize = 4096;
+
+/// The minimum alignment for allocations that are not huge-page aligned,
+/// which should be optimal for both AVX-2 and AVX-512 operations.
+const SIXTY_FOUR_BYTES: usize = 64;
+
+static SYSTEM_INFO: OnceLock<SystemInfo> = OnceLock::new();
+
+/// The Transparent Huge Page (THP) mode configured on the host.
+///
+/// See `/sys/kernel/mm/transparent_hugepage/enabled`.
+#[repr(u32)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ThpMode {
+    /// Transparent Huge Pages are not supported by the kernel.
+    Unavailable = 0,
+    /// Huge pages are used for all eligible mappings.
+    Always = 1,
+    /// Huge pages are only used for regions advised with `MADV_HUGEPAGE`.
+    Madvise = 2,
+    /// Transparent Huge Pages are disabled.
+    Never = 3,
+}
+
+impl ThpMode {
+    /// Parses the mode from a `sysfs` selection such as `always [madvise] never`.
+    fn parse(value: &str) -> Self {
+        match selected(value) {
+            Some("always") => ThpMode::Always,
+            Some("madvise") => ThpMode::Madvise,
+            Some("never") => ThpMode::Never,
+            _ => ThpMode::Unavailable,
+        }
+    }
+}
+
+/// The Transparent Huge Page (THP) defragmentation mode configured on the host.
+///
+/// See `/sys/kernel/mm/transparent_hugepage/defrag`.
+#[repr(u32)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ThpDefrag {
+    /// Transparent Huge Pages are not supported by the kernel.
+    Unavailable = 0,
+    /// Page faults stall for direct reclaim and compaction.
+    Always = 1,
+    /// Reclaim and compaction are deferred to `kswapd` and `kcompactd`.
+    Defer = 2,
+    /// Regions advised with `MADV_HUGEPAGE` stall, all others defer.
+    DeferMadvise = 3,
+    /// Only regions advised with `MADV_HUGEPAGE` stall for reclaim and compaction.
+    Madvise = 4,
+    /// No reclaim or compaction is performed.
+    Never = 5,
+}
+
+impl ThpDefrag {
+    /// Parses the mode from a `sysfs` selection such as `always defer [madvise] never`.
+    fn parse(value: &str) -> Self {
+        match selected(value) {
+            Some("always") => ThpDefrag::Always,
+            Some("defer") => ThpDefrag::Defer,
+            Some("defer+madvise") => ThpDefrag::DeferMadvise,
+            Some("madvise") => ThpDefrag::Madvise,
+            Some("never") => ThpDefrag::Never,
+            _ => ThpDefrag::Unavailable,
+        }
+    }
+}
+
+/// A pool of pre-allocated `hugetlbfs` pages of a specific size.
+///
+/// See `/sys/kernel/mm/hugepages/hugepages-<size>kB`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct HugeTlbPool {
+    /// The size of each page in bytes.
+    pub page_size: usize,
+    /// The number of pages in the pool.
+    pub total_pages: usize,
+    /// The number of pages not yet in use.
+    pub free_pages: usize,
+}
+
+/// The memory page configuration of the host.
+///
+/// ## Example
+/// ```
+/// # use alloc_madvise::SystemInfo;
+/// let info = SystemInfo::get();
+/// assert!(info.page_size.is_power_of_two());
+/// if let Some(huge_page_size) = info.transparent_huge_page_size() {
+///     assert!(huge_page_size > info.page_size);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemInfo {
+    /// The size of a regular memory page in bytes.
+    pub page_size: usize,
+    /// The size of a Transparent Huge Page in bytes, or zero if unavailable.
+    pub huge_page_size: usize,
+    /// The configured Transparent Huge Page mode.
+    pub thp_mode: ThpMode,
+    /// The configured Transparent Huge Page defragmentation mode.
+    pub thp_defrag: ThpDefrag,
+    /// The available `hugetlbfs` page pools, ordered by ascending page size.
+    pub huge_tlb_pools: Vec<HugeTlbPool>,
+    /// The size of an L1 data cache line in bytes.
+    pub cache_line_size: usize,
+}
+
+impl SystemInfo {
+    /// Gets the system information, reading it on first use.
+    pub fn get() -> &'static SystemInfo {
+        SYSTEM_INFO.get_or_init(SystemInfo::detect)
+    }
+
+    /// Reads the system information without consulting the cache.
+    pub fn detect() -> Self {
+        Self {
+            page_size: page_size(),
+            huge_page_size: read_usize(Path::new(THP_PMD_SIZE)).unwrap_or(0),
+            thp_mode: fs::read_to_string(THP_ENABLED)
+                .map(|value| ThpMode::parse(&value))
+                .unwrap_or(ThpMode::Unavailable),
+            thp_defrag: fs::read_to_string(THP_DEFRAG)
+                .map(|value| ThpDefrag::parse(&value))
+                .unwrap_or(ThpDefrag::Unavailable),
+            huge_tlb_pools: huge_tlb_pools(Path::new(HUGETLB_PAGES)),
+            cache_line_size: read_usize(Path::new(CACHE_LINE_SIZE))
+                .filter(|size| size.is_power_of_two())
+                .unwrap_or(SIXTY_FOUR_BYTES),
+        }
+    }
+
+    /// Returns the Transparent Huge Page size if `MADV_HUGEPAGE` can take effect on this host,
+    /// i.e. THP is supported and not disabled.
+    pub fn transparent_huge_page_size(&self) -> Option<usize> {
+        match self.thp_mode {
+            ThpMode::Always | ThpMode::Madvise if self.huge_page_size > self.page_size => {
+                Some(self.huge_page_size)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the alignment for allocations that are not huge-page aligned.
+    ///
+    /// This is the L1 cache line size, but at least 64 bytes, which should be optimal
+    /// for both AVX-2 and AVX-512 operations.
+    pub fn simd_alignment(&self) -> usize {
+        self.cache_line_size.max(SIXTY_FOUR_BYTES)
+    }
+
+    /// Returns the page sizes of the available `hugetlbfs` pools in ascending order.
+    pub fn huge_tlb_page_sizes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.huge_tlb_pools.iter().map(|pool| pool.page_size)
+    }
+}
+
+/// An x86-64 host with 4 KiB pages and 2 MiB Transparent Huge Pages.
+#[cfg(test)]
+pub(crate) fn x86_64() -> SystemInfo {
+    SystemInfo {
+        page_size: 4096,
+        huge_page_size: 2 * 1024 * 1024,
+        thp_mode: ThpMode::Madvise,
+        thp_defrag: ThpDefrag::Madvise,
+        huge_tlb_pools: Vec::new(),
+        cache_line_size: 64,
+    }
+}
+
+/// Gets the bracketed entry of a `sysfs` selection such as `always [madvise] never`.
+fn selected(value: &str) -> Option<&str> {
+    value
+        .split_whitespace()
+        .find(|mode| mode.starts_with('[') && mode.ends_with(']'))
+        .map(|mode| mode.trim_matches(|c| c == '[' || c == ']'))
+}
+
+/// Gets the regular page size, falling back to 4 KiB.
+fn page_size() -> usize {
+    // SAFETY: `sysconf` has no preconditions.
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if size > 0 {
+        size as usize
+    } else {
+        DEFAULT_PAGE_SIZE
+    }
+}
+
+pub(crate) fn read_usize(path: &Path) -> Option<usize> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Reads the `hugetlbfs` pools from directory entries such as `hugepages-2048kB`.
+fn huge_tlb_pools(path: &Path) -> Vec<HugeTlbPool> {
+    let mut pools: Vec<HugeTlbPool> = fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let kilobytes = name
+                .to_str()?
+                .strip_prefix("hugepages-")?
+                .strip_suffix("kB")?;
+            let pool = entry.path();
+            Some(HugeTlbPool {
+                page_size: kilobytes.parse::<usize>().ok()? * 1024,
+                total_pages: read_usize(&pool.join("nr_hugepages")).unwrap_or(0),
+                free_pages: read_usize(&pool.join("free_hugepages")).unwrap_or(0),
+            })
+        })
+        .collect();
+    pools.sort_unstable_by_key(|pool| pool.page_size);
+    pools
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_thp_mode() {
+        assert_eq!(ThpMode::parse("[always] madvise never"), ThpMode::Always);
+        assert_eq!(ThpMode::parse("always [madvise] never\n"), ThpMode::Madvise);
+        assert_eq!(ThpMode::parse("always madvise [never]"), ThpMode::Never);
+        assert_eq!(ThpMode::parse(""), ThpMode::Unavailable);
+    }
+
+    #[test]
+    fn parse_thp_defrag() {
+        let parse = ThpDefrag::parse;
+        assert_eq!(
+            parse("[always] defer defer+madvise madvise never"),
+            ThpDefrag::Always
+        );
+        assert_eq!(
+            parse("always [defer] defer+madvise madvise never"),
+            ThpDefrag::Defer
+        );
+        assert_eq!(
+            parse("always defer [defer+madvise] madvise never"),
+            ThpDefrag::DeferMadvise
+        );
+        assert_eq!(
+            parse("always defer defer+madvise [madvise] never"),
+            ThpDefrag::Madvise
+        );
+        assert_eq!(
+            parse("always defer defer+madvise madvise [never]"),
+            ThpDefrag::Never
+        );
+        assert_eq!(parse("always defer"), ThpDefrag::Unavailable);
+    }
+
+    #[test]
+    fn get_is_cached() {
+        assert!(std::ptr::eq(SystemInfo::get(), SystemInfo::get()));
+        assert_eq!(SystemInfo::get().page_size, SystemInfo::detect().page_size);
+    }
+
+    #[test]
+    fn detect_reports_host() {
+        let info = SystemInfo::detect();
+        assert!(info.page_size.is_power_of_two());
+        assert!(info.simd_alignment() >= SIXTY_FOUR_BYTES);
+        assert!(info
+            .huge_tlb_pools
+            .windows(2)
+            .all(|pools| pools[0].page_size < pools[1].page_size));
+    }
+
+    #[test]
+    fn transparent_huge_page_size_requires_thp() {
+        let mut info = SystemInfo {
+            page_size: 4096,
+            huge_page_size: 2 * 1024 * 1024,
+            thp_mode: ThpMode::Madvise,
+            thp_defrag: ThpDefrag::Madvise,
+            huge_tlb_pools: Vec::new(),
+            cache_line_size: 64,
+        };
+        assert_eq!(info.transparent_huge_page_size(), Some(2 * 1024 * 1024));
+
+        info.thp_mode = ThpMode::Always;
+        assert_eq!(info.transparent_huge_page_size(), Some(2 * 1024 * 1024));
+
+        info.thp_mode = ThpMode::Never;
+        assert_eq!(info.transparent_huge_page_size(), None);
+
+        info.thp_mode = ThpMode::Unavailable;
+        assert_eq!(info.transparent_huge_page_size(), None);
+    }
+}
@@ -0,0 +1,41 @@
+//! Fixtures shared by the unit tests.
+
+use std::path::PathBuf;
+
+/// A directory below the temporary directory that is removed on drop.
+pub(crate) struct TempDir(pub(crate) PathBuf);
+
+impl TempDir {
+    /// Creates the directory, named after the process and `name`.
+    pub(crate) fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("alloc-madvise-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Gets the line of `/proc/self/maps` describing the mapping containing `address`.
+pub(crate) fn maps_line(address: *const libc::c_void) -> String {
+    let address = address as usize;
+    std::fs::read_to_string("/proc/self/maps")
+        .unwrap()
+        .lines()
+        .find(|line| {
+            let range = line.split(' ').next().unwrap_or_default();
+            let Some((start, end)) = range.split_once('-') else {
+                return false;
+            };
+            let start = usize::from_str_radix(start, 16).unwrap_or(usize::MAX);
+            let end = usize::from_str_radix(end, 16).unwrap_or(0);
+            (start..end).contains(&address)
+        })
+        .expect("mapping not found")
+        .to_string()
+}
@@ -0,0 +1,280 @@
+//! Provides instrumentation of allocations, advices and releases using [`tracing`].
+//!
+//! Spans and events are only emitted if the crate is built with the `tracing` feature;
+//! otherwise the instrumentation compiles to nothing. All spans and events use the
+//! `alloc_madvise` target:
+//!
+//! - `allocate` spans (`DEBUG`) cover [`Memory::allocate_with`], [`Memory::allocate_shared`]
+//!   and [`Memory::allocate_secure`], and end with an `allocated` event reporting the capacity,
+//!   alignment, flags, backend and duration, or a `WARN` event reporting the error.
+//! - `free` spans (`DEBUG`) cover [`Memory::free`] and end with a `freed` event.
+//! - `madvise` events (`TRACE`) report the range, advice, duration and any `errno` of
+//!   every advice given, and are recorded within the span of the operation issuing them.
+
+use crate::alloc_result::AllocationError;
+use crate::memory::Memory;
+use std::ffi::c_void;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+#[cfg(feature = "tracing")]
+const TARGET: &str = "alloc_madvise";
+
+/// An allocation or release in progress, traced as a span.
+pub(crate) struct Operation {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+    #[cfg(feature = "tracing")]
+    start: Instant,
+}
+
+impl Operation {
+    /// Enters the span of an allocation using the specified function.
+    #[inline(always)]
+    pub(crate) fn allocate(function: &'static str, num_bytes: usize) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = (function, num_bytes);
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(target: TARGET, "allocate", function, num_bytes).entered(),
+            #[cfg(feature = "tracing")]
+            start: Instant::now(),
+        }
+    }
+
+    /// Enters the span of the release of the memory.
+    #[inline(always)]
+    pub(crate) fn free(memory: &Memory) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = memory;
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                target: TARGET,
+                "free",
+                address = memory.address as usize,
+                capacity = memory.capacity,
+                alignment = memory.alignment,
+                flags = memory.flags,
+                backend = ?memory.backend,
+            )
+            .entered(),
+            #[cfg(feature = "tracing")]
+            start: Instant::now(),
+        }
+    }
+
+    /// Leaves the span of an allocation, reporting its outcome.
+    #[inline(always)]
+    pub(crate) fn allocated(self, result: &Result<Memory, AllocationError>) {
+        #[cfg(feature = "tracing")]
+        {
+            let duration = self.start.elapsed();
+            match result {
+                Ok(memory) => tracing::debug!(
+                    target: TARGET,
+                    address = memory.address as usize,
+                    capacity = memory.capacity,
+                    alignment = memory.alignment,
+                    flags = memory.flags,
+                    backend = ?memory.backend,
+                    ?duration,
+                    "allocated"
+                ),
+                Err(AllocationError::OsError(errno)) => tracing::warn!(
+                    target: TARGET,
+                    errno,
+                    error = %result.as_ref().unwrap_err(),
+                    ?duration,
+                    "allocation failed"
+                ),
+                Err(error) => tracing::warn!(
+                    target: TARGET,
+                    %error,
+                    ?duration,
+                    "allocation failed"
+                ),
+            }
+            drop(self.span);
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = (self, result);
+    }
+
+    /// Leaves the span of a release.
+    #[inline(always)]
+    pub(crate) fn freed(self) {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::debug!(target: TARGET, duration = ?self.start.elapsed(), "freed");
+            drop(self.span);
+        }
+    }
+}
+
+/// Calls `madvise(2)`, tracing the advice and its outcome.
+///
+/// `errno` is preserved, so callers can inspect it as after calling `madvise` directly.
+///
+/// ## Safety
+/// See `libc::madvise`.
+#[inline(always)]
+pub(crate) unsafe fn madvise(
+    address: *mut c_void,
+    num_bytes: usize,
+    advice: libc::c_int,
+) -> libc::c_int {
+    #[cfg(feature = "tracing")]
+    let start = Instant::now();
+    let result = libc::madvise(address, num_bytes, advice);
+
+    #[cfg(feature = "tracing")]
+    if tracing::enabled!(target: TARGET, tracing::Level::TRACE) {
+        let duration = start.elapsed();
+        let advice = advice_name(advice);
+        if result == 0 {
+            tracing::trace!(
+                target: TARGET,
+                address = address as usize,
+                num_bytes,
+                advice,
+                ?duration,
+                "madvise"
+            );
+        } else {
+            // Subscribers may overwrite errno, so it is restored after emitting the event.
+            let errno = *libc::__errno_location();
+            tracing::trace!(
+                target: TARGET,
+                address = address as usize,
+                num_bytes,
+                advice,
+                ?duration,
+                errno,
+                "madvise"
+            );
+            *libc::__errno_location() = errno;
+        }
+    }
+    result
+}
+
+/// Gets the name of an advice given by this crate.
+#[cfg(feature = "tracing")]
+fn advice_name(advice: libc::c_int) -> &'static str {
+    match advice {
+        libc::MADV_NORMAL => "MADV_NORMAL",
+        libc::MADV_RANDOM => "MADV_RANDOM",
+        libc::MADV_SEQUENTIAL => "MADV_SEQUENTIAL",
+        libc::MADV_WILLNEED => "MADV_WILLNEED",
+        libc::MADV_DONTNEED => "MADV_DONTNEED",
+        libc::MADV_FREE => "MADV_FREE",
+        libc::MADV_MERGEABLE => "MADV_MERGEABLE",
+        libc::MADV_UNMERGEABLE => "MADV_UNMERGEABLE",
+        libc::MADV_HUGEPAGE => "MADV_HUGEPAGE",
+        libc::MADV_NOHUGEPAGE => "MADV_NOHUGEPAGE",
+        libc::MADV_DONTFORK => "MADV_DONTFORK",
+        libc::MADV_DOFORK => "MADV_DOFORK",
+        libc::MADV_DONTDUMP => "MADV_DONTDUMP",
+        libc::MADV_WIPEONFORK => "MADV_WIPEONFORK",
+        libc::MADV_KEEPONFORK => "MADV_KEEPONFORK",
+        libc::MADV_POPULATE_WRITE => "MADV_POPULATE_WRITE",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system_info::SystemInfo;
+
+    #[test]
+    fn madvise_preserves_errno() {
+        let page_size = SystemInfo::get().page_size;
+        let mut page = vec![0u8; 2 * page_size];
+        let aligned = page.as_mut_ptr().wrapping_add(1).cast::<c_void>();
+
+        // SAFETY: The advice is rejected since the address is not page-aligned.
+        let result = unsafe { madvise(aligned, page_size, libc::MADV_NORMAL) };
+        assert_eq!(result, -1);
+        assert_eq!(
+            AllocationError::last_os_error(),
+            AllocationError::OsError(libc::EINVAL)
+        );
+    }
+
+    #[cfg(feature = "tracing")]
+    mod subscriber {
+        use super::*;
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// Records the names of the spans and the messages of the events.
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+                metadata.target() == TARGET
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut names = self.0.lock().unwrap();
+                names.push(format!("span {}", span.metadata().name()));
+                Id::from_u64(names.len() as u64)
+            }
+
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                struct Message<'a>(&'a mut String);
+                impl tracing::field::Visit for Message<'_> {
+                    fn record_debug(
+                        &mut self,
+                        field: &tracing::field::Field,
+                        value: &dyn std::fmt::Debug,
+                    ) {
+                        if field.name() == "message" {
+                            *self.0 = format!("{value:?}");
+                        }
+                    }
+                }
+
+                let mut message = String::new();
+                event.record(&mut Message(&mut message));
+                self.0.lock().unwrap().push(format!("event {message}"));
+            }
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        #[test]
+        fn allocation_and_free_are_traced() {
+            let recorder = Recorder::default();
+            tracing::subscriber::with_default(recorder.clone(), || {
+                let memory = Memory::allocate(1024, true, false).unwrap();
+                drop(memory);
+                let _ = Memory::allocate(0, false, false);
+            });
+
+            let names = recorder.0.lock().unwrap();
+            assert_eq!(
+                names.as_slice(),
+                [
+                    "span allocate",
+                    "event madvise",
+                    "event allocated",
+                    "span free",
+                    "event freed",
+                    "span allocate",
+                    "event allocation failed",
+                ]
+            );
+        }
+    }
+}
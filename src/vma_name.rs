@@ -0,0 +1,121 @@
+//! Provides naming of anonymous [`Memory`] mappings.
+//!
+//! Named mappings are shown as `[anon:<name>]` in `/proc/<pid>/maps` and `/proc/<pid>/smaps`,
+//! which allows telling memory blocks apart when profiling. Naming requires a kernel built
+//! with `CONFIG_ANON_VMA_NAME` (Linux 5.17 or later) and is silently skipped otherwise.
+
+use crate::alloc_result::AllocationError;
+use crate::backend::Backend;
+use crate::memory::{Memory, ALLOC_FLAGS_NAMED};
+use std::ffi::CString;
+
+/// The maximum length of a name in bytes, excluding the terminating NUL.
+const MAX_NAME_LEN: usize = 79;
+
+impl Memory {
+    /// Names the mapping of the memory using `prctl(PR_SET_VMA_ANON_NAME)`.
+    ///
+    /// The name is silently ignored if the kernel does not support naming mappings.
+    ///
+    /// ## Errors
+    /// Fails with `EINVAL` unless the memory is an anonymous mapping, see
+    /// [`AllocationOptions::name`](crate::AllocationOptions::name), or if the name is longer
+    /// than 79 bytes or contains non-printable-ASCII characters or any of `\`, `` ` ``, `$`, `[`, `]`.
+    ///
+    /// ## Example
+    /// ```
+    /// # use alloc_madvise::{AllocationOptions, Memory};
+    /// let options = AllocationOptions::new().page_aligned(true);
+    /// let mut memory = Memory::allocate_with(1024, &options).unwrap();
+    /// memory.set_name("feature-index").unwrap();
+    /// ```
+    pub fn set_name(&mut self, name: &str) -> Result<(), AllocationError> {
+        if self.address.is_null() {
+            return Err(AllocationError::EmptyAllocation);
+        }
+        if self.backend != Backend::Anonymous || !is_valid_name(name) {
+            return Err(AllocationError::OsError(libc::EINVAL));
+        }
+
+        let name = CString::new(name).map_err(|_| AllocationError::OsError(libc::EINVAL))?;
+        // SAFETY: The range is the anonymous mapping of the memory, and `name` is a valid C string.
+        let result = unsafe {
+            libc::prctl(
+                libc::PR_SET_VMA,
+                libc::PR_SET_VMA_ANON_NAME,
+                self.address as libc::c_ulong,
+                self.capacity as libc::c_ulong,
+                name.as_ptr() as libc::c_ulong,
+            )
+        };
+
+        // The arguments were validated above, so EINVAL indicates a kernel without support.
+        if result != 0 {
+            let error = AllocationError::last_os_error();
+            if error != AllocationError::OsError(libc::EINVAL) {
+                return Err(error);
+            }
+        }
+        self.flags |= ALLOC_FLAGS_NAMED;
+        Ok(())
+    }
+}
+
+/// Checks a name against the rules of the kernel.
+fn is_valid_name(name: &str) -> bool {
+    name.len() <= MAX_NAME_LEN
+        && name
+            .bytes()
+            .all(|c| (0x20..0x7f).contains(&c) && !b"\\`$[]".contains(&c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::AllocationOptions;
+    use crate::test_support::maps_line;
+
+    #[test]
+    fn name_option_names_mapping() {
+        let options = AllocationOptions::new().name("feature-index");
+        let memory = Memory::allocate_with(8192, &options).unwrap();
+        assert_eq!(memory.backend(), Backend::Anonymous);
+
+        let line = maps_line(memory.to_ptr_const());
+        assert!(
+            line.ends_with("[anon:feature-index]") || !line.contains("[anon:"),
+            "{line}"
+        );
+    }
+
+    #[test]
+    fn invalid_names_fail() {
+        let options = AllocationOptions::new().page_aligned(true);
+        let mut memory = Memory::allocate_with(1024, &options).unwrap();
+        let too_long = "a".repeat(MAX_NAME_LEN + 1);
+        for name in ["[heap]", "tab\t", "nul\0", "dollar$", too_long.as_str()] {
+            assert_eq!(
+                memory.set_name(name),
+                Err(AllocationError::OsError(libc::EINVAL)),
+                "{name}"
+            );
+        }
+
+        let options = AllocationOptions::new().name("a b");
+        assert!(Memory::allocate_with(1024, &options).is_ok());
+        let options = AllocationOptions::new().name("[b]");
+        assert_eq!(
+            Memory::allocate_with(1024, &options).expect_err("invalid name"),
+            AllocationError::OsError(libc::EINVAL)
+        );
+    }
+
+    #[test]
+    fn heap_memory_is_rejected() {
+        let mut memory = Memory::allocate(1024, false, false).unwrap();
+        assert_eq!(
+            memory.set_name("heap"),
+            Err(AllocationError::OsError(libc::EINVAL))
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Tests for the process-wide allocation hook.
+//!
+//! These live in their own test binary since the hook observes all allocations
+//! made in the process.
+
+use alloc_madvise::{
+    clear_alloc_hook, set_alloc_hook, AllocEvent, AllocEventKind, AllocationError,
+    AllocationOptions, Backend, HookDecision, MemfdOptions, Memory, SecureOptions,
+};
+use std::sync::{Arc, Mutex};
+
+/// Serializes the tests, since they all modify the hook.
+static HOOK_LOCK: Mutex<()> = Mutex::new(());
+
+/// The size of the allocations rejected by the hook.
+const REJECTED: usize = 12_345;
+
+/// Sets a hook recording all events, and rejecting allocations of `rejected` bytes.
+fn record_events(rejected: usize) -> Arc<Mutex<Vec<AllocEvent>>> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&events);
+    set_alloc_hook(move |event| {
+        recorded.lock().unwrap().push(*event);
+        if event.kind == AllocEventKind::Allocate && event.num_bytes == rejected {
+            HookDecision::Reject
+        } else {
+            HookDecision::Allow
+        }
+    });
+    events
+}
+
+#[test]
+fn hook_observes_allocation_and_free() {
+    let _guard = HOOK_LOCK.lock().unwrap();
+    let events = record_events(0);
+
+    let memory = Memory::allocate(1000, false, false).expect("allocation failed");
+    let address = memory.to_ptr_const() as usize;
+    let capacity = memory.capacity();
+    drop(memory);
+    clear_alloc_hook();
+
+    let events = events.lock().unwrap();
+    let expected = |kind| AllocEvent {
+        kind,
+        address,
+        num_bytes: 1000,
+        capacity,
+        alignment: events[0].alignment,
+        backend: Backend::Heap,
+    };
+    assert_eq!(
+        events.as_slice(),
+        [
+            expected(AllocEventKind::Allocate),
+            expected(AllocEventKind::Free)
+        ]
+    );
+}
+
+#[test]
+fn hook_rejects_allocations() {
+    let _guard = HOOK_LOCK.lock().unwrap();
+    let events = record_events(REJECTED);
+
+    assert_eq!(
+        Memory::allocate(REJECTED, false, false).unwrap_err(),
+        AllocationError::Rejected
+    );
+    let options = AllocationOptions::new().page_aligned(true);
+    assert_eq!(
+        Memory::allocate_with(REJECTED, &options).unwrap_err(),
+        AllocationError::Rejected
+    );
+    assert_eq!(
+        Memory::allocate_shared(REJECTED, &MemfdOptions::new()).unwrap_err(),
+        AllocationError::Rejected
+    );
+    assert_eq!(
+        Memory::allocate_secure(REJECTED, &SecureOptions::new()).unwrap_err(),
+        AllocationError::Rejected
+    );
+
+    let memory = Memory::allocate(1000, false, false).expect("allocation failed");
+    drop(memory);
+    clear_alloc_hook();
+
+    // Rejected memory is released without a release event.
+    let events = events.lock().unwrap();
+    let kinds: Vec<_> = events.iter().map(|event| event.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            AllocEventKind::Allocate,
+            AllocEventKind::Allocate,
+            AllocEventKind::Allocate,
+            AllocEventKind::Allocate,
+            AllocEventKind::Allocate,
+            AllocEventKind::Free
+        ]
+    );
+    assert_eq!(events[1].backend, Backend::Anonymous);
+    assert_eq!(events[2].backend, Backend::Memfd);
+}
+
+#[test]
+fn cleared_hook_is_not_invoked() {
+    let _guard = HOOK_LOCK.lock().unwrap();
+    let events = record_events(1000);
+    clear_alloc_hook();
+
+    let memory = Memory::allocate(1000, false, false).expect("allocation failed");
+    drop(memory);
+    assert!(events.lock().unwrap().is_empty());
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn ffi_hook_rejects_allocations() {
+    use std::ffi::c_void;
+    use std::ptr::null_mut;
+
+    unsafe extern "C" fn hook(event: *const ffi::AllocEvent, context: *mut c_void) -> bool {
+        let event = &*event;
+        if event.num_bytes != REJECTED {
+            return true;
+        }
+        assert_eq!(
+            event.struct_size as usize,
+            std::mem::size_of::<ffi::AllocEvent>()
+        );
+        assert_eq!(event.kind, AllocEventKind::Allocate);
+        assert!(!event.address.is_null());
+        *context.cast::<usize>() += 1;
+        false
+    }
+
+    let _guard = HOOK_LOCK.lock().unwrap();
+    unsafe {
+        let mut rejected = 0usize;
+        ffi::alloc_madvise_set_alloc_hook(Some(hook), (&mut rejected as *mut usize).cast());
+        let memory = ffi::alloc_madvise_allocate_v2(REJECTED, false, false);
+        ffi::alloc_madvise_set_alloc_hook(None, null_mut());
+
+        assert_eq!(memory.status, ffi::STATUS_REJECTED);
+        assert!(memory.address.is_null());
+        assert_eq!(rejected, 1);
+
+        let memory = ffi::alloc_madvise_allocate_v2(REJECTED, false, false);
+        assert_eq!(memory.status, 0);
+        ffi::alloc_madvise_free_v2(memory);
+    }
+}
+
+/// The FFI entry points and structs, as seen by C callers.
+#[cfg(feature = "ffi")]
+mod ffi {
+    use alloc_madvise::{AllocEventKind, Backend};
+    use std::ffi::c_void;
+
+    /// The status of a `MemoryV2` whose allocation was rejected by the hook.
+    pub const STATUS_REJECTED: u32 = 8;
+
+    #[repr(C)]
+    pub struct MemoryV2 {
+        pub status: u32,
+        pub flags: u32,
+        pub backend: u32,
+        pub fd: i32,
+        pub num_bytes: usize,
+        pub capacity: usize,
+        pub alignment: usize,
+        pub address: *mut c_void,
+    }
+
+    #[repr(C)]
+    pub struct AllocEvent {
+        pub struct_size: u32,
+        pub kind: AllocEventKind,
+        pub backend: Backend,
+        pub num_bytes: usize,
+        pub capacity: usize,
+        pub alignment: usize,
+        pub address: *mut c_void,
+    }
+
+    pub type AllocHook =
+        Option<unsafe extern "C" fn(event: *const AllocEvent, context: *mut c_void) -> bool>;
+
+    extern "C" {
+        pub fn alloc_madvise_allocate_v2(
+            num_bytes: usize,
+            sequential: bool,
+            clear: bool,
+        ) -> MemoryV2;
+        pub fn alloc_madvise_free_v2(memory: MemoryV2);
+        pub fn alloc_madvise_set_alloc_hook(hook: AllocHook, context: *mut c_void);
+    }
+}
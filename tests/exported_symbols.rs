@@ -0,0 +1,123 @@
+//! Inspects the export table of the built `cdylib` to ensure that only the
+//! intended (prefixed) FFI symbols are visible to the dynamic linker.
+
+#![cfg(all(feature = "ffi", target_os = "linux", target_pointer_width = "64"))]
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+/// Symbols that are always exported.
+const PREFIXED_SYMBOLS: &[&str] = &[
+    "alloc_madvise_capabilities",
+    "alloc_madvise_allocate",
+    "alloc_madvise_allocate_secure",
+    "alloc_madvise_allocate_shared",
+    "alloc_madvise_allocate_v2",
+    "alloc_madvise_allocate_with",
+    "alloc_madvise_create_file",
+    "alloc_madvise_flush",
+    "alloc_madvise_free",
+    "alloc_madvise_free_v2",
+    "alloc_madvise_from_fd",
+    "alloc_madvise_map_file",
+    "alloc_madvise_set_alloc_hook",
+    "alloc_madvise_shm_create",
+    "alloc_madvise_shm_open",
+    "alloc_madvise_shm_unlink",
+    "alloc_madvise_snapshot",
+    "alloc_madvise_version",
+];
+
+/// Deprecated symbols exported with the `legacy-symbols` feature.
+const LEGACY_SYMBOLS: &[&str] = &["allocate_block", "free_block", "version"];
+
+/// Symbols exported with the `stats` feature.
+const STATS_SYMBOLS: &[&str] = &["alloc_madvise_get_stats"];
+
+const SHT_DYNSYM: u32 = 11;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STT_FUNC: u8 = 2;
+const SHN_UNDEF: u16 = 0;
+
+#[test]
+fn cdylib_exports_only_ffi_symbols() {
+    let exported = exported_functions(&locate_cdylib());
+
+    let mut expected: BTreeSet<String> = PREFIXED_SYMBOLS.iter().map(|s| s.to_string()).collect();
+    if cfg!(feature = "legacy-symbols") {
+        expected.extend(LEGACY_SYMBOLS.iter().map(|s| s.to_string()));
+    }
+    if cfg!(feature = "stats") {
+        expected.extend(STATS_SYMBOLS.iter().map(|s| s.to_string()));
+    }
+
+    assert_eq!(exported, expected);
+}
+
+#[test]
+fn cdylib_exports_are_prefixed() {
+    let exported = exported_functions(&locate_cdylib());
+
+    for symbol in exported {
+        assert!(
+            symbol.starts_with("alloc_madvise_") || LEGACY_SYMBOLS.contains(&symbol.as_str()),
+            "unexpected unprefixed symbol: {symbol}"
+        );
+    }
+}
+
+/// Finds the `cdylib` built alongside this test binary.
+fn locate_cdylib() -> PathBuf {
+    let exe = std::env::current_exe().expect("failed to determine test executable");
+    let deps_dir = exe.parent().expect("test executable has no parent");
+    [deps_dir, deps_dir.parent().unwrap_or(deps_dir)]
+        .iter()
+        .map(|dir| dir.join("liballoc_madvise.so"))
+        .find(|path| path.exists())
+        .expect("liballoc_madvise.so was not built")
+}
+
+/// Parses the ELF `.dynsym` section and returns the names of all defined global functions.
+fn exported_functions(path: &PathBuf) -> BTreeSet<String> {
+    let elf = std::fs::read(path).expect("failed to read cdylib");
+    assert_eq!(&elf[..4], b"\x7fELF", "not an ELF file");
+    assert_eq!(elf[4], 2, "not a 64-bit ELF file");
+    assert_eq!(elf[5], 1, "not a little-endian ELF file");
+
+    let u16_at = |offset: usize| u16::from_le_bytes(elf[offset..offset + 2].try_into().unwrap());
+    let u32_at = |offset: usize| u32::from_le_bytes(elf[offset..offset + 4].try_into().unwrap());
+    let u64_at = |offset: usize| u64::from_le_bytes(elf[offset..offset + 8].try_into().unwrap());
+
+    let section_offset = u64_at(0x28) as usize;
+    let section_size = u16_at(0x3a) as usize;
+    let section_count = u16_at(0x3c) as usize;
+    let section = |index: usize| section_offset + index * section_size;
+
+    let dynsym = (0..section_count)
+        .map(section)
+        .find(|&header| u32_at(header + 0x04) == SHT_DYNSYM)
+        .expect("no .dynsym section");
+    let dynstr = section(u32_at(dynsym + 0x28) as usize);
+
+    let symbols_offset = u64_at(dynsym + 0x18) as usize;
+    let symbols_size = u64_at(dynsym + 0x20) as usize;
+    let symbol_size = u64_at(dynsym + 0x38) as usize;
+    let strings_offset = u64_at(dynstr + 0x18) as usize;
+
+    (symbols_offset..symbols_offset + symbols_size)
+        .step_by(symbol_size)
+        .filter(|&symbol| {
+            let info = elf[symbol + 4];
+            let binding = info >> 4;
+            (binding == STB_GLOBAL || binding == STB_WEAK)
+                && info & 0xf == STT_FUNC
+                && u16_at(symbol + 6) != SHN_UNDEF
+        })
+        .map(|symbol| {
+            let name = strings_offset + u32_at(symbol) as usize;
+            let end = elf[name..].iter().position(|&b| b == 0).unwrap();
+            String::from_utf8_lossy(&elf[name..name + end]).into_owned()
+        })
+        .collect()
+}
@@ -0,0 +1,212 @@
+//! Tests for the process-wide alignment policy.
+//!
+//! These live in their own test binary since changing the global policy
+//! affects all allocations made in the process. The binary installs a global
+//! allocator verifying that every over-aligned block is deallocated with the
+//! layout it was allocated with.
+
+use alloc_madvise::{
+    reset_global_policy, set_global_policy, AlignmentHint, AlignmentPolicy, AllocationError,
+    AllocationOptions, CacheLineAligned, HugePageThreshold, MemfdOptions, Memory, MirroredMemory,
+    PageAligned, SecureOptions, SystemInfo,
+};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::Mutex;
+
+#[global_allocator]
+static ALLOCATOR: LayoutCheckingAllocator = LayoutCheckingAllocator::new();
+
+/// Serializes the tests, since they all modify the global policy.
+static POLICY_LOCK: Mutex<()> = Mutex::new(());
+
+#[test]
+fn global_policy_applies_to_allocations() {
+    let _guard = POLICY_LOCK.lock().unwrap();
+    let page_size = SystemInfo::get().page_size;
+
+    set_global_policy(PageAligned);
+    let memory = Memory::allocate(1000, false, true).expect("allocation failed");
+    assert_eq!(memory.alignment(), page_size);
+    assert_eq!(memory.to_ptr_const() as usize % page_size, 0);
+
+    // An explicit policy takes precedence.
+    let options = AllocationOptions::new().policy(CacheLineAligned);
+    let other = Memory::allocate_with(1000, &options).expect("allocation failed");
+    assert_eq!(other.alignment(), SystemInfo::get().simd_alignment());
+
+    // Changing the policy must not affect blocks already allocated.
+    reset_global_policy();
+    assert_eq!(memory.alignment(), page_size);
+    drop(memory);
+    drop(other);
+}
+
+#[test]
+fn changing_policy_between_alloc_and_free_is_sound() {
+    let _guard = POLICY_LOCK.lock().unwrap();
+
+    // Allocate blocks whose alignment differs from what the default policy would choose.
+    set_global_policy(PageAligned);
+    let page_aligned = Memory::allocate(1000, false, false).expect("allocation failed");
+    set_global_policy(HugePageThreshold::new(0));
+    let huge_aligned = Memory::allocate(1000, false, false).expect("allocation failed");
+    set_global_policy(CacheLineAligned);
+    let cache_aligned = Memory::allocate(4 * 1024 * 1024, false, false).expect("allocation failed");
+
+    // Free them under a different policy; the allocator panics on a layout mismatch.
+    set_global_policy(PageAligned);
+    drop(huge_aligned);
+    drop(cache_aligned);
+    reset_global_policy();
+    drop(page_aligned);
+
+    assert_eq!(ALLOCATOR.mismatches(), 0);
+}
+
+#[test]
+fn invalid_global_policy_fails_mappings() {
+    /// Returns an alignment that is not a power of two, and below the page size.
+    struct ThreeBytes;
+
+    impl AlignmentPolicy for ThreeBytes {
+        fn hint(&self, _num_bytes: usize, _system: &SystemInfo) -> AlignmentHint {
+            AlignmentHint {
+                alignment: 3,
+                use_huge_pages: false,
+            }
+        }
+    }
+
+    let _guard = POLICY_LOCK.lock().unwrap();
+    set_global_policy(ThreeBytes);
+    let secure = Memory::allocate_secure(1000, &SecureOptions::new());
+    let shared = Memory::allocate_shared(1000, &MemfdOptions::new());
+    let mirrored = MirroredMemory::allocate(1000, &MemfdOptions::new());
+    reset_global_policy();
+
+    assert!(matches!(secure, Err(AllocationError::InvalidAlignment(_))));
+    assert!(matches!(shared, Err(AllocationError::InvalidAlignment(_))));
+    assert!(matches!(
+        mirrored.map(|_| ()),
+        Err(AllocationError::InvalidAlignment(_))
+    ));
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn changing_policy_between_v1_alloc_and_free_is_sound() {
+    let _guard = POLICY_LOCK.lock().unwrap();
+
+    // The V1 entry points do not persist the alignment, so they ignore the global policy.
+    set_global_policy(PageAligned);
+    let first = unsafe { v1::alloc_madvise_allocate(1000, false, false) };
+    set_global_policy(HugePageThreshold::new(0));
+    let second = unsafe { v1::alloc_madvise_allocate(4096, true, false) };
+    assert_eq!(first.status, 0);
+    assert_eq!(second.status, 0);
+    assert_eq!(
+        first.address as usize % SystemInfo::get().simd_alignment(),
+        0
+    );
+
+    set_global_policy(CacheLineAligned);
+    unsafe { v1::alloc_madvise_free(first) };
+    set_global_policy(PageAligned);
+    unsafe { v1::alloc_madvise_free(second) };
+
+    #[cfg(feature = "legacy-symbols")]
+    {
+        set_global_policy(PageAligned);
+        let legacy = unsafe { v1::allocate_block(1000, false, false) };
+        assert_eq!(legacy.status, 0);
+        reset_global_policy();
+        unsafe { v1::free_block(legacy) };
+    }
+
+    reset_global_policy();
+    assert_eq!(ALLOCATOR.mismatches(), 0);
+}
+
+/// Declarations of the V1 FFI entry points, whose struct does not carry the alignment.
+#[cfg(feature = "ffi")]
+mod v1 {
+    use std::ffi::c_void;
+
+    #[repr(C)]
+    pub struct Memory {
+        pub status: u32,
+        pub flags: u32,
+        pub num_bytes: u32,
+        pub address: *mut c_void,
+    }
+
+    extern "C" {
+        pub fn alloc_madvise_allocate(num_bytes: u32, sequential: bool, clear: bool) -> Memory;
+        pub fn alloc_madvise_free(memory: Memory);
+        #[cfg(feature = "legacy-symbols")]
+        pub fn allocate_block(num_bytes: u32, sequential: bool, clear: bool) -> Memory;
+        #[cfg(feature = "legacy-symbols")]
+        pub fn free_block(memory: Memory);
+    }
+}
+
+/// The number of over-aligned allocations tracked at the same time.
+const TRACKED: usize = 256;
+
+/// Wraps the system allocator, recording the layouts of over-aligned allocations
+/// and counting deallocations with a different layout.
+struct LayoutCheckingAllocator {
+    live: Mutex<[(usize, Layout); TRACKED]>,
+    mismatches: Mutex<usize>,
+}
+
+impl LayoutCheckingAllocator {
+    const EMPTY: (usize, Layout) = (0, Layout::new::<u8>());
+
+    const fn new() -> Self {
+        Self {
+            live: Mutex::new([Self::EMPTY; TRACKED]),
+            mismatches: Mutex::new(0),
+        }
+    }
+
+    fn mismatches(&self) -> usize {
+        *self.mismatches.lock().unwrap()
+    }
+
+    fn is_tracked(layout: Layout) -> bool {
+        layout.align() > 16
+    }
+}
+
+unsafe impl GlobalAlloc for LayoutCheckingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() && Self::is_tracked(layout) {
+            let mut live = self.live.lock().unwrap();
+            let slot = live
+                .iter_mut()
+                .find(|(address, _)| *address == 0)
+                .expect("too many live allocations");
+            *slot = (ptr as usize, layout);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut live = self.live.lock().unwrap();
+        if let Some(slot) = live
+            .iter_mut()
+            .find(|(address, _)| *address == ptr as usize)
+        {
+            if slot.1 != layout {
+                *self.mismatches.lock().unwrap() += 1;
+            }
+            *slot = Self::EMPTY;
+        } else if Self::is_tracked(layout) {
+            *self.mismatches.lock().unwrap() += 1;
+        }
+        drop(live);
+        System.dealloc(ptr, layout);
+    }
+}